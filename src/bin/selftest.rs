@@ -0,0 +1,102 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+
+use price_publisher::config::Config;
+use price_publisher::exchanges::{create_exchange, Exchange as _};
+use price_publisher::types::{Exchange, PriceUpdate};
+
+// How long each exchange gets to connect, subscribe and send a price
+const FIRST_PRICE_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Parser)]
+#[command(about = "Checks that each exchange connects and sends a price")]
+struct Cli {
+    /// Exchange to check, e.g. binance (repeatable; defaults to all of them)
+    #[arg(long = "exchange")]
+    exchanges: Vec<Exchange>,
+}
+
+enum Outcome {
+    Pass(PriceUpdate, Duration),
+    Fail(String),
+    // create_exchange has no implementation for it
+    Skip(String),
+}
+
+// Runs one exchange's init and listener until its first price arrives
+async fn check(exchange_type: Exchange, config: &Config) -> Outcome {
+    let mut exchange = match create_exchange(exchange_type, config.trading_pairs.clone()).await {
+        Ok(exchange) => exchange,
+        Err(e) => return Outcome::Skip(e.to_string()),
+    };
+
+    let started = Instant::now();
+    let first_price = async {
+        exchange.init().await?;
+
+        let (price_sender, mut price_receiver) = mpsc::channel(100);
+        let (_shutdown_sender, shutdown) = watch::channel(false);
+        tokio::select! {
+            update = price_receiver.recv() => update.ok_or_else(|| anyhow!("Price channel closed")),
+            result = exchange.listen(price_sender, shutdown) => match result {
+                Ok(()) => Err(anyhow!("Listener stopped before sending a price")),
+                Err(e) => Err(e.into()),
+            },
+        }
+    };
+
+    match tokio::time::timeout(FIRST_PRICE_TIMEOUT, first_price).await {
+        Ok(Ok(update)) => Outcome::Pass(update, started.elapsed()),
+        Ok(Err(e)) => Outcome::Fail(e.to_string()),
+        Err(_) => Outcome::Fail(format!(
+            "No price within {}s",
+            FIRST_PRICE_TIMEOUT.as_secs()
+        )),
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<ExitCode> {
+    let cli = Cli::parse();
+    let exchanges = if cli.exchanges.is_empty() {
+        Exchange::ALL.to_vec()
+    } else {
+        cli.exchanges
+    };
+    let config = Config::default();
+
+    // Check every exchange at once so the whole run takes one timeout at most
+    let outcomes =
+        futures::future::join_all(exchanges.iter().map(|exchange| check(*exchange, &config))).await;
+
+    let mut failed = false;
+    println!("{:<12} {:<6} DETAIL", "EXCHANGE", "RESULT");
+    for (exchange, outcome) in exchanges.iter().zip(outcomes) {
+        let (result, detail) = match outcome {
+            Outcome::Pass(update, elapsed) => (
+                "PASS",
+                format!(
+                    "{} {} after {:.1}s",
+                    update.symbol,
+                    update.price,
+                    elapsed.as_secs_f64()
+                ),
+            ),
+            Outcome::Fail(reason) => {
+                failed = true;
+                ("FAIL", reason)
+            }
+            Outcome::Skip(reason) => ("SKIP", reason),
+        };
+        println!("{:<12} {:<6} {}", exchange.as_str(), result, detail);
+    }
+
+    Ok(if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
@@ -0,0 +1,326 @@
+use anyhow::{Context, Result};
+use log::info;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::exchanges::depth::{DepthConfig, BYBIT_BOOK_DEPTHS};
+use crate::exchanges::remap::SymbolRemap;
+use crate::types::{Exchange, KeyCase, PriceMethod, RedisKeyFormat, TradingPair};
+
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub redis_url: String,
+    // Template for each pair's price key; `{symbol}`, `{base}` and `{quote}`
+    // are filled in, e.g. "price:{base}-{quote}"
+    pub redis_key_template: String,
+    // Casing of the symbol, base and quote in the price key
+    pub redis_key_case: KeyCase,
+    pub trading_pairs: Vec<TradingPair>,
+    pub exchanges: Vec<Exchange>,
+    // How top-of-book prices are derived from the best bid and ask
+    pub price_method: PriceMethod,
+    // Top-of-book pricing unless set
+    pub depth_weighting: Option<DepthConfig>,
+    // Levels per side of Bybit's order book subscription: 1, 50, 200 or 500
+    pub bybit_book_depth: u32,
+    // Max percent a price may deviate from the other sources before it is
+    // treated as an outlier and not published
+    pub outlier_threshold_pct: Decimal,
+    // Max percent between the lowest and highest fresh source price before
+    // `price:{symbol}` is halted
+    pub max_source_spread_pct: Decimal,
+    // Max percent between an update's own bid and ask before it is dropped,
+    // along with any locked or crossed book
+    pub max_book_spread_pct: Decimal,
+    // Updates within this absolute distance of a source's previous price are
+    // treated as repeats and not written to Redis again
+    pub duplicate_price_epsilon: Decimal,
+    // Which fresh exchange wins `price:{symbol}`, most preferred first. Empty
+    // or unlisted exchanges fall back to the most recently updated source.
+    pub source_priority: Vec<Exchange>,
+    // Trust weight per exchange. When set, `price:{symbol}:consensus` is the
+    // weighted average of the fresh listed sources instead of the median.
+    pub source_weights: HashMap<Exchange, f64>,
+    // Fresh sources a symbol needs before `price:{symbol}:consensus` is
+    // written; below it `:consensus:status` is set to insufficient_sources
+    pub min_consensus_sources: usize,
+    // Exchange the others are compared against in `price:{symbol}:deviation`
+    // and the health report; no deviations are computed unless set
+    pub reference_exchange: Option<Exchange>,
+    // Smoothing factor of the per-symbol EMA written to `price:{symbol}:ema`,
+    // in (0, 1]; higher follows the ticks more closely. No EMA unless set
+    pub ema_alpha: Option<Decimal>,
+    // Seconds before a symbol's Redis keys expire if no exchange updates it
+    pub price_expiry_secs: u64,
+    // Milliseconds between our receipt time and an exchange's event time
+    // beyond which the local clock is suspected of drifting
+    pub max_clock_skew_ms: u64,
+    // Per-symbol overrides of `price_expiry_secs`, keyed by canonical symbol
+    pub symbol_price_expiry_secs: HashMap<String, u64>,
+    // Per-symbol factor every source's price is multiplied by before it is
+    // published, keyed by canonical symbol
+    pub symbol_price_scale: HashMap<String, Decimal>,
+    // Inclusive [min, max] a symbol's price must fall within, keyed by
+    // canonical symbol; anything outside is a feed error and is dropped
+    pub symbol_price_bounds: HashMap<String, (Decimal, Decimal)>,
+    // Decimal places `price:{symbol}` and its sources key are written with,
+    // keyed by canonical symbol; unlisted symbols keep the exchange's precision
+    pub symbol_price_decimals: HashMap<String, u32>,
+    // Seconds without an update before a source is evicted from the
+    // in-memory prices entirely
+    pub source_max_age_secs: u64,
+    // Minimum milliseconds between sink writes for a symbol; updates in
+    // between are coalesced to the latest per source. 0 writes every batch.
+    pub min_write_interval_ms: u64,
+    // Per-symbol overrides of `min_write_interval_ms`, keyed by canonical symbol
+    pub symbol_min_write_interval_ms: HashMap<String, u64>,
+    // Subscribe to Hyperliquid's perp contexts and write `funding:{symbol}`
+    pub hyperliquid_funding: bool,
+    // Also subscribe to trade streams (Binance, Bybit, Coinbase) and write
+    // the last traded price to `price:{symbol}:trade`
+    pub trade_prices: bool,
+    // Price Coinbase publishes for a pegged pair, keyed by canonical symbol,
+    // when its ticker sends nothing soon after subscribing
+    pub coinbase_peg_fallbacks: HashMap<String, Decimal>,
+    // Append every published update to this file as newline JSON, and
+    // reseed the in-memory prices from its tail on startup
+    pub wal_path: Option<PathBuf>,
+    // Milliseconds between fsyncs of the WAL; 0 fsyncs every batch
+    pub wal_fsync_interval_ms: u64,
+    // Assets an exchange lists under a different ticker, e.g. Hyperliquid's
+    // kSHIB for 1000 SHIB
+    pub symbol_remaps: Vec<SymbolRemap>,
+    // Exchange health thresholds and reconnect timing
+    pub health: HealthConfig,
+}
+
+/// When exchanges count as unhealthy and how listeners reconnect, as the
+/// `[health]` table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    // Seconds an exchange may go without a message before `is_healthy` fails
+    pub heartbeat_timeout_secs: u64,
+    // Seconds between health checks
+    pub check_interval_secs: u64,
+    // Seconds without a price before the health check warns about an exchange
+    pub stale_exchange_secs: u64,
+    // Error count above which the health check logs an error
+    pub max_error_count: u32,
+    // First reconnect delay in seconds, doubling per consecutive failure up
+    // to `reconnect_max_delay_secs`
+    pub reconnect_base_delay_secs: u64,
+    pub reconnect_max_delay_secs: u64,
+    // Seconds a connection must stay up to reset the reconnect backoff
+    pub stable_connection_secs: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_timeout_secs: 10,
+            check_interval_secs: 30,
+            stale_exchange_secs: 30,
+            max_error_count: 5,
+            reconnect_base_delay_secs: 1,
+            reconnect_max_delay_secs: 120,
+            stable_connection_secs: 60,
+        }
+    }
+}
+
+impl HealthConfig {
+    pub fn heartbeat_timeout(&self) -> Duration {
+        Duration::from_secs(self.heartbeat_timeout_secs)
+    }
+
+    pub fn check_interval(&self) -> Duration {
+        Duration::from_secs(self.check_interval_secs)
+    }
+
+    pub fn stale_exchange_threshold(&self) -> Duration {
+        Duration::from_secs(self.stale_exchange_secs)
+    }
+
+    pub fn reconnect_base_delay(&self) -> Duration {
+        Duration::from_secs(self.reconnect_base_delay_secs)
+    }
+
+    pub fn reconnect_max_delay(&self) -> Duration {
+        Duration::from_secs(self.reconnect_max_delay_secs)
+    }
+
+    pub fn stable_connection_duration(&self) -> Duration {
+        Duration::from_secs(self.stable_connection_secs)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            redis_url: "redis://127.0.0.1/".to_string(),
+            redis_key_template: "price:{symbol}".to_string(),
+            redis_key_case: KeyCase::Upper,
+            trading_pairs: vec![
+                TradingPair::new("BTC", "USDT"),
+                TradingPair::new("ETH", "USDT"),
+                TradingPair::new("SOL", "USDT"),
+                TradingPair::new("USDC", "USDT"),
+            ],
+            exchanges: vec![
+                Exchange::Binance,
+                Exchange::Bybit,
+                Exchange::Coinbase,
+                Exchange::Gateio,
+                Exchange::Hyperliquid,
+                Exchange::Kraken,
+                Exchange::KuCoin,
+                Exchange::Okx,
+            ],
+            price_method: PriceMethod::Mid,
+            depth_weighting: None,
+            bybit_book_depth: 1,
+            outlier_threshold_pct: Decimal::new(5, 0),
+            max_source_spread_pct: Decimal::new(2, 0),
+            max_book_spread_pct: Decimal::new(5, 0),
+            duplicate_price_epsilon: Decimal::new(1, 8),
+            source_priority: Vec::new(),
+            source_weights: HashMap::new(),
+            min_consensus_sources: 2,
+            reference_exchange: None,
+            ema_alpha: None,
+            price_expiry_secs: 60,
+            max_clock_skew_ms: 1000,
+            symbol_price_expiry_secs: HashMap::new(),
+            symbol_price_scale: HashMap::new(),
+            symbol_price_bounds: HashMap::new(),
+            symbol_price_decimals: HashMap::new(),
+            source_max_age_secs: 300,
+            min_write_interval_ms: 0,
+            symbol_min_write_interval_ms: HashMap::new(),
+            hyperliquid_funding: false,
+            trade_prices: false,
+            coinbase_peg_fallbacks: HashMap::from([("USDCUSDT".to_string(), Decimal::ONE)]),
+            wal_path: None,
+            wal_fsync_interval_ms: 1000,
+            symbol_remaps: Vec::new(),
+            health: HealthConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            info!(
+                "No config file at {}, using default configuration",
+                path.display()
+            );
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        let mut config: Config = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+
+        // Normalize casing the same way TradingPair::new does
+        config.trading_pairs = config
+            .trading_pairs
+            .iter()
+            .map(|pair| TradingPair::new(&pair.base, &pair.quote))
+            .collect();
+        config.symbol_price_expiry_secs = config
+            .symbol_price_expiry_secs
+            .into_iter()
+            .map(|(symbol, secs)| (symbol.to_uppercase(), secs))
+            .collect();
+        config.symbol_price_scale = config
+            .symbol_price_scale
+            .into_iter()
+            .map(|(symbol, scale)| (symbol.to_uppercase(), scale))
+            .collect();
+        config.symbol_price_bounds = config
+            .symbol_price_bounds
+            .into_iter()
+            .map(|(symbol, bounds)| (symbol.to_uppercase(), bounds))
+            .collect();
+        config.symbol_price_decimals = config
+            .symbol_price_decimals
+            .into_iter()
+            .map(|(symbol, decimals)| (symbol.to_uppercase(), decimals))
+            .collect();
+        config.symbol_min_write_interval_ms = config
+            .symbol_min_write_interval_ms
+            .into_iter()
+            .map(|(symbol, millis)| (symbol.to_uppercase(), millis))
+            .collect();
+        config.coinbase_peg_fallbacks = config
+            .coinbase_peg_fallbacks
+            .into_iter()
+            .map(|(symbol, price)| (symbol.to_uppercase(), price))
+            .collect();
+
+        anyhow::ensure!(
+            config.redis_key_format().is_per_symbol(),
+            "redis_key_template {:?} needs {{symbol}}, {{base}} or {{quote}}",
+            config.redis_key_template
+        );
+
+        anyhow::ensure!(
+            BYBIT_BOOK_DEPTHS.contains(&config.bybit_book_depth),
+            "bybit_book_depth must be one of {:?}, got {}",
+            BYBIT_BOOK_DEPTHS,
+            config.bybit_book_depth
+        );
+
+        for (symbol, (min, max)) in &config.symbol_price_bounds {
+            anyhow::ensure!(
+                min < max,
+                "symbol_price_bounds for {} must be [min, max] with min below max, got [{}, {}]",
+                symbol,
+                min,
+                max
+            );
+        }
+
+        anyhow::ensure!(
+            config.health.check_interval_secs > 0,
+            "health.check_interval_secs must be above 0"
+        );
+        anyhow::ensure!(
+            config.health.reconnect_base_delay_secs <= config.health.reconnect_max_delay_secs,
+            "health.reconnect_base_delay_secs must not exceed reconnect_max_delay_secs"
+        );
+
+        if let Some(alpha) = config.ema_alpha {
+            anyhow::ensure!(
+                alpha > Decimal::ZERO && alpha <= Decimal::ONE,
+                "ema_alpha must be above 0 and at most 1, got {}",
+                alpha
+            );
+        }
+
+        info!("Loaded config from {}", path.display());
+        Ok(config)
+    }
+
+    pub fn redis_key_format(&self) -> RedisKeyFormat {
+        RedisKeyFormat::new(&self.redis_key_template, self.redis_key_case)
+    }
+
+    pub fn symbols(&self) -> Vec<String> {
+        self.trading_pairs
+            .iter()
+            .map(|pair| pair.to_canonical_symbol())
+            .collect()
+    }
+}
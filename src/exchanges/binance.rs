@@ -1,17 +1,46 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use async_trait::async_trait;
 use chrono::Utc;
 use log::{error, info};
+use rust_decimal::Decimal;
 use serde::Deserialize;
+use std::str::FromStr;
 use std::sync::atomic::{AtomicI64, Ordering};
-use tokio::sync::mpsc::Sender;
+use std::time::Duration;
+use tokio::sync::{mpsc::Sender, watch};
 
-use super::{ws_stream::WsStream, Exchange};
-use crate::types::{PriceUpdate, TradingPair};
+use super::depth::{depth_weighted_mid, parse_levels, DepthConfig};
+use super::error::ExchangeError;
+use super::pairs::{diff_subscriptions, TrackedPairs};
+use super::remap::SymbolRemaps;
+use super::subscription::SubscriptionBuilder;
+use super::ticks::TickCounter;
+use super::ws_stream::{ConnState, RateLimit, SharedConnState, WsStream, WsStreamConfig};
+use super::{canonical_symbols, heartbeat_is_recent, Exchange, DEFAULT_HEARTBEAT_TIMEOUT};
+use crate::clock::{system_clock, SharedClock};
+use crate::types::{system_time_from_millis, PriceKind, PriceMethod, PriceUpdate, TradingPair};
+
+// Binance disconnects clients sending more than 5 messages per second
+const BINANCE_SEND_RATE_LIMIT: RateLimit = RateLimit {
+    messages: 5,
+    per: Duration::from_secs(1),
+};
 
 pub struct BinanceExchange {
-    trading_pairs: Vec<TradingPair>,
+    trading_pairs: TrackedPairs,
     last_heartbeat: AtomicI64,
+    ticks: TickCounter,
+    ws_state: SharedConnState,
+    remaps: SymbolRemaps,
+    clock: SharedClock,
+    // Longest gap between messages before `is_healthy` fails
+    heartbeat_timeout: Duration,
+    price_method: PriceMethod,
+    depth: Option<DepthConfig>,
+    // Also subscribe to @trade and emit last-trade prices
+    trades: bool,
+    // Overrides the public endpoint, e.g. to point at a mock server in tests
+    websocket_url: Option<String>,
 }
 
 impl Clone for BinanceExchange {
@@ -19,49 +48,189 @@ impl Clone for BinanceExchange {
         Self {
             trading_pairs: self.trading_pairs.clone(),
             last_heartbeat: AtomicI64::new(self.last_heartbeat.load(Ordering::SeqCst)),
+            ticks: self.ticks.clone(),
+            ws_state: self.ws_state.clone(),
+            remaps: self.remaps.clone(),
+            clock: self.clock.clone(),
+            heartbeat_timeout: self.heartbeat_timeout,
+            price_method: self.price_method,
+            depth: self.depth,
+            trades: self.trades,
+            websocket_url: self.websocket_url.clone(),
         }
     }
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BinanceRestBookTicker {
+    symbol: String,
+    bid_price: String,
+    ask_price: String,
+}
+
 #[derive(Debug, Deserialize)]
 struct BinanceBookTicker {
     #[serde(rename = "s")]
     symbol: String,
     #[serde(rename = "b")]
     best_bid: String,
+    #[serde(rename = "B")]
+    best_bid_size: String,
     #[serde(rename = "a")]
     best_ask: String,
+    #[serde(rename = "A")]
+    best_ask_size: String,
+    // Only some bookTicker streams carry an event time
+    #[serde(rename = "E")]
+    event_time: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BinanceTrade {
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "p")]
+    price: String,
+    #[serde(rename = "T")]
+    trade_time: i64,
+}
+
+// Combined-stream envelope; `stream` is e.g. "btcusdt@bookTicker"
+#[derive(Debug, Deserialize)]
+struct BinanceCombined<T> {
+    stream: String,
+    data: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct BinanceDepthData {
+    bids: Vec<Vec<String>>,
+    asks: Vec<Vec<String>>,
 }
 
 impl BinanceExchange {
     pub fn new(trading_pairs: Vec<TradingPair>) -> Self {
         Self {
-            trading_pairs,
+            trading_pairs: TrackedPairs::new(trading_pairs),
             last_heartbeat: AtomicI64::new(Utc::now().timestamp()),
+            ticks: TickCounter::new(),
+            ws_state: SharedConnState::default(),
+            remaps: SymbolRemaps::default(),
+            clock: system_clock(),
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+            price_method: PriceMethod::default(),
+            depth: None,
+            trades: false,
+            websocket_url: None,
         }
     }
 
+    /// How long the feed may go without a message before it is unhealthy.
+    pub fn with_heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    /// Time heartbeats and tick windows by `clock` instead of the system
+    /// clock.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.last_heartbeat = AtomicI64::new(clock.timestamp());
+        self.ticks = TickCounter::with_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    /// Translate assets Binance lists under its own tickers.
+    pub fn with_symbol_remaps(mut self, remaps: SymbolRemaps) -> Self {
+        self.remaps = remaps;
+        self
+    }
+
+    /// How to price the top of book; the mid unless set.
+    pub fn with_price_method(mut self, price_method: PriceMethod) -> Self {
+        self.price_method = price_method;
+        self
+    }
+
+    /// Switch from top-of-book to depth-weighted pricing over the partial
+    /// book depth stream.
+    pub fn with_depth_weighting(mut self, depth: DepthConfig) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    /// Also subscribe to each symbol's trade stream and emit last-trade prices.
+    pub fn with_trades(mut self) -> Self {
+        self.trades = true;
+        self
+    }
+
+    /// Connect to `url` instead of Binance's public stream endpoint.
+    pub fn with_websocket_url(mut self, url: impl Into<String>) -> Self {
+        self.websocket_url = Some(url.into());
+        self
+    }
+
     fn get_websocket_url(&self) -> String {
-        let symbols = self
-            .trading_pairs
-            .iter()
-            .map(|pair| pair.to_binance_symbol().to_lowercase())
-            .collect::<Vec<_>>()
-            .join("/");
-        format!("wss://stream.binance.com:9443/ws/{}@bookTicker", symbols)
+        if let Some(url) = &self.websocket_url {
+            return url.clone();
+        }
+        // Combined-stream endpoint: many streams on one connection, each
+        // message wrapped as {"stream":..,"data":..}
+        "wss://stream.binance.com:9443/stream".to_string()
     }
 
-    fn create_subscription_message(&self) -> String {
+    // One bookTicker stream per symbol, or a partial book depth stream when
+    // depth weighting is on, plus a trade stream per symbol if enabled
+    fn streams(&self, pairs: &[TradingPair]) -> Vec<String> {
+        let suffix = match &self.depth {
+            // Partial book depth is only published at 5, 10 or 20 levels
+            Some(depth) => match depth.levels {
+                0..=5 => "depth5@100ms",
+                6..=10 => "depth10@100ms",
+                _ => "depth20@100ms",
+            },
+            None => "bookTicker",
+        };
+        let mut suffixes = vec![suffix];
+        if self.trades {
+            suffixes.push("trade");
+        }
+        SubscriptionBuilder::new(pairs, &self.remaps, |pair| {
+            pair.to_binance_symbol().to_lowercase()
+        })
+        .topics(&suffixes, |suffix, symbol| format!("{}@{}", symbol, suffix))
+    }
+
+    // SUBSCRIBE or UNSUBSCRIBE; the initial subscription is id 1
+    fn create_request(method: &str, streams: &[String], id: u64) -> String {
         serde_json::json!({
-            "method": "SUBSCRIBE",
-            "params": [format!("{}@bookTicker", self.trading_pairs.iter().map(|pair| pair.to_binance_symbol().to_lowercase()).collect::<Vec<_>>().join("/"))],
-            "id": 1
-        }).to_string()
+            "method": method,
+            "params": streams,
+            "id": id
+        })
+        .to_string()
+    }
+
+    // Answer to any of our requests, e.g. {"result":null,"id":2}
+    fn is_response(text: &str) -> bool {
+        serde_json::from_str::<serde_json::Value>(text)
+            .is_ok_and(|message| message.get("result").is_some() && message.get("id").is_some())
+    }
+
+    // SUBSCRIBE is answered with {"result":null,"id":1}
+    fn is_subscription_ack(text: &str) -> Result<bool, ExchangeError> {
+        Ok(
+            serde_json::from_str::<serde_json::Value>(text).is_ok_and(|message| {
+                message.get("result").is_some_and(|result| result.is_null()) && message["id"] == 1
+            }),
+        )
     }
 
     fn update_heartbeat(&self) {
         self.last_heartbeat
-            .store(Utc::now().timestamp(), Ordering::SeqCst);
+            .store(self.clock.timestamp(), Ordering::SeqCst);
     }
 }
 
@@ -72,53 +241,239 @@ impl Exchange for BinanceExchange {
         Ok(())
     }
 
-    async fn listen(&self, price_sender: Sender<PriceUpdate>) -> Result<()> {
-        let mut ws = WsStream::connect(&self.get_websocket_url()).await?;
-        info!("Connected to Binance WebSocket");
+    async fn listen(
+        &self,
+        price_sender: Sender<PriceUpdate>,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> Result<(), ExchangeError> {
+        let config = WsStreamConfig {
+            send_rate_limit: Some(BINANCE_SEND_RATE_LIMIT),
+            state: self.ws_state.clone(),
+            ..Default::default()
+        };
+        let mut pair_changes = self.trading_pairs.watch();
+        let pairs = pair_changes.borrow_and_update().clone();
+        let mut subscribed = self.streams(&pairs);
+        let subscription_msg = Self::create_request("SUBSCRIBE", &subscribed, 1);
+        let mut ws = WsStream::connect_and_subscribe_with(
+            &self.get_websocket_url(),
+            config,
+            vec![subscription_msg.clone()],
+            Self::is_subscription_ack,
+        )
+        .await?;
+        info!("Subscribed to Binance: {}", subscription_msg);
 
-        // Send subscription message
-        let subscription_msg = self.create_subscription_message();
-        ws.send_text(subscription_msg.clone()).await?;
-        info!("Sent subscription message to Binance: {}", subscription_msg);
+        let mut symbols = canonical_symbols(&pairs, &self.remaps, |pair| pair.to_binance_symbol());
+        let mut request_id = 1;
 
         self.update_heartbeat();
 
-        while let Some(text) = ws.read_text().await? {
-            if let Ok(ticker) = serde_json::from_str::<BinanceBookTicker>(&text) {
-                let best_bid = ticker.best_bid.parse::<f64>()?;
-                let best_ask = ticker.best_ask.parse::<f64>()?;
-                let mid_price = (best_bid + best_ask) / 2.0;
+        loop {
+            let text = tokio::select! {
+                Ok(()) = pair_changes.changed() => {
+                    let pairs = pair_changes.borrow_and_update().clone();
+                    let streams = self.streams(&pairs);
+                    let (added, removed) = diff_subscriptions(&subscribed, &streams);
+                    if !removed.is_empty() {
+                        request_id += 1;
+                        ws.send_text(Self::create_request("UNSUBSCRIBE", &removed, request_id))
+                            .await?;
+                    }
+                    if !added.is_empty() {
+                        request_id += 1;
+                        ws.send_text(Self::create_request("SUBSCRIBE", &added, request_id))
+                            .await?;
+                    }
+                    info!("Resubscribed to Binance: +{:?} -{:?}", added, removed);
+                    subscribed = streams;
+                    symbols =
+                        canonical_symbols(&pairs, &self.remaps, |pair| pair.to_binance_symbol());
+                    continue;
+                }
+                text = ws.read_text_or_shutdown(&mut shutdown) => match text? {
+                    Some(text) => text,
+                    None => break,
+                },
+            };
+
+            if let Ok(BinanceCombined { data: ticker, .. }) =
+                serde_json::from_str::<BinanceCombined<BinanceBookTicker>>(&text)
+            {
+                ws.record_decoded();
+                let canonical = match symbols.get(&ticker.symbol) {
+                    Some(canonical) => canonical,
+                    None => continue,
+                };
+                let best_bid = Decimal::from_str(&ticker.best_bid)?;
+                let best_ask = Decimal::from_str(&ticker.best_ask)?;
+                let sizes = Decimal::from_str(&ticker.best_bid_size)
+                    .ok()
+                    .zip(Decimal::from_str(&ticker.best_ask_size).ok());
 
                 let update = PriceUpdate {
-                    symbol: ticker.symbol,
-                    price: mid_price,
+                    symbol: canonical.symbol.clone(),
+                    price: self.price_method.price(best_bid, best_ask, sizes),
                     timestamp: Utc::now().into(),
                     source: "binance".to_string(),
+                    kind: PriceKind::TopOfBook,
+                    bid: Some(best_bid),
+                    ask: Some(best_ask),
+                    exchange_timestamp: ticker.event_time.map(system_time_from_millis),
+                }
+                .scaled(canonical.price_scale);
+
+                if let Err(e) = price_sender.send(update).await {
+                    error!("Failed to send price update: {}", e);
+                    return Err(ExchangeError::ChannelClosed);
+                }
+
+                self.update_heartbeat();
+                self.ticks.record();
+            } else if let Ok(BinanceCombined { data: trade, .. }) =
+                serde_json::from_str::<BinanceCombined<BinanceTrade>>(&text)
+            {
+                ws.record_decoded();
+                let canonical = match symbols.get(&trade.symbol) {
+                    Some(canonical) => canonical,
+                    None => continue,
                 };
+                let update = PriceUpdate {
+                    symbol: canonical.symbol.clone(),
+                    price: Decimal::from_str(&trade.price)?,
+                    timestamp: Utc::now().into(),
+                    source: "binance".to_string(),
+                    kind: PriceKind::LastTrade,
+                    bid: None,
+                    ask: None,
+                    exchange_timestamp: Some(system_time_from_millis(trade.trade_time)),
+                }
+                .scaled(canonical.price_scale);
 
                 if let Err(e) = price_sender.send(update).await {
                     error!("Failed to send price update: {}", e);
-                    return Err(anyhow!("Channel closed"));
+                    return Err(ExchangeError::ChannelClosed);
                 }
 
                 self.update_heartbeat();
+                self.ticks.record();
+            } else if let (Some(depth), Ok(event)) = (
+                &self.depth,
+                serde_json::from_str::<BinanceCombined<BinanceDepthData>>(&text),
+            ) {
+                ws.record_decoded();
+                let native = event
+                    .stream
+                    .split('@')
+                    .next()
+                    .unwrap_or(&event.stream)
+                    .to_uppercase();
+                let canonical = match symbols.get(&native) {
+                    Some(canonical) => canonical,
+                    None => continue,
+                };
+                let bids = parse_levels(&event.data.bids);
+                let asks = parse_levels(&event.data.asks);
+                if let Some(price) = depth_weighted_mid(&bids, &asks, depth) {
+                    let update = PriceUpdate {
+                        symbol: canonical.symbol.clone(),
+                        price,
+                        timestamp: Utc::now().into(),
+                        source: "binance".to_string(),
+                        kind: PriceKind::DepthWeighted,
+                        bid: bids.first().map(|(price, _)| *price),
+                        ask: asks.first().map(|(price, _)| *price),
+                        exchange_timestamp: None,
+                    }
+                    .scaled(canonical.price_scale);
+
+                    if let Err(e) = price_sender.send(update).await {
+                        error!("Failed to send price update: {}", e);
+                        return Err(ExchangeError::ChannelClosed);
+                    }
+
+                    self.update_heartbeat();
+                    self.ticks.record();
+                }
+            } else if Self::is_response(&text) {
+                ws.record_decoded();
+            } else {
+                ws.record_decode_failure(&text)?;
             }
         }
 
-        Err(anyhow!("WebSocket stream ended"))
+        if *shutdown.borrow() {
+            ws.close().await?;
+            return Ok(());
+        }
+
+        Err(ExchangeError::StreamEnded)
     }
 
-    fn get_trading_pairs(&self) -> &[TradingPair] {
+    fn tracked_pairs(&self) -> &TrackedPairs {
         &self.trading_pairs
     }
 
+    // Subscribes and unsubscribes streams on the open connection
+    fn resubscribes_live(&self) -> bool {
+        true
+    }
+
     fn get_name(&self) -> &'static str {
         "binance"
     }
 
+    async fn fetch_snapshot(&self) -> Result<Vec<PriceUpdate>> {
+        let symbols = canonical_symbols(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_binance_symbol()
+        });
+        let native_symbols = serde_json::to_string(&symbols.keys().collect::<Vec<_>>())?;
+
+        let tickers: Vec<BinanceRestBookTicker> = reqwest::Client::new()
+            .get("https://api.binance.com/api/v3/ticker/bookTicker")
+            .query(&[("symbols", native_symbols)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let mut updates = Vec::new();
+        for ticker in tickers {
+            if let (Some(canonical), Ok(best_bid), Ok(best_ask)) = (
+                symbols.get(&ticker.symbol),
+                Decimal::from_str(&ticker.bid_price),
+                Decimal::from_str(&ticker.ask_price),
+            ) {
+                updates.push(
+                    PriceUpdate {
+                        symbol: canonical.symbol.clone(),
+                        price: self.price_method.price(best_bid, best_ask, None),
+                        timestamp: Utc::now().into(),
+                        source: "binance".to_string(),
+                        kind: PriceKind::TopOfBook,
+                        bid: Some(best_bid),
+                        ask: Some(best_ask),
+                        exchange_timestamp: None,
+                    }
+                    .scaled(canonical.price_scale),
+                );
+            }
+        }
+        Ok(updates)
+    }
+
     async fn is_healthy(&self) -> bool {
-        let last = self.last_heartbeat.load(Ordering::SeqCst);
-        let age = Utc::now().timestamp() - last;
-        age < 10
+        // A live socket isn't enough; prices must actually be arriving
+        heartbeat_is_recent(&self.last_heartbeat, &self.clock, self.heartbeat_timeout)
+            && self.ticks.is_active()
+    }
+
+    fn ticks_last_interval(&self) -> u64 {
+        self.ticks.last_interval()
+    }
+
+    fn ws_state(&self) -> ConnState {
+        self.ws_state.get()
     }
 }
@@ -0,0 +1,333 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use log::{error, info};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+use tokio::sync::{mpsc::Sender, watch};
+
+use super::depth::parse_levels;
+use super::error::ExchangeError;
+use super::pairs::TrackedPairs;
+use super::remap::SymbolRemaps;
+use super::subscription::SubscriptionBuilder;
+use super::ticks::TickCounter;
+use super::ws_stream::{ConnState, SharedConnState, WsStream, WsStreamConfig};
+use super::{canonical_symbols, heartbeat_is_recent, Exchange, DEFAULT_HEARTBEAT_TIMEOUT};
+use crate::clock::{system_clock, SharedClock};
+use crate::types::{system_time_from_millis, PriceKind, PriceMethod, PriceUpdate, TradingPair};
+
+const BITSTAMP_ORDER_BOOK_PREFIX: &str = "order_book_";
+
+pub struct BitstampExchange {
+    trading_pairs: TrackedPairs,
+    last_heartbeat: AtomicI64,
+    ticks: TickCounter,
+    ws_state: SharedConnState,
+    remaps: SymbolRemaps,
+    clock: SharedClock,
+    // Longest gap between messages before `is_healthy` fails
+    heartbeat_timeout: Duration,
+    price_method: PriceMethod,
+}
+
+impl Clone for BitstampExchange {
+    fn clone(&self) -> Self {
+        Self {
+            trading_pairs: self.trading_pairs.clone(),
+            last_heartbeat: AtomicI64::new(self.last_heartbeat.load(Ordering::SeqCst)),
+            ticks: self.ticks.clone(),
+            ws_state: self.ws_state.clone(),
+            remaps: self.remaps.clone(),
+            clock: self.clock.clone(),
+            heartbeat_timeout: self.heartbeat_timeout,
+            price_method: self.price_method,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BitstampMessage {
+    event: String,
+    #[serde(default)]
+    channel: String,
+    #[serde(default)]
+    data: serde_json::Value,
+}
+
+// order_book_{pair} snapshot of the top 100 levels, best first
+#[derive(Debug, Deserialize)]
+struct BitstampOrderBook {
+    // Microseconds since epoch
+    microtimestamp: Option<String>,
+    bids: Vec<Vec<String>>,
+    asks: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BitstampRestTicker {
+    // e.g. "BTC/USD"
+    pair: String,
+    bid: String,
+    ask: String,
+}
+
+impl BitstampExchange {
+    pub fn new(trading_pairs: Vec<TradingPair>) -> Self {
+        Self {
+            trading_pairs: TrackedPairs::new(trading_pairs),
+            last_heartbeat: AtomicI64::new(Utc::now().timestamp()),
+            ticks: TickCounter::new(),
+            ws_state: SharedConnState::default(),
+            remaps: SymbolRemaps::default(),
+            clock: system_clock(),
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+            price_method: PriceMethod::default(),
+        }
+    }
+
+    /// How long the feed may go without a message before it is unhealthy.
+    pub fn with_heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    /// Time heartbeats and tick windows by `clock` instead of the system
+    /// clock.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.last_heartbeat = AtomicI64::new(clock.timestamp());
+        self.ticks = TickCounter::with_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    /// Translate assets Bitstamp lists under its own tickers.
+    pub fn with_symbol_remaps(mut self, remaps: SymbolRemaps) -> Self {
+        self.remaps = remaps;
+        self
+    }
+
+    /// How to price the top of book; the mid unless set.
+    pub fn with_price_method(mut self, price_method: PriceMethod) -> Self {
+        self.price_method = price_method;
+        self
+    }
+
+    fn get_websocket_url(&self) -> String {
+        "wss://ws.bitstamp.net".to_string()
+    }
+
+    // Bitstamp takes one channel per subscribe message
+    fn create_subscription_messages(&self) -> Vec<String> {
+        SubscriptionBuilder::new(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_bitstamp_symbol()
+        })
+        .per_symbol(|symbol| {
+            serde_json::json!({
+                "event": "bts:subscribe",
+                "data": {
+                    "channel": format!("{}{}", BITSTAMP_ORDER_BOOK_PREFIX, symbol)
+                }
+            })
+        })
+    }
+
+    // Answered with bts:subscription_succeeded, or bts:error for a pair
+    // Bitstamp doesn't list
+    fn is_subscription_ack(text: &str) -> Result<bool, ExchangeError> {
+        match serde_json::from_str::<BitstampMessage>(text) {
+            Ok(message) if message.event == "bts:error" => Err(ExchangeError::Rejected(format!(
+                "Bitstamp subscription failed: {}",
+                message.data["message"].as_str().unwrap_or_default()
+            ))),
+            Ok(message) => Ok(message.event == "bts:subscription_succeeded"),
+            Err(_) => Ok(false),
+        }
+    }
+
+    fn update_heartbeat(&self) {
+        self.last_heartbeat
+            .store(self.clock.timestamp(), Ordering::SeqCst);
+    }
+}
+
+#[async_trait]
+impl Exchange for BitstampExchange {
+    async fn init(&mut self) -> Result<()> {
+        // Bitstamp doesn't require initialization
+        Ok(())
+    }
+
+    async fn listen(
+        &self,
+        price_sender: Sender<PriceUpdate>,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> Result<(), ExchangeError> {
+        let subscription_msgs = self.create_subscription_messages();
+        let mut ws = WsStream::connect_and_subscribe_with(
+            &self.get_websocket_url(),
+            WsStreamConfig {
+                state: self.ws_state.clone(),
+                ..Default::default()
+            },
+            subscription_msgs.clone(),
+            Self::is_subscription_ack,
+        )
+        .await?;
+        info!("Subscribed to Bitstamp: {:?}", subscription_msgs);
+
+        let symbols = canonical_symbols(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_bitstamp_symbol()
+        });
+
+        self.update_heartbeat();
+
+        while let Some(text) = ws.read_text_or_shutdown(&mut shutdown).await? {
+            let message = match serde_json::from_str::<BitstampMessage>(&text) {
+                Ok(message) => message,
+                Err(_) => {
+                    ws.record_decode_failure(&text)?;
+                    continue;
+                }
+            };
+            match message.event.as_str() {
+                "data" => {}
+                // Sent ahead of maintenance; the server drops us shortly after
+                "bts:request_reconnect" => {
+                    info!("Bitstamp requested a reconnect");
+                    return Err(ExchangeError::StreamEnded);
+                }
+                "bts:error" => {
+                    return Err(ExchangeError::Rejected(format!(
+                        "Bitstamp error: {}",
+                        message.data["message"].as_str().unwrap_or_default()
+                    )));
+                }
+                _ => {
+                    ws.record_decoded();
+                    continue;
+                }
+            }
+
+            let canonical = match message
+                .channel
+                .strip_prefix(BITSTAMP_ORDER_BOOK_PREFIX)
+                .and_then(|native| symbols.get(native))
+            {
+                Some(canonical) => canonical,
+                None => continue,
+            };
+            let book = match serde_json::from_value::<BitstampOrderBook>(message.data) {
+                Ok(book) => book,
+                Err(_) => {
+                    ws.record_decode_failure(&text)?;
+                    continue;
+                }
+            };
+            ws.record_decoded();
+            let bids = parse_levels(&book.bids);
+            let asks = parse_levels(&book.asks);
+            let (Some(&(best_bid, bid_size)), Some(&(best_ask, ask_size))) =
+                (bids.first(), asks.first())
+            else {
+                continue;
+            };
+
+            let update = PriceUpdate {
+                symbol: canonical.symbol.clone(),
+                price: self
+                    .price_method
+                    .price(best_bid, best_ask, Some((bid_size, ask_size))),
+                timestamp: Utc::now().into(),
+                source: "bitstamp".to_string(),
+                kind: PriceKind::TopOfBook,
+                bid: Some(best_bid),
+                ask: Some(best_ask),
+                exchange_timestamp: book
+                    .microtimestamp
+                    .and_then(|micros| micros.parse::<i64>().ok())
+                    .map(|micros| system_time_from_millis(micros / 1000)),
+            }
+            .scaled(canonical.price_scale);
+
+            if let Err(e) = price_sender.send(update).await {
+                error!("Failed to send price update: {}", e);
+                return Err(ExchangeError::ChannelClosed);
+            }
+
+            self.update_heartbeat();
+            self.ticks.record();
+        }
+
+        if *shutdown.borrow() {
+            ws.close().await?;
+            return Ok(());
+        }
+
+        Err(ExchangeError::StreamEnded)
+    }
+
+    fn tracked_pairs(&self) -> &TrackedPairs {
+        &self.trading_pairs
+    }
+
+    fn get_name(&self) -> &'static str {
+        "bitstamp"
+    }
+
+    async fn fetch_snapshot(&self) -> Result<Vec<PriceUpdate>> {
+        let symbols = canonical_symbols(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_bitstamp_symbol()
+        });
+
+        let tickers: Vec<BitstampRestTicker> = reqwest::Client::new()
+            .get("https://www.bitstamp.net/api/v2/ticker/")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let mut updates = Vec::new();
+        for ticker in tickers {
+            let native = ticker.pair.replace('/', "").to_lowercase();
+            if let (Some(canonical), Ok(best_bid), Ok(best_ask)) = (
+                symbols.get(&native),
+                Decimal::from_str(&ticker.bid),
+                Decimal::from_str(&ticker.ask),
+            ) {
+                updates.push(
+                    PriceUpdate {
+                        symbol: canonical.symbol.clone(),
+                        price: self.price_method.price(best_bid, best_ask, None),
+                        timestamp: Utc::now().into(),
+                        source: "bitstamp".to_string(),
+                        kind: PriceKind::TopOfBook,
+                        bid: Some(best_bid),
+                        ask: Some(best_ask),
+                        exchange_timestamp: None,
+                    }
+                    .scaled(canonical.price_scale),
+                );
+            }
+        }
+        Ok(updates)
+    }
+
+    async fn is_healthy(&self) -> bool {
+        // A live socket isn't enough; prices must actually be arriving
+        heartbeat_is_recent(&self.last_heartbeat, &self.clock, self.heartbeat_timeout)
+            && self.ticks.is_active()
+    }
+
+    fn ticks_last_interval(&self) -> u64 {
+        self.ticks.last_interval()
+    }
+
+    fn ws_state(&self) -> ConnState {
+        self.ws_state.get()
+    }
+}
@@ -1,17 +1,44 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use async_trait::async_trait;
 use chrono::Utc;
-use log::{error, info};
+use log::{error, info, warn};
+use rust_decimal::Decimal;
 use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
 use std::sync::atomic::{AtomicI64, Ordering};
-use tokio::sync::mpsc::Sender;
+use tokio::sync::{mpsc::Sender, watch};
+use tokio::time::{interval, Duration};
 
-use super::{ws_stream::WsStream, Exchange};
-use crate::types::{PriceUpdate, TradingPair};
+use super::depth::{depth_weighted_mid, parse_levels, DepthConfig, BYBIT_BOOK_DEPTHS};
+use super::error::ExchangeError;
+use super::pairs::{diff_subscriptions, TrackedPairs};
+use super::remap::SymbolRemaps;
+use super::subscription::SubscriptionBuilder;
+use super::ticks::TickCounter;
+use super::ws_stream::{ConnState, SharedConnState, WsStream, WsStreamConfig};
+use super::{canonical_symbols, heartbeat_is_recent, Exchange, DEFAULT_HEARTBEAT_TIMEOUT};
+use crate::clock::{system_clock, SharedClock};
+use crate::types::{system_time_from_millis, PriceKind, PriceMethod, PriceUpdate, TradingPair};
+
+// Bybit drops connections that don't send an application-level ping
+const BYBIT_PING_INTERVAL: Duration = Duration::from_secs(20);
 
 pub struct BybitExchange {
-    trading_pairs: Vec<TradingPair>,
+    trading_pairs: TrackedPairs,
     last_heartbeat: AtomicI64,
+    ticks: TickCounter,
+    ws_state: SharedConnState,
+    remaps: SymbolRemaps,
+    clock: SharedClock,
+    // Longest gap between messages before `is_healthy` fails
+    heartbeat_timeout: Duration,
+    price_method: PriceMethod,
+    // Also subscribe to publicTrade and emit last-trade prices
+    trades: bool,
+    // Levels per side of the orderbook topic; one of BYBIT_BOOK_DEPTHS
+    book_depth: u32,
+    depth: Option<DepthConfig>,
 }
 
 impl Clone for BybitExchange {
@@ -19,6 +46,15 @@ impl Clone for BybitExchange {
         Self {
             trading_pairs: self.trading_pairs.clone(),
             last_heartbeat: AtomicI64::new(self.last_heartbeat.load(Ordering::SeqCst)),
+            ticks: self.ticks.clone(),
+            ws_state: self.ws_state.clone(),
+            remaps: self.remaps.clone(),
+            clock: self.clock.clone(),
+            heartbeat_timeout: self.heartbeat_timeout,
+            price_method: self.price_method,
+            trades: self.trades,
+            book_depth: self.book_depth,
+            depth: self.depth,
         }
     }
 }
@@ -26,46 +62,267 @@ impl Clone for BybitExchange {
 #[derive(Debug, Deserialize)]
 struct BybitOrderbook {
     topic: String,
+    // "snapshot" or "delta"
+    #[serde(rename = "type")]
+    update_type: String,
+    // Milliseconds since epoch when the exchange generated the message
+    ts: Option<i64>,
     data: BybitOrderbookData,
 }
 
+#[derive(Debug, Deserialize)]
+struct BybitRestResponse {
+    result: BybitRestTickers,
+}
+
+#[derive(Debug, Deserialize)]
+struct BybitRestTickers {
+    list: Vec<BybitRestTicker>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BybitRestTicker {
+    symbol: String,
+    bid1_price: String,
+    ask1_price: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct BybitTradeMessage {
+    topic: String,
+    data: Vec<BybitTrade>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BybitTrade {
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "p")]
+    price: String,
+    // Trade time, milliseconds since epoch
+    #[serde(rename = "T")]
+    trade_time: i64,
+}
+
+// Response to one of our own ops: subscription acks and pongs
+#[derive(Debug, Deserialize)]
+struct BybitOpResponse {
+    op: String,
+    success: Option<bool>,
+    ret_msg: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 struct BybitOrderbookData {
+    // [price, size] levels, up to the subscribed depth on a snapshot and
+    // only the changed ones on a delta
     #[serde(rename = "b")]
     bids: Vec<Vec<String>>,
     #[serde(rename = "a")]
     asks: Vec<Vec<String>>,
+    // Increments by one per message for a symbol; 1 on a snapshot after a
+    // service restart
+    #[serde(rename = "u")]
+    update_id: u64,
+}
+
+// Book for one symbol, up to the subscribed depth, rebuilt from a snapshot
+// plus in-order deltas
+#[derive(Debug, Default)]
+struct BybitBook {
+    // Price to size
+    bids: BTreeMap<Decimal, Decimal>,
+    asks: BTreeMap<Decimal, Decimal>,
+    update_id: u64,
+}
+
+impl BybitBook {
+    // Applies [price, size] levels to one side; size 0 removes the level.
+    // At depth 1 each level replaces the side, as Bybit only ever sends the
+    // current top.
+    fn apply(side: &mut BTreeMap<Decimal, Decimal>, levels: &[Vec<String>], depth: u32) {
+        for (price, size) in parse_levels(levels) {
+            if size.is_zero() {
+                side.remove(&price);
+            } else {
+                if depth == 1 {
+                    side.clear();
+                }
+                side.insert(price, size);
+            }
+        }
+    }
+
+    fn apply_all(&mut self, data: &BybitOrderbookData, depth: u32) {
+        Self::apply(&mut self.bids, &data.bids, depth);
+        Self::apply(&mut self.asks, &data.asks, depth);
+    }
+
+    // Best first
+    fn bid_levels(&self) -> Vec<(Decimal, Decimal)> {
+        self.bids
+            .iter()
+            .rev()
+            .map(|(&price, &size)| (price, size))
+            .collect()
+    }
+
+    fn ask_levels(&self) -> Vec<(Decimal, Decimal)> {
+        self.asks
+            .iter()
+            .map(|(&price, &size)| (price, size))
+            .collect()
+    }
+
+    fn best_bid(&self) -> Option<(Decimal, Decimal)> {
+        self.bids
+            .last_key_value()
+            .map(|(&price, &size)| (price, size))
+    }
+
+    fn best_ask(&self) -> Option<(Decimal, Decimal)> {
+        self.asks
+            .first_key_value()
+            .map(|(&price, &size)| (price, size))
+    }
+
+    fn price(&self, method: PriceMethod, depth: Option<&DepthConfig>) -> Option<Decimal> {
+        if let Some(depth) = depth {
+            return depth_weighted_mid(&self.bid_levels(), &self.ask_levels(), depth);
+        }
+        let ((bid, bid_size), (ask, ask_size)) = (self.best_bid()?, self.best_ask()?);
+        Some(method.price(bid, ask, Some((bid_size, ask_size))))
+    }
 }
 
 impl BybitExchange {
     pub fn new(trading_pairs: Vec<TradingPair>) -> Self {
         Self {
-            trading_pairs,
+            trading_pairs: TrackedPairs::new(trading_pairs),
             last_heartbeat: AtomicI64::new(Utc::now().timestamp()),
+            ticks: TickCounter::new(),
+            ws_state: SharedConnState::default(),
+            remaps: SymbolRemaps::default(),
+            clock: system_clock(),
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+            price_method: PriceMethod::default(),
+            trades: false,
+            book_depth: 1,
+            depth: None,
+        }
+    }
+
+    /// How long the feed may go without a message before it is unhealthy.
+    pub fn with_heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    /// Time heartbeats and tick windows by `clock` instead of the system
+    /// clock.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.last_heartbeat = AtomicI64::new(clock.timestamp());
+        self.ticks = TickCounter::with_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    /// Translate assets Bybit lists under its own tickers.
+    pub fn with_symbol_remaps(mut self, remaps: SymbolRemaps) -> Self {
+        self.remaps = remaps;
+        self
+    }
+
+    /// How to price the top of book; the mid unless set.
+    pub fn with_price_method(mut self, price_method: PriceMethod) -> Self {
+        self.price_method = price_method;
+        self
+    }
+
+    /// Also subscribe to each symbol's public trades and emit last-trade
+    /// prices.
+    pub fn with_trades(mut self) -> Self {
+        self.trades = true;
+        self
+    }
+
+    /// Subscribe to `depth` levels per side instead of the top of book;
+    /// Bybit only publishes the depths in `BYBIT_BOOK_DEPTHS`.
+    pub fn with_book_depth(mut self, depth: u32) -> Self {
+        if BYBIT_BOOK_DEPTHS.contains(&depth) {
+            self.book_depth = depth;
+        } else {
+            warn!(
+                "Bybit does not publish a {}-level book, keeping {} (supported: {:?})",
+                depth, self.book_depth, BYBIT_BOOK_DEPTHS
+            );
         }
+        self
+    }
+
+    /// Switch from top-of-book to depth-weighted pricing over the book,
+    /// subscribing deeper if the book depth doesn't cover `depth.levels`.
+    pub fn with_depth_weighting(mut self, depth: DepthConfig) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    // The subscribed depth: the configured one, raised to the smallest that
+    // covers the depth-weighting levels
+    fn effective_book_depth(&self) -> u32 {
+        let wanted = self.depth.map_or(0, |depth| depth.levels);
+        BYBIT_BOOK_DEPTHS
+            .into_iter()
+            .find(|&depth| depth >= self.book_depth && depth as usize >= wanted)
+            .unwrap_or(BYBIT_BOOK_DEPTHS[BYBIT_BOOK_DEPTHS.len() - 1])
     }
 
     fn get_websocket_url(&self) -> String {
         "wss://stream.bybit.com/v5/public/spot".to_string()
     }
 
-    fn create_subscription_message(&self) -> String {
-        let args = self
-            .trading_pairs
-            .iter()
-            .map(|pair| format!("orderbook.1.{}", pair.to_bybit_symbol()))
-            .collect::<Vec<_>>();
+    // The topics to subscribe to for `pairs`
+    fn topics(&self, pairs: &[TradingPair]) -> Vec<String> {
+        let orderbook = format!("orderbook.{}", self.effective_book_depth());
+        let mut channels = vec![orderbook.as_str()];
+        if self.trades {
+            channels.push("publicTrade");
+        }
+        SubscriptionBuilder::new(pairs, &self.remaps, |pair| pair.to_bybit_symbol())
+            .topics(&channels, |channel, symbol| {
+                format!("{}.{}", channel, symbol)
+            })
+    }
 
+    fn create_op_message(op: &str, topics: &[String]) -> String {
         serde_json::json!({
-            "op": "subscribe",
-            "args": args
+            "op": op,
+            "args": topics
         })
         .to_string()
     }
 
+    // Re-subscribing to a topic makes Bybit push a fresh snapshot
+    fn create_resubscription_messages(topic: &str) -> [String; 2] {
+        [
+            serde_json::json!({ "op": "unsubscribe", "args": [topic] }).to_string(),
+            serde_json::json!({ "op": "subscribe", "args": [topic] }).to_string(),
+        ]
+    }
+
+    fn is_subscription_ack(text: &str) -> Result<bool, ExchangeError> {
+        Ok(serde_json::from_str::<BybitOpResponse>(text)
+            .is_ok_and(|response| response.op == "subscribe" && response.success == Some(true)))
+    }
+
+    fn create_ping_message() -> String {
+        serde_json::json!({ "op": "ping" }).to_string()
+    }
+
     fn update_heartbeat(&self) {
         self.last_heartbeat
-            .store(Utc::now().timestamp(), Ordering::SeqCst);
+            .store(self.clock.timestamp(), Ordering::SeqCst);
     }
 }
 
@@ -76,69 +333,315 @@ impl Exchange for BybitExchange {
         Ok(())
     }
 
-    async fn listen(&self, price_sender: Sender<PriceUpdate>) -> Result<()> {
-        let mut ws = WsStream::connect(&self.get_websocket_url()).await?;
-        info!("Connected to Bybit WebSocket");
+    async fn listen(
+        &self,
+        price_sender: Sender<PriceUpdate>,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> Result<(), ExchangeError> {
+        let mut pair_changes = self.trading_pairs.watch();
+        let pairs = pair_changes.borrow_and_update().clone();
+        let mut subscribed = self.topics(&pairs);
+        let subscription_msg = Self::create_op_message("subscribe", &subscribed);
+        let mut ws = WsStream::connect_and_subscribe_with(
+            &self.get_websocket_url(),
+            WsStreamConfig {
+                state: self.ws_state.clone(),
+                ..Default::default()
+            },
+            vec![subscription_msg.clone()],
+            Self::is_subscription_ack,
+        )
+        .await?;
+        info!("Subscribed to Bybit: {}", subscription_msg);
 
-        // Send subscription message
-        let subscription_msg = self.create_subscription_message();
-        ws.send_text(subscription_msg.clone()).await?;
-        info!("Sent subscription message to Bybit: {}", subscription_msg);
+        let mut symbols = canonical_symbols(&pairs, &self.remaps, |pair| pair.to_bybit_symbol());
 
         self.update_heartbeat();
 
-        while let Some(text) = ws.read_text().await? {
+        let mut ping_interval = interval(BYBIT_PING_INTERVAL);
+        ping_interval.tick().await;
+        let book_depth = self.effective_book_depth();
+        let topic_prefix = format!("orderbook.{}.", book_depth);
+        let mut books: HashMap<String, BybitBook> = HashMap::new();
+        // Symbols waiting on a fresh snapshot after a sequence gap
+        let mut resyncing: HashSet<String> = HashSet::new();
+
+        loop {
+            let text = tokio::select! {
+                _ = ping_interval.tick() => {
+                    ws.send_text(Self::create_ping_message()).await?;
+                    continue;
+                }
+                Ok(()) = pair_changes.changed() => {
+                    let pairs = pair_changes.borrow_and_update().clone();
+                    let topics = self.topics(&pairs);
+                    let (added, removed) = diff_subscriptions(&subscribed, &topics);
+                    if !removed.is_empty() {
+                        ws.send_text(Self::create_op_message("unsubscribe", &removed)).await?;
+                    }
+                    if !added.is_empty() {
+                        ws.send_text(Self::create_op_message("subscribe", &added)).await?;
+                    }
+                    info!("Resubscribed to Bybit: +{:?} -{:?}", added, removed);
+                    subscribed = topics;
+                    symbols = canonical_symbols(&pairs, &self.remaps, |pair| pair.to_bybit_symbol());
+                    books.retain(|native, _| symbols.contains_key(native));
+                    resyncing.retain(|native| symbols.contains_key(native));
+                    continue;
+                }
+                text = ws.read_text_or_shutdown(&mut shutdown) => match text? {
+                    Some(text) => text,
+                    None => break,
+                },
+            };
+
+            if let Ok(response) = serde_json::from_str::<BybitOpResponse>(&text) {
+                ws.record_decoded();
+                match response.op.as_str() {
+                    "subscribe" => {
+                        if response.success != Some(true) {
+                            return Err(ExchangeError::Rejected(format!(
+                                "Bybit subscription failed: {}",
+                                response.ret_msg.unwrap_or_default()
+                            )));
+                        }
+                    }
+                    "unsubscribe" => {}
+                    // Spot echoes op "ping" with ret_msg "pong"
+                    "ping" | "pong" => self.update_heartbeat(),
+                    other => warn!("Unexpected Bybit op response: {}", other),
+                }
+                continue;
+            }
+
+            if let Ok(message) = serde_json::from_str::<BybitTradeMessage>(&text) {
+                ws.record_decoded();
+                if !message.topic.starts_with("publicTrade.") {
+                    continue;
+                }
+                // Trades arrive oldest first; only the latest is the last price
+                let Some(trade) = message.data.last() else {
+                    continue;
+                };
+                let (Some(canonical), Ok(price)) =
+                    (symbols.get(&trade.symbol), Decimal::from_str(&trade.price))
+                else {
+                    continue;
+                };
+                let update = PriceUpdate {
+                    symbol: canonical.symbol.clone(),
+                    price,
+                    timestamp: Utc::now().into(),
+                    source: "bybit".to_string(),
+                    kind: PriceKind::LastTrade,
+                    bid: None,
+                    ask: None,
+                    exchange_timestamp: Some(system_time_from_millis(trade.trade_time)),
+                }
+                .scaled(canonical.price_scale);
+
+                if let Err(e) = price_sender.send(update).await {
+                    error!("Failed to send price update: {}", e);
+                    return Err(ExchangeError::ChannelClosed);
+                }
+
+                self.update_heartbeat();
+                self.ticks.record();
+                continue;
+            }
+
             if let Ok(orderbook) = serde_json::from_str::<BybitOrderbook>(&text) {
-                if let (Some(best_bid), Some(best_ask)) = (
-                    orderbook
-                        .data
-                        .bids
-                        .first()
-                        .and_then(|bid| bid[0].parse::<f64>().ok()),
-                    orderbook
-                        .data
-                        .asks
-                        .first()
-                        .and_then(|ask| ask[0].parse::<f64>().ok()),
-                ) {
-                    let mid_price = (best_bid + best_ask) / 2.0;
-                    let symbol = orderbook
-                        .topic
-                        .strip_prefix("orderbook.1.")
-                        .unwrap_or(&orderbook.topic)
-                        .to_string();
+                ws.record_decoded();
+                let native = orderbook
+                    .topic
+                    .strip_prefix(topic_prefix.as_str())
+                    .unwrap_or(&orderbook.topic);
+                let canonical = match symbols.get(native) {
+                    Some(canonical) => canonical,
+                    None => continue,
+                };
+                let data = &orderbook.data;
 
+                if orderbook.update_type == "snapshot" {
+                    let mut book = BybitBook {
+                        update_id: data.update_id,
+                        ..Default::default()
+                    };
+                    book.apply_all(data, book_depth);
+                    books.insert(native.to_string(), book);
+                    resyncing.remove(native);
+                } else {
+                    // A delta only applies on top of the message right before it
+                    let in_sequence = books
+                        .get(native)
+                        .is_some_and(|book| data.update_id == book.update_id + 1);
+                    if !in_sequence {
+                        books.remove(native);
+                        // Deltas keep arriving until the snapshot does, so
+                        // only ask once per gap
+                        if resyncing.insert(native.to_string()) {
+                            warn!(
+                                "Bybit {} update {} is out of sequence, resubscribing for a snapshot",
+                                native, data.update_id
+                            );
+                            for msg in Self::create_resubscription_messages(&orderbook.topic) {
+                                ws.send_text(msg).await?;
+                            }
+                        }
+                        continue;
+                    }
+                    if let Some(book) = books.get_mut(native) {
+                        book.update_id = data.update_id;
+                        book.apply_all(data, book_depth);
+                    }
+                }
+
+                let book = match books.get(native) {
+                    Some(book) => book,
+                    None => continue,
+                };
+                if let Some(price) = book.price(self.price_method, self.depth.as_ref()) {
                     let update = PriceUpdate {
-                        symbol,
-                        price: mid_price,
+                        symbol: canonical.symbol.clone(),
+                        price,
                         timestamp: Utc::now().into(),
                         source: "bybit".to_string(),
-                    };
+                        kind: if self.depth.is_some() {
+                            PriceKind::DepthWeighted
+                        } else {
+                            PriceKind::TopOfBook
+                        },
+                        bid: book.best_bid().map(|(price, _)| price),
+                        ask: book.best_ask().map(|(price, _)| price),
+                        exchange_timestamp: orderbook.ts.map(system_time_from_millis),
+                    }
+                    .scaled(canonical.price_scale);
 
                     if let Err(e) = price_sender.send(update).await {
                         error!("Failed to send price update: {}", e);
-                        return Err(anyhow!("Channel closed"));
+                        return Err(ExchangeError::ChannelClosed);
                     }
 
                     self.update_heartbeat();
+                    self.ticks.record();
                 }
+                continue;
             }
+
+            ws.record_decode_failure(&text)?;
         }
 
-        Err(anyhow!("WebSocket stream ended"))
+        if *shutdown.borrow() {
+            ws.close().await?;
+            return Ok(());
+        }
+
+        Err(ExchangeError::StreamEnded)
     }
 
-    fn get_trading_pairs(&self) -> &[TradingPair] {
+    fn tracked_pairs(&self) -> &TrackedPairs {
         &self.trading_pairs
     }
 
+    // Subscribes and unsubscribes topics on the open connection
+    fn resubscribes_live(&self) -> bool {
+        true
+    }
+
     fn get_name(&self) -> &'static str {
         "bybit"
     }
 
+    async fn fetch_snapshot(&self) -> Result<Vec<PriceUpdate>> {
+        let symbols = canonical_symbols(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_bybit_symbol()
+        });
+
+        let response: BybitRestResponse = reqwest::Client::new()
+            .get("https://api.bybit.com/v5/market/tickers")
+            .query(&[("category", "spot")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let mut updates = Vec::new();
+        for ticker in response.result.list {
+            if let (Some(canonical), Ok(best_bid), Ok(best_ask)) = (
+                symbols.get(&ticker.symbol),
+                Decimal::from_str(&ticker.bid1_price),
+                Decimal::from_str(&ticker.ask1_price),
+            ) {
+                updates.push(
+                    PriceUpdate {
+                        symbol: canonical.symbol.clone(),
+                        price: self.price_method.price(best_bid, best_ask, None),
+                        timestamp: Utc::now().into(),
+                        source: "bybit".to_string(),
+                        kind: PriceKind::TopOfBook,
+                        bid: Some(best_bid),
+                        ask: Some(best_ask),
+                        exchange_timestamp: None,
+                    }
+                    .scaled(canonical.price_scale),
+                );
+            }
+        }
+        Ok(updates)
+    }
+
     async fn is_healthy(&self) -> bool {
-        let last = self.last_heartbeat.load(Ordering::SeqCst);
-        let age = Utc::now().timestamp() - last;
-        age < 10
+        // A live socket isn't enough; prices must actually be arriving
+        heartbeat_is_recent(&self.last_heartbeat, &self.clock, self.heartbeat_timeout)
+            && self.ticks.is_active()
+    }
+
+    fn ticks_last_interval(&self) -> u64 {
+        self.ticks.last_interval()
+    }
+
+    fn ws_state(&self) -> ConnState {
+        self.ws_state.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(side: &[(&str, &str)]) -> Vec<(Decimal, Decimal)> {
+        side.iter()
+            .map(|(price, size)| (price.parse().unwrap(), size.parse().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn deltas_update_and_remove_levels_on_a_deep_book() {
+        let snapshot: BybitOrderbookData = serde_json::from_str(
+            r#"{"b":[["100","1"],["99","2"],["98","3"]],"a":[["101","1"],["102","2"]],"u":1}"#,
+        )
+        .unwrap();
+        let mut book = BybitBook {
+            update_id: snapshot.update_id,
+            ..Default::default()
+        };
+        book.apply_all(&snapshot, 50);
+
+        // The best bid is pulled, a new level joins and the best ask shrinks
+        let delta: BybitOrderbookData =
+            serde_json::from_str(r#"{"b":[["100","0"],["99.5","4"]],"a":[["101","0.5"]],"u":2}"#)
+                .unwrap();
+        book.apply_all(&delta, 50);
+
+        assert_eq!(
+            book.bid_levels(),
+            levels(&[("99.5", "4"), ("99", "2"), ("98", "3")])
+        );
+        assert_eq!(book.ask_levels(), levels(&[("101", "0.5"), ("102", "2")]));
+        assert_eq!(
+            book.price(PriceMethod::Mid, None),
+            Some(Decimal::new(10025, 2))
+        );
     }
 }
@@ -1,82 +1,211 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use async_trait::async_trait;
-use chrono::Utc;
-use log::{error, info};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use rust_decimal::Decimal;
 use serde::Deserialize;
+use std::collections::HashMap;
+use std::str::FromStr;
 use std::sync::atomic::{AtomicI64, Ordering};
-use tokio::sync::mpsc::Sender;
+use std::time::Duration;
+use tokio::sync::{mpsc::Sender, watch};
+use tokio::time::sleep;
 
-use super::{ws_stream::WsStream, Exchange};
-use crate::types::{PriceUpdate, TradingPair};
+use super::error::ExchangeError;
+use super::pairs::TrackedPairs;
+use super::remap::SymbolRemaps;
+use super::subscription::SubscriptionBuilder;
+use super::ticks::TickCounter;
+use super::ws_stream::{ConnState, SharedConnState, WsStream, WsStreamConfig};
+use super::{canonical_symbols, heartbeat_is_recent, Exchange, DEFAULT_HEARTBEAT_TIMEOUT};
+use crate::clock::{system_clock, SharedClock};
+use crate::types::{PriceKind, PriceMethod, PriceUpdate, TradingPair};
 
 pub struct CoinbaseExchange {
-    trading_pairs: Vec<TradingPair>,
+    trading_pairs: TrackedPairs,
     last_heartbeat: AtomicI64,
+    ticks: TickCounter,
+    ws_state: SharedConnState,
+    remaps: SymbolRemaps,
+    clock: SharedClock,
+    // Longest gap between messages before `is_healthy` fails
+    heartbeat_timeout: Duration,
+    price_method: PriceMethod,
+    // Also subscribe to matches and emit last-trade prices
+    trades: bool,
+    // Prices for pegged pairs, keyed by canonical symbol, sent when their
+    // ticker stays silent for PEG_FALLBACK_AFTER after subscribing
+    peg_fallbacks: HashMap<String, Decimal>,
 }
 
+// How long a pegged pair's ticker has to send its first tick before its
+// fallback price is published instead
+const PEG_FALLBACK_AFTER: Duration = Duration::from_secs(10);
+
 impl Clone for CoinbaseExchange {
     fn clone(&self) -> Self {
         Self {
             trading_pairs: self.trading_pairs.clone(),
             last_heartbeat: AtomicI64::new(self.last_heartbeat.load(Ordering::SeqCst)),
+            ticks: self.ticks.clone(),
+            ws_state: self.ws_state.clone(),
+            remaps: self.remaps.clone(),
+            clock: self.clock.clone(),
+            heartbeat_timeout: self.heartbeat_timeout,
+            price_method: self.price_method,
+            trades: self.trades,
+            peg_fallbacks: self.peg_fallbacks.clone(),
         }
     }
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum CoinbaseMessage {
+    Ticker(CoinbaseTicker),
+    // A trade; last_match is the most recent one, sent once on subscribe
+    #[serde(alias = "last_match")]
+    Match(CoinbaseMatch),
+    // Sent after every subscribe/unsubscribe with the active channels
+    Subscriptions {
+        channels: Vec<serde_json::Value>,
+    },
+    // e.g. an unknown product_id; no ticks follow for the rejected request
+    Error {
+        message: String,
+        reason: Option<String>,
+    },
+    #[serde(other)]
+    Other,
+}
+
 #[derive(Debug, Deserialize)]
 struct CoinbaseTicker {
     product_id: String,
     best_bid: String,
+    best_bid_size: Option<String>,
     best_ask: String,
+    best_ask_size: Option<String>,
+    // RFC 3339 match time
+    time: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CoinbaseMatch {
+    product_id: String,
+    price: String,
+    time: Option<String>,
+}
+
+fn parse_time(time: Option<&str>) -> Option<std::time::SystemTime> {
+    time.and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        .map(|time| time.into())
 }
 
 impl CoinbaseExchange {
     pub fn new(trading_pairs: Vec<TradingPair>) -> Self {
         Self {
-            trading_pairs,
+            trading_pairs: TrackedPairs::new(trading_pairs),
             last_heartbeat: AtomicI64::new(Utc::now().timestamp()),
+            ticks: TickCounter::new(),
+            ws_state: SharedConnState::default(),
+            remaps: SymbolRemaps::default(),
+            clock: system_clock(),
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+            price_method: PriceMethod::default(),
+            trades: false,
+            peg_fallbacks: HashMap::new(),
         }
     }
 
+    /// How long the feed may go without a message before it is unhealthy.
+    pub fn with_heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    /// Time heartbeats and tick windows by `clock` instead of the system
+    /// clock.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.last_heartbeat = AtomicI64::new(clock.timestamp());
+        self.ticks = TickCounter::with_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    /// Translate assets Coinbase lists under its own tickers.
+    pub fn with_symbol_remaps(mut self, remaps: SymbolRemaps) -> Self {
+        self.remaps = remaps;
+        self
+    }
+
+    /// How to price the top of book; the mid unless set.
+    pub fn with_price_method(mut self, price_method: PriceMethod) -> Self {
+        self.price_method = price_method;
+        self
+    }
+
+    /// Also subscribe to the matches channel and emit last-trade prices.
+    pub fn with_trades(mut self) -> Self {
+        self.trades = true;
+        self
+    }
+
+    /// Publish a fixed price for each pegged pair, keyed by canonical
+    /// symbol, whose ticker sends nothing in the first seconds after
+    /// subscribing. A real tick always wins.
+    pub fn with_peg_fallbacks(mut self, peg_fallbacks: HashMap<String, Decimal>) -> Self {
+        self.peg_fallbacks = peg_fallbacks;
+        self
+    }
+
     fn get_websocket_url(&self) -> String {
         "wss://ws-feed.exchange.coinbase.com/ws".to_string()
     }
 
     fn create_subscription_message(&self) -> String {
-        let product_ids = self
-            .trading_pairs
-            .iter()
-            .map(|pair| pair.to_coinbase_symbol())
-            .collect::<Vec<_>>();
+        let mut channels = vec!["ticker"];
+        if self.trades {
+            channels.push("matches");
+        }
 
-        serde_json::json!({
-            "type": "subscribe",
-            "product_ids": product_ids,
-            "channels": ["ticker"]
+        SubscriptionBuilder::new(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_coinbase_symbol()
+        })
+        .batched(|product_ids| {
+            serde_json::json!({
+                "type": "subscribe",
+                "product_ids": product_ids,
+                "channels": channels
+            })
         })
-        .to_string()
     }
 
-    fn update_heartbeat(&self) {
-        self.last_heartbeat
-            .store(Utc::now().timestamp(), Ordering::SeqCst);
-    }
-
-    fn handle_usdc_usdt(&self, price_sender: &Sender<PriceUpdate>) -> Result<()> {
-        // Special case: USDC/USDT is always 1:1
-        if self.trading_pairs.iter().any(|pair| {
-            pair.base.eq_ignore_ascii_case("USDC") && pair.quote.eq_ignore_ascii_case("USDT")
-        }) {
-            let update = PriceUpdate {
-                symbol: "USDCUSDT".to_string(),
-                price: 1.0,
-                timestamp: Utc::now().into(),
-                source: "coinbase".to_string(),
-            };
+    fn error_from(message: String, reason: Option<String>) -> ExchangeError {
+        ExchangeError::Rejected(match reason {
+            Some(reason) => format!("Coinbase error: {} ({})", message, reason),
+            None => format!("Coinbase error: {}", message),
+        })
+    }
 
-            price_sender.try_send(update)?;
+    // Coinbase confirms with the full list of active channels, or rejects the
+    // whole subscribe with an error
+    fn is_subscription_ack(text: &str) -> Result<bool, ExchangeError> {
+        match serde_json::from_str::<CoinbaseMessage>(text) {
+            Ok(CoinbaseMessage::Subscriptions { channels }) => {
+                info!("Coinbase subscriptions confirmed: {:?}", channels);
+                Ok(true)
+            }
+            Ok(CoinbaseMessage::Error { message, reason }) => {
+                Err(Self::error_from(message, reason))
+            }
+            _ => Ok(false),
         }
-        Ok(())
+    }
+
+    fn update_heartbeat(&self) {
+        self.last_heartbeat
+            .store(self.clock.timestamp(), Ordering::SeqCst);
     }
 }
 
@@ -87,53 +216,174 @@ impl Exchange for CoinbaseExchange {
         Ok(())
     }
 
-    async fn listen(&self, price_sender: Sender<PriceUpdate>) -> Result<()> {
-        // Handle special case for USDC/USDT
-        self.handle_usdc_usdt(&price_sender)?;
-
-        let mut ws = WsStream::connect(&self.get_websocket_url()).await?;
-        info!("Connected to Coinbase WebSocket");
-
-        // Send subscription message
+    async fn listen(
+        &self,
+        price_sender: Sender<PriceUpdate>,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> Result<(), ExchangeError> {
         let subscription_msg = self.create_subscription_message();
-        ws.send_text(subscription_msg.clone()).await?;
-        info!(
-            "Sent subscription message to Coinbase: {}",
-            subscription_msg
-        );
+        let mut ws = WsStream::connect_and_subscribe_with(
+            &self.get_websocket_url(),
+            WsStreamConfig {
+                state: self.ws_state.clone(),
+                ..Default::default()
+            },
+            vec![subscription_msg.clone()],
+            Self::is_subscription_ack,
+        )
+        .await?;
+        info!("Subscribed to Coinbase: {}", subscription_msg);
+
+        let symbols = canonical_symbols(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_coinbase_symbol()
+        });
 
         self.update_heartbeat();
 
-        while let Some(text) = ws.read_text().await? {
-            if let Ok(ticker) = serde_json::from_str::<CoinbaseTicker>(&text) {
-                if let (Ok(best_bid), Ok(best_ask)) = (
-                    ticker.best_bid.parse::<f64>(),
-                    ticker.best_ask.parse::<f64>(),
-                ) {
-                    let mid_price = (best_bid + best_ask) / 2.0;
-                    let symbol = ticker.product_id.replace("-", "");
+        // Pegged pairs we track that haven't ticked yet on this connection
+        let mut pending_fallbacks: HashMap<String, Decimal> = self
+            .peg_fallbacks
+            .iter()
+            .filter(|(symbol, _)| {
+                symbols
+                    .values()
+                    .any(|canonical| &canonical.symbol == *symbol)
+            })
+            .map(|(symbol, price)| (symbol.clone(), *price))
+            .collect();
+        let fallback_deadline = sleep(PEG_FALLBACK_AFTER);
+        tokio::pin!(fallback_deadline);
 
+        loop {
+            let text = tokio::select! {
+                _ = &mut fallback_deadline, if !pending_fallbacks.is_empty() => {
+                    for (symbol, price) in pending_fallbacks.drain() {
+                        warn!(
+                            "No Coinbase ticker for {} after {:?}, publishing fallback price {}",
+                            symbol, PEG_FALLBACK_AFTER, price
+                        );
+                        let update = PriceUpdate {
+                            symbol,
+                            price,
+                            timestamp: Utc::now().into(),
+                            source: "coinbase".to_string(),
+                            kind: PriceKind::TopOfBook,
+                            bid: None,
+                            ask: None,
+                            exchange_timestamp: None,
+                        };
+                        if let Err(e) = price_sender.send(update).await {
+                            error!("Failed to send price update: {}", e);
+                            return Err(ExchangeError::ChannelClosed);
+                        }
+                    }
+                    continue;
+                }
+                text = ws.read_text_or_shutdown(&mut shutdown) => match text? {
+                    Some(text) => text,
+                    None => break,
+                },
+            };
+            let message = match serde_json::from_str::<CoinbaseMessage>(&text) {
+                Ok(message) => message,
+                Err(_) => {
+                    ws.record_decode_failure(&text)?;
+                    continue;
+                }
+            };
+            ws.record_decoded();
+            let ticker = match message {
+                CoinbaseMessage::Ticker(ticker) => ticker,
+                CoinbaseMessage::Match(trade) => {
+                    let (Some(canonical), Ok(price)) = (
+                        symbols.get(&trade.product_id),
+                        Decimal::from_str(&trade.price),
+                    ) else {
+                        continue;
+                    };
                     let update = PriceUpdate {
-                        symbol,
-                        price: mid_price,
+                        symbol: canonical.symbol.clone(),
+                        price,
                         timestamp: Utc::now().into(),
                         source: "coinbase".to_string(),
-                    };
+                        kind: PriceKind::LastTrade,
+                        bid: None,
+                        ask: None,
+                        exchange_timestamp: parse_time(trade.time.as_deref()),
+                    }
+                    .scaled(canonical.price_scale);
 
                     if let Err(e) = price_sender.send(update).await {
                         error!("Failed to send price update: {}", e);
-                        return Err(anyhow!("Channel closed"));
+                        return Err(ExchangeError::ChannelClosed);
                     }
 
                     self.update_heartbeat();
+                    self.ticks.record();
+                    continue;
+                }
+                CoinbaseMessage::Subscriptions { channels } => {
+                    info!("Coinbase subscriptions updated: {:?}", channels);
+                    continue;
+                }
+                // Restart the connection rather than sit on a dead subscription
+                CoinbaseMessage::Error { message, reason } => {
+                    return Err(Self::error_from(message, reason));
+                }
+                CoinbaseMessage::Other => continue,
+            };
+
+            if let (Ok(best_bid), Ok(best_ask)) = (
+                Decimal::from_str(&ticker.best_bid),
+                Decimal::from_str(&ticker.best_ask),
+            ) {
+                let sizes = ticker
+                    .best_bid_size
+                    .as_deref()
+                    .and_then(|size| Decimal::from_str(size).ok())
+                    .zip(
+                        ticker
+                            .best_ask_size
+                            .as_deref()
+                            .and_then(|size| Decimal::from_str(size).ok()),
+                    );
+                let canonical = match symbols.get(&ticker.product_id) {
+                    Some(canonical) => canonical,
+                    None => continue,
+                };
+                pending_fallbacks.remove(&canonical.symbol);
+
+                let update = PriceUpdate {
+                    symbol: canonical.symbol.clone(),
+                    price: self.price_method.price(best_bid, best_ask, sizes),
+                    timestamp: Utc::now().into(),
+                    source: "coinbase".to_string(),
+                    kind: PriceKind::TopOfBook,
+                    bid: Some(best_bid),
+                    ask: Some(best_ask),
+                    exchange_timestamp: parse_time(ticker.time.as_deref()),
+                }
+                .scaled(canonical.price_scale);
+
+                if let Err(e) = price_sender.send(update).await {
+                    error!("Failed to send price update: {}", e);
+                    return Err(ExchangeError::ChannelClosed);
                 }
+
+                self.update_heartbeat();
+                self.ticks.record();
             }
         }
 
-        Err(anyhow!("WebSocket stream ended"))
+        if *shutdown.borrow() {
+            ws.close().await?;
+            return Ok(());
+        }
+
+        Err(ExchangeError::StreamEnded)
     }
 
-    fn get_trading_pairs(&self) -> &[TradingPair] {
+    fn tracked_pairs(&self) -> &TrackedPairs {
         &self.trading_pairs
     }
 
@@ -142,8 +392,16 @@ impl Exchange for CoinbaseExchange {
     }
 
     async fn is_healthy(&self) -> bool {
-        let last = self.last_heartbeat.load(Ordering::SeqCst);
-        let age = Utc::now().timestamp() - last;
-        age < 10
+        // A live socket isn't enough; prices must actually be arriving
+        heartbeat_is_recent(&self.last_heartbeat, &self.clock, self.heartbeat_timeout)
+            && self.ticks.is_active()
+    }
+
+    fn ticks_last_interval(&self) -> u64 {
+        self.ticks.last_interval()
+    }
+
+    fn ws_state(&self) -> ConnState {
+        self.ws_state.get()
     }
 }
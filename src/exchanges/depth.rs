@@ -0,0 +1,65 @@
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::str::FromStr;
+
+// How deep to walk the book when computing a depth-weighted price
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct DepthConfig {
+    pub levels: usize,
+    pub max_notional: Decimal,
+}
+
+/// Order book depths Bybit publishes (`orderbook.{depth}.{symbol}`).
+pub const BYBIT_BOOK_DEPTHS: [u32; 4] = [1, 50, 200, 500];
+
+// Parses [price, size] string levels as sent by most exchanges, dropping any
+// level that doesn't parse
+pub fn parse_levels(levels: &[Vec<String>]) -> Vec<(Decimal, Decimal)> {
+    levels
+        .iter()
+        .filter_map(|level| {
+            let price = Decimal::from_str(level.first()?).ok()?;
+            let size = Decimal::from_str(level.get(1)?).ok()?;
+            Some((price, size))
+        })
+        .collect()
+}
+
+// VWAP of one side of the book, walking at most `config.levels` levels until
+// `config.max_notional` has been filled
+fn side_vwap(levels: &[(Decimal, Decimal)], config: &DepthConfig) -> Option<Decimal> {
+    let mut notional = Decimal::ZERO;
+    let mut quantity = Decimal::ZERO;
+
+    for &(price, size) in levels.iter().take(config.levels) {
+        let remaining = config.max_notional - notional;
+        if remaining <= Decimal::ZERO || price <= Decimal::ZERO {
+            break;
+        }
+        let fill = if price * size > remaining {
+            remaining / price
+        } else {
+            size
+        };
+        notional += fill * price;
+        quantity += fill;
+    }
+
+    if quantity.is_zero() {
+        None
+    } else {
+        Some(notional / quantity)
+    }
+}
+
+/// Mid of the bid-side and ask-side VWAPs over the configured depth. Levels
+/// must be ordered best-first.
+pub fn depth_weighted_mid(
+    bids: &[(Decimal, Decimal)],
+    asks: &[(Decimal, Decimal)],
+    config: &DepthConfig,
+) -> Option<Decimal> {
+    let bid = side_vwap(bids, config)?;
+    let ask = side_vwap(asks, config)?;
+    Some((bid + ask) / Decimal::TWO)
+}
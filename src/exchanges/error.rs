@@ -0,0 +1,69 @@
+use tokio_tungstenite::tungstenite::{
+    self,
+    protocol::{frame::coding::CloseCode, CloseFrame},
+};
+
+/// Why an exchange feed stopped, so the publisher can tell a server hanging
+/// up from a connection that can't be established or a broken feed.
+#[derive(Debug, thiserror::Error)]
+pub enum ExchangeError {
+    // The publisher dropped its receiver, normally during shutdown
+    #[error("Price channel closed")]
+    ChannelClosed,
+    // DNS, TCP, TLS or proxy failures while establishing the connection
+    #[error("WebSocket connection error: {0}")]
+    Connect(String),
+    #[error("WebSocket connection timeout")]
+    ConnectTimeout,
+    #[error("Timed out waiting for subscription ack")]
+    SubscribeTimeout,
+    #[error("WebSocket closed by server: {}", describe_close(.0))]
+    ServerClose(Option<CloseFrame<'static>>),
+    // The tracked pairs changed on an exchange that has to reconnect to
+    // subscribe to them
+    #[error("Trading pairs changed, reconnecting")]
+    PairsChanged,
+    #[error("WebSocket stream ended")]
+    StreamEnded,
+    #[error("WebSocket ping timeout")]
+    PingTimeout,
+    #[error("WebSocket error: {0}")]
+    // Boxed, tungstenite's error is several times the size of the rest
+    WebSocket(Box<tungstenite::Error>),
+    #[error("Failed to deserialize message: {0}")]
+    Deserialize(#[from] serde_json::Error),
+    // This many messages in a row matched none of the exchange's message
+    // types, e.g. after a schema change
+    #[error("{0} consecutive messages failed to deserialize")]
+    Undecodable(u32),
+    #[error("Invalid price: {0}")]
+    InvalidPrice(#[from] rust_decimal::Error),
+    // Reported by the exchange itself, e.g. a rejected subscription
+    #[error("{0}")]
+    Rejected(String),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl ExchangeError {
+    /// The code the server closed the connection with, if it sent one.
+    pub fn close_code(&self) -> Option<CloseCode> {
+        match self {
+            ExchangeError::ServerClose(Some(frame)) => Some(frame.code),
+            _ => None,
+        }
+    }
+}
+
+impl From<tungstenite::Error> for ExchangeError {
+    fn from(error: tungstenite::Error) -> Self {
+        ExchangeError::WebSocket(Box::new(error))
+    }
+}
+
+fn describe_close(frame: &Option<CloseFrame<'static>>) -> String {
+    match frame {
+        Some(frame) => frame.to_string(),
+        None => "no close frame".to_string(),
+    }
+}
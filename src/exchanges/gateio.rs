@@ -0,0 +1,342 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use log::{error, info};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicI64, Ordering};
+use tokio::sync::{mpsc::Sender, watch};
+use tokio::time::{interval, Duration};
+
+use super::error::ExchangeError;
+use super::pairs::TrackedPairs;
+use super::remap::SymbolRemaps;
+use super::subscription::SubscriptionBuilder;
+use super::ticks::TickCounter;
+use super::ws_stream::{ConnState, SharedConnState, WsStream, WsStreamConfig};
+use super::{canonical_symbols, heartbeat_is_recent, Exchange, DEFAULT_HEARTBEAT_TIMEOUT};
+use crate::clock::{system_clock, SharedClock};
+use crate::types::{system_time_from_millis, PriceKind, PriceMethod, PriceUpdate, TradingPair};
+
+// Gate.io expects an application-level spot.ping to keep the connection alive
+const GATEIO_PING_INTERVAL: Duration = Duration::from_secs(20);
+
+pub struct GateioExchange {
+    trading_pairs: TrackedPairs,
+    last_heartbeat: AtomicI64,
+    ticks: TickCounter,
+    ws_state: SharedConnState,
+    remaps: SymbolRemaps,
+    clock: SharedClock,
+    // Longest gap between messages before `is_healthy` fails
+    heartbeat_timeout: Duration,
+    price_method: PriceMethod,
+}
+
+impl Clone for GateioExchange {
+    fn clone(&self) -> Self {
+        Self {
+            trading_pairs: self.trading_pairs.clone(),
+            last_heartbeat: AtomicI64::new(self.last_heartbeat.load(Ordering::SeqCst)),
+            ticks: self.ticks.clone(),
+            ws_state: self.ws_state.clone(),
+            remaps: self.remaps.clone(),
+            clock: self.clock.clone(),
+            heartbeat_timeout: self.heartbeat_timeout,
+            price_method: self.price_method,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GateioMessage {
+    channel: String,
+    event: Option<String>,
+    result: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GateioBookTicker {
+    // Milliseconds since epoch
+    #[serde(rename = "t")]
+    time: Option<i64>,
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "b")]
+    best_bid: String,
+    #[serde(rename = "B")]
+    best_bid_size: String,
+    #[serde(rename = "a")]
+    best_ask: String,
+    #[serde(rename = "A")]
+    best_ask_size: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GateioRestTicker {
+    currency_pair: String,
+    highest_bid: String,
+    lowest_ask: String,
+}
+
+impl GateioExchange {
+    pub fn new(trading_pairs: Vec<TradingPair>) -> Self {
+        Self {
+            trading_pairs: TrackedPairs::new(trading_pairs),
+            last_heartbeat: AtomicI64::new(Utc::now().timestamp()),
+            ticks: TickCounter::new(),
+            ws_state: SharedConnState::default(),
+            remaps: SymbolRemaps::default(),
+            clock: system_clock(),
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+            price_method: PriceMethod::default(),
+        }
+    }
+
+    /// How long the feed may go without a message before it is unhealthy.
+    pub fn with_heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    /// Time heartbeats and tick windows by `clock` instead of the system
+    /// clock.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.last_heartbeat = AtomicI64::new(clock.timestamp());
+        self.ticks = TickCounter::with_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    /// Translate assets Gate.io lists under its own tickers.
+    pub fn with_symbol_remaps(mut self, remaps: SymbolRemaps) -> Self {
+        self.remaps = remaps;
+        self
+    }
+
+    /// How to price the top of book; the mid unless set.
+    pub fn with_price_method(mut self, price_method: PriceMethod) -> Self {
+        self.price_method = price_method;
+        self
+    }
+
+    fn get_websocket_url(&self) -> String {
+        "wss://api.gateio.ws/ws/v4/".to_string()
+    }
+
+    fn create_subscription_message(&self) -> String {
+        SubscriptionBuilder::new(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_gateio_symbol()
+        })
+        .batched(|payload| {
+            serde_json::json!({
+                "time": Utc::now().timestamp(),
+                "channel": "spot.book_ticker",
+                "event": "subscribe",
+                "payload": payload
+            })
+        })
+    }
+
+    fn is_subscription_ack(text: &str) -> Result<bool, ExchangeError> {
+        Ok(
+            serde_json::from_str::<GateioMessage>(text).is_ok_and(|message| {
+                message.channel == "spot.book_ticker"
+                    && message.event.as_deref() == Some("subscribe")
+                    && message
+                        .result
+                        .is_some_and(|result| result["status"] == "success")
+            }),
+        )
+    }
+
+    fn create_ping_message() -> String {
+        serde_json::json!({
+            "time": Utc::now().timestamp(),
+            "channel": "spot.ping"
+        })
+        .to_string()
+    }
+
+    fn update_heartbeat(&self) {
+        self.last_heartbeat
+            .store(self.clock.timestamp(), Ordering::SeqCst);
+    }
+}
+
+#[async_trait]
+impl Exchange for GateioExchange {
+    async fn init(&mut self) -> Result<()> {
+        // Gate.io doesn't require initialization
+        Ok(())
+    }
+
+    async fn listen(
+        &self,
+        price_sender: Sender<PriceUpdate>,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> Result<(), ExchangeError> {
+        let subscription_msg = self.create_subscription_message();
+        let mut ws = WsStream::connect_and_subscribe_with(
+            &self.get_websocket_url(),
+            WsStreamConfig {
+                state: self.ws_state.clone(),
+                ..Default::default()
+            },
+            vec![subscription_msg.clone()],
+            Self::is_subscription_ack,
+        )
+        .await?;
+        info!("Subscribed to Gate.io: {}", subscription_msg);
+
+        let symbols = canonical_symbols(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_gateio_symbol()
+        });
+
+        self.update_heartbeat();
+
+        let mut ping_interval = interval(GATEIO_PING_INTERVAL);
+        ping_interval.tick().await;
+
+        loop {
+            let text = tokio::select! {
+                _ = ping_interval.tick() => {
+                    ws.send_text(Self::create_ping_message()).await?;
+                    continue;
+                }
+                text = ws.read_text_or_shutdown(&mut shutdown) => match text? {
+                    Some(text) => text,
+                    None => break,
+                },
+            };
+
+            let message = match serde_json::from_str::<GateioMessage>(&text) {
+                Ok(message) => message,
+                Err(_) => {
+                    ws.record_decode_failure(&text)?;
+                    continue;
+                }
+            };
+            if message.channel == "spot.pong" {
+                ws.record_decoded();
+                self.update_heartbeat();
+                continue;
+            }
+            if message.channel != "spot.book_ticker" || message.event.as_deref() != Some("update") {
+                ws.record_decoded();
+                continue;
+            }
+
+            let ticker = match message
+                .result
+                .and_then(|result| serde_json::from_value::<GateioBookTicker>(result).ok())
+            {
+                Some(ticker) => ticker,
+                None => {
+                    ws.record_decode_failure(&text)?;
+                    continue;
+                }
+            };
+            ws.record_decoded();
+            let canonical = match symbols.get(&ticker.symbol) {
+                Some(canonical) => canonical,
+                None => continue,
+            };
+            if let (Ok(best_bid), Ok(best_ask)) = (
+                Decimal::from_str(&ticker.best_bid),
+                Decimal::from_str(&ticker.best_ask),
+            ) {
+                let sizes = Decimal::from_str(&ticker.best_bid_size)
+                    .ok()
+                    .zip(Decimal::from_str(&ticker.best_ask_size).ok());
+                let update = PriceUpdate {
+                    symbol: canonical.symbol.clone(),
+                    price: self.price_method.price(best_bid, best_ask, sizes),
+                    timestamp: Utc::now().into(),
+                    source: "gateio".to_string(),
+                    kind: PriceKind::TopOfBook,
+                    bid: Some(best_bid),
+                    ask: Some(best_ask),
+                    exchange_timestamp: ticker.time.map(system_time_from_millis),
+                }
+                .scaled(canonical.price_scale);
+
+                if let Err(e) = price_sender.send(update).await {
+                    error!("Failed to send price update: {}", e);
+                    return Err(ExchangeError::ChannelClosed);
+                }
+
+                self.update_heartbeat();
+                self.ticks.record();
+            }
+        }
+
+        if *shutdown.borrow() {
+            ws.close().await?;
+            return Ok(());
+        }
+
+        Err(ExchangeError::StreamEnded)
+    }
+
+    fn tracked_pairs(&self) -> &TrackedPairs {
+        &self.trading_pairs
+    }
+
+    fn get_name(&self) -> &'static str {
+        "gateio"
+    }
+
+    async fn fetch_snapshot(&self) -> Result<Vec<PriceUpdate>> {
+        let symbols = canonical_symbols(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_gateio_symbol()
+        });
+
+        let tickers: Vec<GateioRestTicker> = reqwest::Client::new()
+            .get("https://api.gateio.ws/api/v4/spot/tickers")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let mut updates = Vec::new();
+        for ticker in tickers {
+            if let (Some(canonical), Ok(best_bid), Ok(best_ask)) = (
+                symbols.get(&ticker.currency_pair),
+                Decimal::from_str(&ticker.highest_bid),
+                Decimal::from_str(&ticker.lowest_ask),
+            ) {
+                updates.push(
+                    PriceUpdate {
+                        symbol: canonical.symbol.clone(),
+                        price: self.price_method.price(best_bid, best_ask, None),
+                        timestamp: Utc::now().into(),
+                        source: "gateio".to_string(),
+                        kind: PriceKind::TopOfBook,
+                        bid: Some(best_bid),
+                        ask: Some(best_ask),
+                        exchange_timestamp: None,
+                    }
+                    .scaled(canonical.price_scale),
+                );
+            }
+        }
+        Ok(updates)
+    }
+
+    async fn is_healthy(&self) -> bool {
+        // A live socket isn't enough; prices must actually be arriving
+        heartbeat_is_recent(&self.last_heartbeat, &self.clock, self.heartbeat_timeout)
+            && self.ticks.is_active()
+    }
+
+    fn ticks_last_interval(&self) -> u64 {
+        self.ticks.last_interval()
+    }
+
+    fn ws_state(&self) -> ConnState {
+        self.ws_state.get()
+    }
+}
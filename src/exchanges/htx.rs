@@ -0,0 +1,340 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use log::{error, info};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+use tokio::sync::{mpsc::Sender, watch};
+
+use super::error::ExchangeError;
+use super::pairs::TrackedPairs;
+use super::remap::SymbolRemaps;
+use super::subscription::SubscriptionBuilder;
+use super::ticks::TickCounter;
+use super::ws_stream::{Compression, ConnState, SharedConnState, WsStream, WsStreamConfig};
+use super::{canonical_symbols, heartbeat_is_recent, Exchange, DEFAULT_HEARTBEAT_TIMEOUT};
+use crate::clock::{system_clock, SharedClock};
+use crate::types::{system_time_from_millis, PriceKind, PriceMethod, PriceUpdate, TradingPair};
+
+/// HTX, formerly Huobi. Every frame is gzip-compressed binary, and the
+/// server's `{"ping":ts}` must be answered with `{"pong":ts}` or it drops
+/// the connection after two missed pings.
+pub struct HtxExchange {
+    trading_pairs: TrackedPairs,
+    last_heartbeat: AtomicI64,
+    ticks: TickCounter,
+    ws_state: SharedConnState,
+    remaps: SymbolRemaps,
+    clock: SharedClock,
+    // Longest gap between messages before `is_healthy` fails
+    heartbeat_timeout: Duration,
+    price_method: PriceMethod,
+    websocket_url: Option<String>,
+}
+
+impl Clone for HtxExchange {
+    fn clone(&self) -> Self {
+        Self {
+            trading_pairs: self.trading_pairs.clone(),
+            last_heartbeat: AtomicI64::new(self.last_heartbeat.load(Ordering::SeqCst)),
+            ticks: self.ticks.clone(),
+            ws_state: self.ws_state.clone(),
+            remaps: self.remaps.clone(),
+            clock: self.clock.clone(),
+            heartbeat_timeout: self.heartbeat_timeout,
+            price_method: self.price_method,
+            websocket_url: self.websocket_url.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct HtxMessage {
+    // Server heartbeat, to be echoed back as a pong
+    ping: Option<i64>,
+    // e.g. market.btcusdt.bbo
+    ch: Option<String>,
+    tick: Option<HtxBbo>,
+    // "ok" or "error" on subscription replies
+    status: Option<String>,
+    #[serde(rename = "err-msg")]
+    err_msg: Option<String>,
+}
+
+// market.{symbol}.bbo tick; prices and sizes are JSON numbers
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HtxBbo {
+    bid: Decimal,
+    bid_size: Decimal,
+    ask: Decimal,
+    ask_size: Decimal,
+    // Milliseconds since epoch
+    quote_time: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HtxRestTickers {
+    data: Vec<HtxRestTicker>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HtxRestTicker {
+    symbol: String,
+    bid: Option<Decimal>,
+    ask: Option<Decimal>,
+}
+
+impl HtxExchange {
+    pub fn new(trading_pairs: Vec<TradingPair>) -> Self {
+        Self {
+            trading_pairs: TrackedPairs::new(trading_pairs),
+            last_heartbeat: AtomicI64::new(Utc::now().timestamp()),
+            ticks: TickCounter::new(),
+            ws_state: SharedConnState::default(),
+            remaps: SymbolRemaps::default(),
+            clock: system_clock(),
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+            price_method: PriceMethod::default(),
+            websocket_url: None,
+        }
+    }
+
+    /// How long the feed may go without a message before it is unhealthy.
+    pub fn with_heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    /// Time heartbeats and tick windows by `clock` instead of the system
+    /// clock.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.last_heartbeat = AtomicI64::new(clock.timestamp());
+        self.ticks = TickCounter::with_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    /// Translate assets HTX lists under its own tickers.
+    pub fn with_symbol_remaps(mut self, remaps: SymbolRemaps) -> Self {
+        self.remaps = remaps;
+        self
+    }
+
+    /// How to price the top of book; the mid unless set.
+    pub fn with_price_method(mut self, price_method: PriceMethod) -> Self {
+        self.price_method = price_method;
+        self
+    }
+
+    /// Connect to `url` instead of HTX's public market endpoint.
+    pub fn with_websocket_url(mut self, url: impl Into<String>) -> Self {
+        self.websocket_url = Some(url.into());
+        self
+    }
+
+    fn get_websocket_url(&self) -> String {
+        if let Some(url) = &self.websocket_url {
+            return url.clone();
+        }
+        "wss://api.huobi.pro/ws".to_string()
+    }
+
+    // HTX takes one topic per sub message
+    fn create_subscription_messages(&self) -> Vec<String> {
+        SubscriptionBuilder::new(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_htx_symbol()
+        })
+        .per_symbol(|symbol| {
+            serde_json::json!({
+                "sub": format!("market.{}.bbo", symbol),
+                "id": symbol
+            })
+        })
+    }
+
+    fn create_pong_message(ping: i64) -> String {
+        serde_json::json!({ "pong": ping }).to_string()
+    }
+
+    // Answered with status "ok", or "error" for a symbol HTX doesn't list
+    fn is_subscription_ack(text: &str) -> Result<bool, ExchangeError> {
+        match serde_json::from_str::<HtxMessage>(text) {
+            Ok(message) => match message.status.as_deref() {
+                Some("ok") => Ok(true),
+                Some("error") => Err(ExchangeError::Rejected(format!(
+                    "HTX subscription failed: {}",
+                    message.err_msg.unwrap_or_default()
+                ))),
+                _ => Ok(false),
+            },
+            Err(_) => Ok(false),
+        }
+    }
+
+    fn update_heartbeat(&self) {
+        self.last_heartbeat
+            .store(self.clock.timestamp(), Ordering::SeqCst);
+    }
+}
+
+#[async_trait]
+impl Exchange for HtxExchange {
+    async fn init(&mut self) -> Result<()> {
+        // HTX doesn't require initialization
+        Ok(())
+    }
+
+    async fn listen(
+        &self,
+        price_sender: Sender<PriceUpdate>,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> Result<(), ExchangeError> {
+        let subscription_msgs = self.create_subscription_messages();
+        let mut ws = WsStream::connect_and_subscribe_with(
+            &self.get_websocket_url(),
+            WsStreamConfig {
+                state: self.ws_state.clone(),
+                compression: Some(Compression::Gzip),
+                ..Default::default()
+            },
+            subscription_msgs.clone(),
+            Self::is_subscription_ack,
+        )
+        .await?;
+        info!("Subscribed to HTX: {:?}", subscription_msgs);
+
+        let symbols = canonical_symbols(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_htx_symbol()
+        });
+
+        self.update_heartbeat();
+
+        while let Some(text) = ws.read_text_or_shutdown(&mut shutdown).await? {
+            let message = match serde_json::from_str::<HtxMessage>(&text) {
+                Ok(message) => message,
+                Err(_) => {
+                    ws.record_decode_failure(&text)?;
+                    continue;
+                }
+            };
+            ws.record_decoded();
+            if let Some(ping) = message.ping {
+                ws.send_text(Self::create_pong_message(ping)).await?;
+                self.update_heartbeat();
+                continue;
+            }
+            if message.status.as_deref() == Some("error") {
+                return Err(ExchangeError::Rejected(format!(
+                    "HTX error: {}",
+                    message.err_msg.unwrap_or_default()
+                )));
+            }
+
+            let canonical = match message
+                .ch
+                .as_deref()
+                .and_then(|channel| channel.strip_prefix("market."))
+                .and_then(|channel| channel.strip_suffix(".bbo"))
+                .and_then(|native| symbols.get(native))
+            {
+                Some(canonical) => canonical,
+                None => continue,
+            };
+            let Some(bbo) = message.tick else {
+                continue;
+            };
+
+            let update = PriceUpdate {
+                symbol: canonical.symbol.clone(),
+                price: self.price_method.price(
+                    bbo.bid,
+                    bbo.ask,
+                    Some((bbo.bid_size, bbo.ask_size)),
+                ),
+                timestamp: Utc::now().into(),
+                source: "htx".to_string(),
+                kind: PriceKind::TopOfBook,
+                bid: Some(bbo.bid),
+                ask: Some(bbo.ask),
+                exchange_timestamp: bbo.quote_time.map(system_time_from_millis),
+            }
+            .scaled(canonical.price_scale);
+
+            if let Err(e) = price_sender.send(update).await {
+                error!("Failed to send price update: {}", e);
+                return Err(ExchangeError::ChannelClosed);
+            }
+
+            self.update_heartbeat();
+            self.ticks.record();
+        }
+
+        if *shutdown.borrow() {
+            ws.close().await?;
+            return Ok(());
+        }
+
+        Err(ExchangeError::StreamEnded)
+    }
+
+    fn tracked_pairs(&self) -> &TrackedPairs {
+        &self.trading_pairs
+    }
+
+    fn get_name(&self) -> &'static str {
+        "htx"
+    }
+
+    async fn fetch_snapshot(&self) -> Result<Vec<PriceUpdate>> {
+        let symbols = canonical_symbols(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_htx_symbol()
+        });
+
+        let tickers: HtxRestTickers = reqwest::Client::new()
+            .get("https://api.huobi.pro/market/tickers")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let mut updates = Vec::new();
+        for ticker in tickers.data {
+            if let (Some(canonical), Some(best_bid), Some(best_ask)) =
+                (symbols.get(&ticker.symbol), ticker.bid, ticker.ask)
+            {
+                updates.push(
+                    PriceUpdate {
+                        symbol: canonical.symbol.clone(),
+                        price: self.price_method.price(best_bid, best_ask, None),
+                        timestamp: Utc::now().into(),
+                        source: "htx".to_string(),
+                        kind: PriceKind::TopOfBook,
+                        bid: Some(best_bid),
+                        ask: Some(best_ask),
+                        exchange_timestamp: None,
+                    }
+                    .scaled(canonical.price_scale),
+                );
+            }
+        }
+        Ok(updates)
+    }
+
+    async fn is_healthy(&self) -> bool {
+        // A live socket isn't enough; prices must actually be arriving
+        heartbeat_is_recent(&self.last_heartbeat, &self.clock, self.heartbeat_timeout)
+            && self.ticks.is_active()
+    }
+
+    fn ticks_last_interval(&self) -> u64 {
+        self.ticks.last_interval()
+    }
+
+    fn ws_state(&self) -> ConnState {
+        self.ws_state.get()
+    }
+}
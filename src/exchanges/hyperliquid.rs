@@ -1,18 +1,41 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use async_trait::async_trait;
 use log::{error, info};
+use rust_decimal::Decimal;
 use serde::Deserialize;
-use tokio::sync::mpsc::Sender;
+use tokio::sync::{mpsc::Sender, watch};
 
-use chrono::Utc;
+use chrono::{DurationRound, TimeDelta, Utc};
+use std::collections::HashMap;
+use std::str::FromStr;
 use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
 
-use super::{ws_stream::WsStream, Exchange};
-use crate::types::{PriceUpdate, TradingPair};
+use super::depth::{depth_weighted_mid, DepthConfig};
+use super::error::ExchangeError;
+use super::pairs::TrackedPairs;
+use super::remap::SymbolRemaps;
+use super::subscription::SubscriptionBuilder;
+use super::ticks::TickCounter;
+use super::ws_stream::{ConnState, SharedConnState, WsStream, WsStreamConfig};
+use super::{
+    canonical_symbols, heartbeat_is_recent, CanonicalSymbol, Exchange, DEFAULT_HEARTBEAT_TIMEOUT,
+};
+use crate::clock::{system_clock, SharedClock};
+use crate::types::{system_time_from_millis, FundingUpdate, PriceKind, PriceUpdate, TradingPair};
 
 pub struct HyperliquidExchange {
-    trading_pairs: Vec<TradingPair>,
+    trading_pairs: TrackedPairs,
     last_heartbeat: AtomicI64,
+    ticks: TickCounter,
+    ws_state: SharedConnState,
+    remaps: SymbolRemaps,
+    clock: SharedClock,
+    // Longest gap between messages before `is_healthy` fails
+    heartbeat_timeout: Duration,
+    depth: Option<DepthConfig>,
+    // Set when funding rates should be streamed alongside the mids
+    funding_sender: Option<Sender<FundingUpdate>>,
 }
 
 impl Clone for HyperliquidExchange {
@@ -20,6 +43,13 @@ impl Clone for HyperliquidExchange {
         Self {
             trading_pairs: self.trading_pairs.clone(),
             last_heartbeat: AtomicI64::new(self.last_heartbeat.load(Ordering::SeqCst)),
+            ticks: self.ticks.clone(),
+            ws_state: self.ws_state.clone(),
+            remaps: self.remaps.clone(),
+            clock: self.clock.clone(),
+            heartbeat_timeout: self.heartbeat_timeout,
+            depth: self.depth,
+            funding_sender: self.funding_sender.clone(),
         }
     }
 }
@@ -32,17 +62,109 @@ struct HyperliquidMessage {
 
 #[derive(Debug, Deserialize)]
 struct HyperliquidData {
-    mids: std::collections::HashMap<String, String>,
+    mids: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HyperliquidAssetCtxMessage {
+    channel: String,
+    data: HyperliquidAssetCtx,
+}
+
+#[derive(Debug, Deserialize)]
+struct HyperliquidAssetCtx {
+    coin: String,
+    ctx: HyperliquidPerpCtx,
+}
+
+#[derive(Debug, Deserialize)]
+struct HyperliquidPerpCtx {
+    funding: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HyperliquidBookMessage {
+    channel: String,
+    data: HyperliquidBook,
+}
+
+#[derive(Debug, Deserialize)]
+struct HyperliquidBook {
+    coin: String,
+    time: Option<i64>,
+    // [bids, asks], each ordered best-first
+    levels: Vec<Vec<HyperliquidLevel>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HyperliquidLevel {
+    px: String,
+    sz: String,
+}
+
+fn parse_book_side(levels: Option<&Vec<HyperliquidLevel>>) -> Vec<(Decimal, Decimal)> {
+    levels
+        .into_iter()
+        .flatten()
+        .filter_map(|level| {
+            Some((
+                Decimal::from_str(&level.px).ok()?,
+                Decimal::from_str(&level.sz).ok()?,
+            ))
+        })
+        .collect()
 }
 
 impl HyperliquidExchange {
     pub fn new(trading_pairs: Vec<TradingPair>) -> Self {
         Self {
-            trading_pairs,
+            trading_pairs: TrackedPairs::new(trading_pairs),
             last_heartbeat: AtomicI64::new(Utc::now().timestamp()),
+            ticks: TickCounter::new(),
+            ws_state: SharedConnState::default(),
+            remaps: SymbolRemaps::default(),
+            clock: system_clock(),
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+            depth: None,
+            funding_sender: None,
         }
     }
 
+    /// How long the feed may go without a message before it is unhealthy.
+    pub fn with_heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    /// Time heartbeats and tick windows by `clock` instead of the system
+    /// clock.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.last_heartbeat = AtomicI64::new(clock.timestamp());
+        self.ticks = TickCounter::with_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    /// Translate assets Hyperliquid lists under its own tickers.
+    pub fn with_symbol_remaps(mut self, remaps: SymbolRemaps) -> Self {
+        self.remaps = remaps;
+        self
+    }
+
+    /// Switch from allMids to depth-weighted pricing over per-coin l2Book
+    /// snapshots.
+    pub fn with_depth_weighting(mut self, depth: DepthConfig) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    /// Also subscribe to each coin's perp context and send its funding rate
+    /// to `sender`. The price feed is unaffected.
+    pub fn with_funding(mut self, sender: Sender<FundingUpdate>) -> Self {
+        self.funding_sender = Some(sender);
+        self
+    }
+
     fn get_websocket_url(&self) -> String {
         "wss://api.hyperliquid.xyz/ws".to_string()
     }
@@ -57,9 +179,80 @@ impl HyperliquidExchange {
         .to_string()
     }
 
+    fn coins(&self) -> SubscriptionBuilder {
+        SubscriptionBuilder::new(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.base.clone()
+        })
+    }
+
+    // One subscription of `kind` per coin, for the feeds that aren't global
+    fn create_per_coin_subscription_messages(&self, kind: &str) -> Vec<String> {
+        self.coins().per_symbol(|coin| {
+            serde_json::json!({
+                "method": "subscribe",
+                "subscription": {
+                    "type": kind,
+                    "coin": coin,
+                }
+            })
+        })
+    }
+
+    // Hyperliquid settles funding on the hour
+    fn next_funding_time() -> std::time::SystemTime {
+        let now = Utc::now();
+        let next = now
+            .duration_trunc(TimeDelta::hours(1))
+            .map(|hour| hour + TimeDelta::hours(1))
+            .unwrap_or(now);
+        next.into()
+    }
+
+    async fn handle_asset_ctx(
+        &self,
+        ctx: HyperliquidAssetCtx,
+        symbols: &HashMap<String, CanonicalSymbol>,
+    ) -> Result<(), ExchangeError> {
+        let (Some(sender), Some(canonical)) = (&self.funding_sender, symbols.get(&ctx.coin)) else {
+            return Ok(());
+        };
+        let Ok(funding_rate) = Decimal::from_str(&ctx.ctx.funding) else {
+            return Ok(());
+        };
+
+        // Funding is a fraction of notional, so remapped coins need no scaling
+        let update = FundingUpdate {
+            symbol: canonical.symbol.clone(),
+            funding_rate,
+            next_funding_ts: Self::next_funding_time(),
+            source: "hyperliquid".to_string(),
+        };
+        if let Err(e) = sender.send(update).await {
+            error!("Failed to send funding update: {}", e);
+            return Err(ExchangeError::ChannelClosed);
+        }
+        self.update_heartbeat();
+        Ok(())
+    }
+
+    fn is_subscription_ack(text: &str) -> Result<bool, ExchangeError> {
+        Ok(serde_json::from_str::<serde_json::Value>(text)
+            .is_ok_and(|message| message["channel"] == "subscriptionResponse"))
+    }
+
+    // Subscription replies and pongs, which carry no prices
+    fn is_control_message(text: &str) -> bool {
+        serde_json::from_str::<serde_json::Value>(text).is_ok_and(|message| {
+            matches!(
+                message["channel"].as_str(),
+                Some("subscriptionResponse" | "pong")
+            )
+        })
+    }
+
     fn update_heartbeat(&self) {
         self.last_heartbeat
-            .store(Utc::now().timestamp(), Ordering::SeqCst);
+            .store(self.clock.timestamp(), Ordering::SeqCst);
     }
 }
 
@@ -70,48 +263,128 @@ impl Exchange for HyperliquidExchange {
         Ok(())
     }
 
-    async fn listen(&self, price_sender: Sender<PriceUpdate>) -> Result<()> {
-        let mut ws = WsStream::connect(&self.get_websocket_url()).await?;
-        info!("Connected to Hyperliquid WebSocket");
+    async fn listen(
+        &self,
+        price_sender: Sender<PriceUpdate>,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> Result<(), ExchangeError> {
+        let mut subscription_msgs = if self.depth.is_some() {
+            self.create_per_coin_subscription_messages("l2Book")
+        } else {
+            vec![self.create_subscription_message()]
+        };
+        if self.funding_sender.is_some() {
+            subscription_msgs.extend(self.create_per_coin_subscription_messages("activeAssetCtx"));
+        }
+        let mut ws = WsStream::connect_and_subscribe_with(
+            &self.get_websocket_url(),
+            WsStreamConfig {
+                state: self.ws_state.clone(),
+                ..Default::default()
+            },
+            subscription_msgs.clone(),
+            Self::is_subscription_ack,
+        )
+        .await?;
+        info!("Subscribed to Hyperliquid: {:?}", subscription_msgs);
 
-        // Send subscription message
-        let subscription_msg = self.create_subscription_message();
-        ws.send_text(subscription_msg.clone()).await?;
-        info!(
-            "Sent subscription message to Hyperliquid: {}",
-            subscription_msg
-        );
+        // Hyperliquid quotes by bare coin name, e.g. "BTC". allMids carries
+        // every listed coin, so anything not in this map is dropped here
+        let symbols = canonical_symbols(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.base.clone()
+        });
 
         self.update_heartbeat();
 
-        while let Some(text) = ws.read_text().await? {
+        while let Some(text) = ws.read_text_or_shutdown(&mut shutdown).await? {
             if let Ok(message) = serde_json::from_str::<HyperliquidMessage>(&text) {
+                ws.record_decoded();
                 if message.channel == "allMids" {
-                    for (symbol, price_str) in message.data.mids {
-                        if let Ok(price) = price_str.parse::<f64>() {
+                    for (coin, price_str) in message.data.mids {
+                        let canonical = match symbols.get(&coin) {
+                            Some(canonical) => canonical,
+                            None => continue,
+                        };
+                        if let Ok(price) = Decimal::from_str(&price_str) {
                             let update = PriceUpdate {
-                                symbol,
+                                symbol: canonical.symbol.clone(),
                                 price,
                                 timestamp: Utc::now().into(),
                                 source: "hyperliquid".to_string(),
-                            };
+                                kind: PriceKind::TopOfBook,
+                                bid: None,
+                                ask: None,
+                                exchange_timestamp: None,
+                            }
+                            .scaled(canonical.price_scale);
 
                             if let Err(e) = price_sender.send(update).await {
                                 error!("Failed to send price update: {}", e);
-                                return Err(anyhow!("Channel closed"));
+                                return Err(ExchangeError::ChannelClosed);
                             }
 
                             self.update_heartbeat();
+                            self.ticks.record();
+                        }
+                    }
+                }
+            } else if let Ok(message) = serde_json::from_str::<HyperliquidAssetCtxMessage>(&text) {
+                ws.record_decoded();
+                if message.channel == "activeAssetCtx" {
+                    self.handle_asset_ctx(message.data, &symbols).await?;
+                }
+            } else if let (Some(depth), Ok(message)) = (
+                &self.depth,
+                serde_json::from_str::<HyperliquidBookMessage>(&text),
+            ) {
+                ws.record_decoded();
+                if message.channel == "l2Book" {
+                    let book = message.data;
+                    let canonical = match symbols.get(&book.coin) {
+                        Some(canonical) => canonical,
+                        None => continue,
+                    };
+                    let bids = parse_book_side(book.levels.first());
+                    let asks = parse_book_side(book.levels.get(1));
+
+                    if let Some(price) = depth_weighted_mid(&bids, &asks, depth) {
+                        let update = PriceUpdate {
+                            symbol: canonical.symbol.clone(),
+                            price,
+                            timestamp: Utc::now().into(),
+                            source: "hyperliquid".to_string(),
+                            kind: PriceKind::DepthWeighted,
+                            bid: bids.first().map(|(price, _)| *price),
+                            ask: asks.first().map(|(price, _)| *price),
+                            exchange_timestamp: book.time.map(system_time_from_millis),
+                        }
+                        .scaled(canonical.price_scale);
+
+                        if let Err(e) = price_sender.send(update).await {
+                            error!("Failed to send price update: {}", e);
+                            return Err(ExchangeError::ChannelClosed);
                         }
+
+                        self.update_heartbeat();
+                        self.ticks.record();
                     }
                 }
+            } else if Self::is_control_message(&text) {
+                ws.record_decoded();
+            } else {
+                ws.record_decode_failure(&text)?;
             }
         }
 
-        Err(anyhow!("WebSocket stream ended"))
+        if *shutdown.borrow() {
+            ws.close().await?;
+            return Ok(());
+        }
+
+        Err(ExchangeError::StreamEnded)
     }
 
-    fn get_trading_pairs(&self) -> &[TradingPair] {
+    fn tracked_pairs(&self) -> &TrackedPairs {
         &self.trading_pairs
     }
 
@@ -119,9 +392,54 @@ impl Exchange for HyperliquidExchange {
         "hyperliquid"
     }
 
+    async fn fetch_snapshot(&self) -> Result<Vec<PriceUpdate>> {
+        let symbols = canonical_symbols(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.base.clone()
+        });
+
+        let mids: HashMap<String, String> = reqwest::Client::new()
+            .post("https://api.hyperliquid.xyz/info")
+            .json(&serde_json::json!({ "type": "allMids" }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let mut updates = Vec::new();
+        for (coin, price_str) in mids {
+            if let (Some(canonical), Ok(price)) =
+                (symbols.get(&coin), Decimal::from_str(&price_str))
+            {
+                updates.push(
+                    PriceUpdate {
+                        symbol: canonical.symbol.clone(),
+                        price,
+                        timestamp: Utc::now().into(),
+                        source: "hyperliquid".to_string(),
+                        kind: PriceKind::TopOfBook,
+                        bid: None,
+                        ask: None,
+                        exchange_timestamp: None,
+                    }
+                    .scaled(canonical.price_scale),
+                );
+            }
+        }
+        Ok(updates)
+    }
+
     async fn is_healthy(&self) -> bool {
-        let last = self.last_heartbeat.load(Ordering::SeqCst);
-        let age = Utc::now().timestamp() - last;
-        age < 10
+        // A live socket isn't enough; prices must actually be arriving
+        heartbeat_is_recent(&self.last_heartbeat, &self.clock, self.heartbeat_timeout)
+            && self.ticks.is_active()
+    }
+
+    fn ticks_last_interval(&self) -> u64 {
+        self.ticks.last_interval()
+    }
+
+    fn ws_state(&self) -> ConnState {
+        self.ws_state.get()
     }
 }
@@ -0,0 +1,259 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use log::{error, info};
+use rust_decimal::Decimal;
+use serde::{de::IgnoredAny, Deserialize};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+use tokio::sync::{mpsc::Sender, watch};
+
+use super::error::ExchangeError;
+use super::pairs::TrackedPairs;
+use super::remap::SymbolRemaps;
+use super::subscription::SubscriptionBuilder;
+use super::ticks::TickCounter;
+use super::ws_stream::{ConnState, SharedConnState, WsStream, WsStreamConfig};
+use super::{canonical_symbols, heartbeat_is_recent, Exchange, DEFAULT_HEARTBEAT_TIMEOUT};
+use crate::clock::{system_clock, SharedClock};
+use crate::types::{PriceKind, PriceMethod, PriceUpdate, TradingPair};
+
+pub struct KrakenExchange {
+    trading_pairs: TrackedPairs,
+    last_heartbeat: AtomicI64,
+    ticks: TickCounter,
+    ws_state: SharedConnState,
+    remaps: SymbolRemaps,
+    clock: SharedClock,
+    // Longest gap between messages before `is_healthy` fails
+    heartbeat_timeout: Duration,
+    price_method: PriceMethod,
+}
+
+impl Clone for KrakenExchange {
+    fn clone(&self) -> Self {
+        Self {
+            trading_pairs: self.trading_pairs.clone(),
+            last_heartbeat: AtomicI64::new(self.last_heartbeat.load(Ordering::SeqCst)),
+            ticks: self.ticks.clone(),
+            ws_state: self.ws_state.clone(),
+            remaps: self.remaps.clone(),
+            clock: self.clock.clone(),
+            heartbeat_timeout: self.heartbeat_timeout,
+            price_method: self.price_method,
+        }
+    }
+}
+
+// Ticker updates arrive as arrays: [channel_id, data, channel_name, pair]
+#[derive(Debug, Deserialize)]
+struct KrakenTicker(IgnoredAny, KrakenTickerData, IgnoredAny, String);
+
+#[derive(Debug, Deserialize)]
+struct KrakenTickerData {
+    // [price, whole lot volume, lot volume]
+    #[serde(rename = "b")]
+    best_bid: Vec<String>,
+    #[serde(rename = "a")]
+    best_ask: Vec<String>,
+}
+
+impl KrakenExchange {
+    pub fn new(trading_pairs: Vec<TradingPair>) -> Self {
+        Self {
+            trading_pairs: TrackedPairs::new(trading_pairs),
+            last_heartbeat: AtomicI64::new(Utc::now().timestamp()),
+            ticks: TickCounter::new(),
+            ws_state: SharedConnState::default(),
+            remaps: SymbolRemaps::default(),
+            clock: system_clock(),
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+            price_method: PriceMethod::default(),
+        }
+    }
+
+    /// How long the feed may go without a message before it is unhealthy.
+    pub fn with_heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    /// Time heartbeats and tick windows by `clock` instead of the system
+    /// clock.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.last_heartbeat = AtomicI64::new(clock.timestamp());
+        self.ticks = TickCounter::with_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    /// Translate assets Kraken lists under its own tickers.
+    pub fn with_symbol_remaps(mut self, remaps: SymbolRemaps) -> Self {
+        self.remaps = remaps;
+        self
+    }
+
+    /// How to price the top of book; the mid unless set.
+    pub fn with_price_method(mut self, price_method: PriceMethod) -> Self {
+        self.price_method = price_method;
+        self
+    }
+
+    fn get_websocket_url(&self) -> String {
+        "wss://ws.kraken.com".to_string()
+    }
+
+    fn create_subscription_message(&self) -> String {
+        SubscriptionBuilder::new(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_kraken_symbol()
+        })
+        .batched(|pairs| {
+            serde_json::json!({
+                "event": "subscribe",
+                "pair": pairs,
+                "subscription": {
+                    "name": "ticker"
+                }
+            })
+        })
+    }
+
+    // One subscriptionStatus per pair; the first successful one will do
+    fn is_subscription_ack(text: &str) -> Result<bool, ExchangeError> {
+        Ok(
+            serde_json::from_str::<serde_json::Value>(text).is_ok_and(|message| {
+                message["event"] == "subscriptionStatus" && message["status"] == "subscribed"
+            }),
+        )
+    }
+
+    // heartbeat, systemStatus, subscriptionStatus and the like
+    fn is_event(text: &str) -> bool {
+        serde_json::from_str::<serde_json::Value>(text)
+            .is_ok_and(|message| message.get("event").is_some())
+    }
+
+    fn update_heartbeat(&self) {
+        self.last_heartbeat
+            .store(self.clock.timestamp(), Ordering::SeqCst);
+    }
+}
+
+#[async_trait]
+impl Exchange for KrakenExchange {
+    async fn init(&mut self) -> Result<()> {
+        // Kraken doesn't require initialization
+        Ok(())
+    }
+
+    async fn listen(
+        &self,
+        price_sender: Sender<PriceUpdate>,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> Result<(), ExchangeError> {
+        let subscription_msg = self.create_subscription_message();
+        let mut ws = WsStream::connect_and_subscribe_with(
+            &self.get_websocket_url(),
+            WsStreamConfig {
+                state: self.ws_state.clone(),
+                ..Default::default()
+            },
+            vec![subscription_msg.clone()],
+            Self::is_subscription_ack,
+        )
+        .await?;
+        info!("Subscribed to Kraken: {}", subscription_msg);
+
+        let symbols = canonical_symbols(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_kraken_symbol()
+        });
+
+        self.update_heartbeat();
+
+        while let Some(text) = ws.read_text_or_shutdown(&mut shutdown).await? {
+            // Event messages (heartbeat, systemStatus, subscriptionStatus) are objects
+            // and won't match the ticker array layout
+            if let Ok(KrakenTicker(_, data, _, pair)) = serde_json::from_str::<KrakenTicker>(&text)
+            {
+                ws.record_decoded();
+                if let (Some(best_bid), Some(best_ask)) = (
+                    data.best_bid
+                        .first()
+                        .and_then(|bid| Decimal::from_str(bid).ok()),
+                    data.best_ask
+                        .first()
+                        .and_then(|ask| Decimal::from_str(ask).ok()),
+                ) {
+                    // Lot volume, the last field, is the size at the best price
+                    let sizes = data
+                        .best_bid
+                        .last()
+                        .and_then(|size| Decimal::from_str(size).ok())
+                        .zip(
+                            data.best_ask
+                                .last()
+                                .and_then(|size| Decimal::from_str(size).ok()),
+                        );
+                    let canonical = match symbols.get(&pair) {
+                        Some(canonical) => canonical,
+                        None => continue,
+                    };
+
+                    let update = PriceUpdate {
+                        symbol: canonical.symbol.clone(),
+                        price: self.price_method.price(best_bid, best_ask, sizes),
+                        timestamp: Utc::now().into(),
+                        source: "kraken".to_string(),
+                        kind: PriceKind::TopOfBook,
+                        bid: Some(best_bid),
+                        ask: Some(best_ask),
+                        exchange_timestamp: None,
+                    }
+                    .scaled(canonical.price_scale);
+
+                    if let Err(e) = price_sender.send(update).await {
+                        error!("Failed to send price update: {}", e);
+                        return Err(ExchangeError::ChannelClosed);
+                    }
+
+                    self.update_heartbeat();
+                    self.ticks.record();
+                }
+            } else if Self::is_event(&text) {
+                ws.record_decoded();
+            } else {
+                ws.record_decode_failure(&text)?;
+            }
+        }
+
+        if *shutdown.borrow() {
+            ws.close().await?;
+            return Ok(());
+        }
+
+        Err(ExchangeError::StreamEnded)
+    }
+
+    fn tracked_pairs(&self) -> &TrackedPairs {
+        &self.trading_pairs
+    }
+
+    fn get_name(&self) -> &'static str {
+        "kraken"
+    }
+
+    async fn is_healthy(&self) -> bool {
+        // A live socket isn't enough; prices must actually be arriving
+        heartbeat_is_recent(&self.last_heartbeat, &self.clock, self.heartbeat_timeout)
+            && self.ticks.is_active()
+    }
+
+    fn ticks_last_interval(&self) -> u64 {
+        self.ticks.last_interval()
+    }
+
+    fn ws_state(&self) -> ConnState {
+        self.ws_state.get()
+    }
+}
@@ -0,0 +1,397 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use log::{error, info, warn};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc::Sender, watch};
+use tokio::time::{interval, Duration};
+
+use super::error::ExchangeError;
+use super::pairs::TrackedPairs;
+use super::remap::SymbolRemaps;
+use super::subscription::SubscriptionBuilder;
+use super::ticks::TickCounter;
+use super::ws_stream::{ConnState, SharedConnState, WsStream, WsStreamConfig};
+use super::{canonical_symbols, heartbeat_is_recent, Exchange, DEFAULT_HEARTBEAT_TIMEOUT};
+use crate::clock::{system_clock, SharedClock};
+use crate::types::{system_time_from_millis, PriceKind, PriceMethod, PriceUpdate, TradingPair};
+
+const KUCOIN_API_URL: &str = "https://api.kucoin.com";
+
+pub struct KucoinExchange {
+    trading_pairs: TrackedPairs,
+    last_heartbeat: AtomicI64,
+    ticks: TickCounter,
+    ws_state: SharedConnState,
+    remaps: SymbolRemaps,
+    clock: SharedClock,
+    // Longest gap between messages before `is_healthy` fails
+    heartbeat_timeout: Duration,
+    price_method: PriceMethod,
+    // Negotiated in init() and used by the first connection; tokens are
+    // single-use, so reconnects negotiate a fresh one
+    bullet: Arc<Mutex<Option<KucoinBullet>>>,
+}
+
+impl Clone for KucoinExchange {
+    fn clone(&self) -> Self {
+        Self {
+            trading_pairs: self.trading_pairs.clone(),
+            last_heartbeat: AtomicI64::new(self.last_heartbeat.load(Ordering::SeqCst)),
+            ticks: self.ticks.clone(),
+            ws_state: self.ws_state.clone(),
+            remaps: self.remaps.clone(),
+            clock: self.clock.clone(),
+            heartbeat_timeout: self.heartbeat_timeout,
+            price_method: self.price_method,
+            bullet: self.bullet.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct KucoinResponse<T> {
+    code: String,
+    data: T,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KucoinBullet {
+    token: String,
+    instance_servers: Vec<KucoinInstanceServer>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KucoinInstanceServer {
+    endpoint: String,
+    // Milliseconds
+    ping_interval: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct KucoinMessage {
+    #[serde(rename = "type")]
+    message_type: String,
+    topic: Option<String>,
+    data: Option<KucoinTicker>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KucoinTicker {
+    best_bid: String,
+    best_bid_size: Option<String>,
+    best_ask: String,
+    best_ask_size: Option<String>,
+    #[serde(rename = "Time")]
+    time: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KucoinAllTickers {
+    ticker: Vec<KucoinRestTicker>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KucoinRestTicker {
+    symbol: String,
+    buy: Option<String>,
+    sell: Option<String>,
+}
+
+fn top_of_book(best_bid: &str, best_ask: &str) -> Option<(Decimal, Decimal)> {
+    Some((
+        Decimal::from_str(best_bid).ok()?,
+        Decimal::from_str(best_ask).ok()?,
+    ))
+}
+
+impl KucoinExchange {
+    pub fn new(trading_pairs: Vec<TradingPair>) -> Self {
+        Self {
+            trading_pairs: TrackedPairs::new(trading_pairs),
+            last_heartbeat: AtomicI64::new(Utc::now().timestamp()),
+            ticks: TickCounter::new(),
+            ws_state: SharedConnState::default(),
+            remaps: SymbolRemaps::default(),
+            clock: system_clock(),
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+            price_method: PriceMethod::default(),
+            bullet: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// How long the feed may go without a message before it is unhealthy.
+    pub fn with_heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    /// Time heartbeats and tick windows by `clock` instead of the system
+    /// clock.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.last_heartbeat = AtomicI64::new(clock.timestamp());
+        self.ticks = TickCounter::with_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    /// Translate assets KuCoin lists under its own tickers.
+    pub fn with_symbol_remaps(mut self, remaps: SymbolRemaps) -> Self {
+        self.remaps = remaps;
+        self
+    }
+
+    /// How to price the top of book; the mid unless set.
+    pub fn with_price_method(mut self, price_method: PriceMethod) -> Self {
+        self.price_method = price_method;
+        self
+    }
+
+    // KuCoin hands out the websocket endpoint and a connect token over REST
+    async fn negotiate_bullet() -> Result<KucoinBullet> {
+        let response: KucoinResponse<KucoinBullet> = reqwest::Client::new()
+            .post(format!("{}/api/v1/bullet-public", KUCOIN_API_URL))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if response.code != "200000" {
+            return Err(anyhow!(
+                "KuCoin bullet-public failed: code {}",
+                response.code
+            ));
+        }
+        Ok(response.data)
+    }
+
+    fn create_subscription_message(&self) -> String {
+        SubscriptionBuilder::new(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_kucoin_symbol()
+        })
+        .batched(|symbols| {
+            serde_json::json!({
+                "id": Utc::now().timestamp_millis().to_string(),
+                "type": "subscribe",
+                "topic": format!("/market/ticker:{}", symbols.join(",")),
+                "privateChannel": false,
+                "response": true
+            })
+        })
+    }
+
+    fn is_subscription_ack(text: &str) -> Result<bool, ExchangeError> {
+        Ok(serde_json::from_str::<KucoinMessage>(text)
+            .is_ok_and(|message| message.message_type == "ack"))
+    }
+
+    fn create_ping_message() -> String {
+        serde_json::json!({
+            "id": Utc::now().timestamp_millis().to_string(),
+            "type": "ping"
+        })
+        .to_string()
+    }
+
+    fn update_heartbeat(&self) {
+        self.last_heartbeat
+            .store(self.clock.timestamp(), Ordering::SeqCst);
+    }
+}
+
+#[async_trait]
+impl Exchange for KucoinExchange {
+    async fn init(&mut self) -> Result<()> {
+        let bullet = Self::negotiate_bullet().await?;
+        info!("Negotiated KuCoin websocket token");
+        *self.bullet.lock().unwrap() = Some(bullet);
+        Ok(())
+    }
+
+    async fn listen(
+        &self,
+        price_sender: Sender<PriceUpdate>,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> Result<(), ExchangeError> {
+        let stored = self.bullet.lock().unwrap().take();
+        let bullet = match stored {
+            Some(bullet) => bullet,
+            None => Self::negotiate_bullet().await?,
+        };
+        let server = bullet
+            .instance_servers
+            .first()
+            .ok_or_else(|| anyhow!("KuCoin returned no websocket servers"))?;
+
+        let url = format!(
+            "{}?token={}&connectId={}",
+            server.endpoint,
+            bullet.token,
+            Utc::now().timestamp_millis()
+        );
+        let subscription_msg = self.create_subscription_message();
+        let mut ws = WsStream::connect_and_subscribe_with(
+            &url,
+            WsStreamConfig {
+                state: self.ws_state.clone(),
+                ..Default::default()
+            },
+            vec![subscription_msg.clone()],
+            Self::is_subscription_ack,
+        )
+        .await?;
+        info!("Subscribed to KuCoin: {}", subscription_msg);
+
+        let symbols = canonical_symbols(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_kucoin_symbol()
+        });
+
+        self.update_heartbeat();
+
+        // KuCoin closes connections that don't ping within the server's interval
+        let mut ping_interval = interval(Duration::from_millis(server.ping_interval));
+        ping_interval.tick().await;
+
+        loop {
+            let text = tokio::select! {
+                _ = ping_interval.tick() => {
+                    ws.send_text(Self::create_ping_message()).await?;
+                    continue;
+                }
+                text = ws.read_text_or_shutdown(&mut shutdown) => match text? {
+                    Some(text) => text,
+                    None => break,
+                },
+            };
+
+            let message = match serde_json::from_str::<KucoinMessage>(&text) {
+                Ok(message) => message,
+                Err(_) => {
+                    ws.record_decode_failure(&text)?;
+                    continue;
+                }
+            };
+            ws.record_decoded();
+
+            match message.message_type.as_str() {
+                "welcome" | "ack" | "pong" => self.update_heartbeat(),
+                "error" => warn!("KuCoin error message: {}", text),
+                "message" => {
+                    let (Some(topic), Some(ticker)) = (message.topic, message.data) else {
+                        continue;
+                    };
+                    let native = topic.strip_prefix("/market/ticker:").unwrap_or(&topic);
+                    let canonical = match symbols.get(native) {
+                        Some(canonical) => canonical,
+                        None => continue,
+                    };
+                    if let Some((best_bid, best_ask)) =
+                        top_of_book(&ticker.best_bid, &ticker.best_ask)
+                    {
+                        let sizes = match (&ticker.best_bid_size, &ticker.best_ask_size) {
+                            (Some(bid_size), Some(ask_size)) => top_of_book(bid_size, ask_size),
+                            _ => None,
+                        };
+                        let update = PriceUpdate {
+                            symbol: canonical.symbol.clone(),
+                            price: self.price_method.price(best_bid, best_ask, sizes),
+                            timestamp: Utc::now().into(),
+                            source: "kucoin".to_string(),
+                            kind: PriceKind::TopOfBook,
+                            bid: Some(best_bid),
+                            ask: Some(best_ask),
+                            exchange_timestamp: ticker.time.map(system_time_from_millis),
+                        }
+                        .scaled(canonical.price_scale);
+
+                        if let Err(e) = price_sender.send(update).await {
+                            error!("Failed to send price update: {}", e);
+                            return Err(ExchangeError::ChannelClosed);
+                        }
+
+                        self.update_heartbeat();
+                        self.ticks.record();
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if *shutdown.borrow() {
+            ws.close().await?;
+            return Ok(());
+        }
+
+        Err(ExchangeError::StreamEnded)
+    }
+
+    fn tracked_pairs(&self) -> &TrackedPairs {
+        &self.trading_pairs
+    }
+
+    fn get_name(&self) -> &'static str {
+        "kucoin"
+    }
+
+    async fn fetch_snapshot(&self) -> Result<Vec<PriceUpdate>> {
+        let symbols = canonical_symbols(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_kucoin_symbol()
+        });
+
+        let response: KucoinResponse<KucoinAllTickers> = reqwest::Client::new()
+            .get(format!("{}/api/v1/market/allTickers", KUCOIN_API_URL))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let mut updates = Vec::new();
+        for ticker in response.data.ticker {
+            let book = match (&ticker.buy, &ticker.sell) {
+                (Some(buy), Some(sell)) => top_of_book(buy, sell),
+                _ => None,
+            };
+            if let (Some(canonical), Some((best_bid, best_ask))) =
+                (symbols.get(&ticker.symbol), book)
+            {
+                updates.push(
+                    PriceUpdate {
+                        symbol: canonical.symbol.clone(),
+                        price: self.price_method.price(best_bid, best_ask, None),
+                        timestamp: Utc::now().into(),
+                        source: "kucoin".to_string(),
+                        kind: PriceKind::TopOfBook,
+                        bid: Some(best_bid),
+                        ask: Some(best_ask),
+                        exchange_timestamp: None,
+                    }
+                    .scaled(canonical.price_scale),
+                );
+            }
+        }
+        Ok(updates)
+    }
+
+    async fn is_healthy(&self) -> bool {
+        // A live socket isn't enough; prices must actually be arriving
+        heartbeat_is_recent(&self.last_heartbeat, &self.clock, self.heartbeat_timeout)
+            && self.ticks.is_active()
+    }
+
+    fn ticks_last_interval(&self) -> u64 {
+        self.ticks.last_interval()
+    }
+
+    fn ws_state(&self) -> ConnState {
+        self.ws_state.get()
+    }
+}
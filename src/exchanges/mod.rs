@@ -1,67 +1,602 @@
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use tokio::sync::mpsc::Sender;
+use log::warn;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+use tokio::sync::{mpsc::Sender, watch};
 
-use crate::types::{PriceUpdate, TradingPair};
+use crate::clock::SharedClock;
+use crate::types::{FundingUpdate, PriceMethod, PriceUpdate, TradingPair};
+use error::ExchangeError;
+use pairs::TrackedPairs;
+use remap::SymbolRemaps;
+use ws_stream::ConnState;
 
+#[cfg(feature = "binance")]
 pub mod binance;
+#[cfg(feature = "bitstamp")]
+pub mod bitstamp;
+#[cfg(feature = "bybit")]
 pub mod bybit;
+#[cfg(feature = "coinbase")]
 pub mod coinbase;
+pub mod depth;
+pub mod error;
+#[cfg(feature = "gateio")]
+pub mod gateio;
+#[cfg(feature = "htx")]
+pub mod htx;
+#[cfg(feature = "hyperliquid")]
 pub mod hyperliquid;
+#[cfg(feature = "kraken")]
+pub mod kraken;
+#[cfg(feature = "kucoin")]
+pub mod kucoin;
+#[cfg(feature = "okx")]
+pub mod okx;
+pub mod pairs;
+pub mod remap;
+pub mod replay;
+pub mod subscription;
+pub mod ticks;
 pub mod ws_stream;
 
+#[cfg(not(any(
+    feature = "binance",
+    feature = "bitstamp",
+    feature = "bybit",
+    feature = "coinbase",
+    feature = "gateio",
+    feature = "htx",
+    feature = "hyperliquid",
+    feature = "kraken",
+    feature = "kucoin",
+    feature = "okx"
+)))]
+compile_error!("enable at least one exchange feature, e.g. --features binance");
+
 #[derive(Clone)]
 pub enum ExchangeImpl {
+    #[cfg(feature = "binance")]
     Binance(binance::BinanceExchange),
+    #[cfg(feature = "bitstamp")]
+    Bitstamp(bitstamp::BitstampExchange),
+    #[cfg(feature = "bybit")]
     Bybit(bybit::BybitExchange),
+    #[cfg(feature = "coinbase")]
     Coinbase(coinbase::CoinbaseExchange),
+    #[cfg(feature = "gateio")]
+    Gateio(gateio::GateioExchange),
+    #[cfg(feature = "htx")]
+    Htx(htx::HtxExchange),
+    #[cfg(feature = "hyperliquid")]
     Hyperliquid(hyperliquid::HyperliquidExchange),
+    #[cfg(feature = "kraken")]
+    Kraken(kraken::KrakenExchange),
+    #[cfg(feature = "kucoin")]
+    KuCoin(kucoin::KucoinExchange),
+    #[cfg(feature = "okx")]
+    Okx(okx::OkxExchange),
+}
+
+// Canonical symbol behind an exchange-native one
+#[derive(Debug, Clone)]
+pub struct CanonicalSymbol {
+    pub symbol: String,
+    // Multiplies the exchange's prices into canonical units; 1 unless remapped
+    pub price_scale: Decimal,
+}
+
+/// How long a feed may go without a message before `is_healthy` fails,
+/// unless configured.
+pub const DEFAULT_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// True while the last message, stored as a Unix timestamp, is younger
+// than `timeout`
+fn heartbeat_is_recent(last_heartbeat: &AtomicI64, clock: &SharedClock, timeout: Duration) -> bool {
+    let age = clock.timestamp() - last_heartbeat.load(Ordering::SeqCst);
+    age < timeout.as_secs() as i64
+}
+
+/// Maps each pair's exchange-native symbol to its canonical symbol, so
+/// exchanges can translate incoming symbols and drop ones we don't track.
+/// `remaps` swaps in the exchange's own ticker where it differs.
+pub fn canonical_symbols<F>(
+    trading_pairs: &[TradingPair],
+    remaps: &SymbolRemaps,
+    to_native: F,
+) -> HashMap<String, CanonicalSymbol>
+where
+    F: Fn(&TradingPair) -> String,
+{
+    let mut symbols = HashMap::new();
+    for pair in trading_pairs {
+        // First configured pair wins when several share a native symbol
+        symbols
+            .entry(to_native(&remaps.native_pair(pair)))
+            .or_insert_with(|| CanonicalSymbol {
+                symbol: pair.to_canonical_symbol(),
+                price_scale: remaps.price_scale(pair),
+            });
+    }
+    symbols
+}
+
+impl ExchangeImpl {
+    async fn listen_to(
+        &self,
+        price_sender: Sender<PriceUpdate>,
+        shutdown: watch::Receiver<bool>,
+    ) -> Result<(), ExchangeError> {
+        match self {
+            #[cfg(feature = "binance")]
+            ExchangeImpl::Binance(e) => e.listen(price_sender, shutdown).await,
+            #[cfg(feature = "bitstamp")]
+            ExchangeImpl::Bitstamp(e) => e.listen(price_sender, shutdown).await,
+            #[cfg(feature = "bybit")]
+            ExchangeImpl::Bybit(e) => e.listen(price_sender, shutdown).await,
+            #[cfg(feature = "coinbase")]
+            ExchangeImpl::Coinbase(e) => e.listen(price_sender, shutdown).await,
+            #[cfg(feature = "gateio")]
+            ExchangeImpl::Gateio(e) => e.listen(price_sender, shutdown).await,
+            #[cfg(feature = "htx")]
+            ExchangeImpl::Htx(e) => e.listen(price_sender, shutdown).await,
+            #[cfg(feature = "hyperliquid")]
+            ExchangeImpl::Hyperliquid(e) => e.listen(price_sender, shutdown).await,
+            #[cfg(feature = "kraken")]
+            ExchangeImpl::Kraken(e) => e.listen(price_sender, shutdown).await,
+            #[cfg(feature = "kucoin")]
+            ExchangeImpl::KuCoin(e) => e.listen(price_sender, shutdown).await,
+            #[cfg(feature = "okx")]
+            ExchangeImpl::Okx(e) => e.listen(price_sender, shutdown).await,
+        }
+    }
+
+    /// Enables depth-weighted pricing on exchanges whose feeds publish
+    /// several book levels; the rest keep pricing from top of book.
+    #[cfg_attr(
+        not(any(feature = "binance", feature = "bybit", feature = "hyperliquid")),
+        allow(unused_variables)
+    )]
+    pub fn with_depth_weighting(self, depth: depth::DepthConfig) -> Self {
+        match self {
+            #[cfg(feature = "binance")]
+            ExchangeImpl::Binance(e) => ExchangeImpl::Binance(e.with_depth_weighting(depth)),
+            #[cfg(feature = "bybit")]
+            ExchangeImpl::Bybit(e) => ExchangeImpl::Bybit(e.with_depth_weighting(depth)),
+            #[cfg(feature = "hyperliquid")]
+            ExchangeImpl::Hyperliquid(e) => {
+                ExchangeImpl::Hyperliquid(e.with_depth_weighting(depth))
+            }
+            #[allow(unreachable_patterns)]
+            other => {
+                warn!(
+                    "{} does not support depth-weighted pricing, using top of book",
+                    other.get_name()
+                );
+                other
+            }
+        }
+    }
+
+    /// How long the feed may go without a message before it is unhealthy.
+    pub fn with_heartbeat_timeout(self, timeout: Duration) -> Self {
+        match self {
+            #[cfg(feature = "binance")]
+            ExchangeImpl::Binance(e) => ExchangeImpl::Binance(e.with_heartbeat_timeout(timeout)),
+            #[cfg(feature = "bitstamp")]
+            ExchangeImpl::Bitstamp(e) => ExchangeImpl::Bitstamp(e.with_heartbeat_timeout(timeout)),
+            #[cfg(feature = "bybit")]
+            ExchangeImpl::Bybit(e) => ExchangeImpl::Bybit(e.with_heartbeat_timeout(timeout)),
+            #[cfg(feature = "coinbase")]
+            ExchangeImpl::Coinbase(e) => ExchangeImpl::Coinbase(e.with_heartbeat_timeout(timeout)),
+            #[cfg(feature = "gateio")]
+            ExchangeImpl::Gateio(e) => ExchangeImpl::Gateio(e.with_heartbeat_timeout(timeout)),
+            #[cfg(feature = "htx")]
+            ExchangeImpl::Htx(e) => ExchangeImpl::Htx(e.with_heartbeat_timeout(timeout)),
+            #[cfg(feature = "hyperliquid")]
+            ExchangeImpl::Hyperliquid(e) => {
+                ExchangeImpl::Hyperliquid(e.with_heartbeat_timeout(timeout))
+            }
+            #[cfg(feature = "kraken")]
+            ExchangeImpl::Kraken(e) => ExchangeImpl::Kraken(e.with_heartbeat_timeout(timeout)),
+            #[cfg(feature = "kucoin")]
+            ExchangeImpl::KuCoin(e) => ExchangeImpl::KuCoin(e.with_heartbeat_timeout(timeout)),
+            #[cfg(feature = "okx")]
+            ExchangeImpl::Okx(e) => ExchangeImpl::Okx(e.with_heartbeat_timeout(timeout)),
+        }
+    }
+
+    /// Times heartbeats and tick windows by `clock`, e.g. a `MockClock` in
+    /// tests.
+    pub fn with_clock(self, clock: SharedClock) -> Self {
+        match self {
+            #[cfg(feature = "binance")]
+            ExchangeImpl::Binance(e) => ExchangeImpl::Binance(e.with_clock(clock)),
+            #[cfg(feature = "bitstamp")]
+            ExchangeImpl::Bitstamp(e) => ExchangeImpl::Bitstamp(e.with_clock(clock)),
+            #[cfg(feature = "bybit")]
+            ExchangeImpl::Bybit(e) => ExchangeImpl::Bybit(e.with_clock(clock)),
+            #[cfg(feature = "coinbase")]
+            ExchangeImpl::Coinbase(e) => ExchangeImpl::Coinbase(e.with_clock(clock)),
+            #[cfg(feature = "gateio")]
+            ExchangeImpl::Gateio(e) => ExchangeImpl::Gateio(e.with_clock(clock)),
+            #[cfg(feature = "htx")]
+            ExchangeImpl::Htx(e) => ExchangeImpl::Htx(e.with_clock(clock)),
+            #[cfg(feature = "hyperliquid")]
+            ExchangeImpl::Hyperliquid(e) => ExchangeImpl::Hyperliquid(e.with_clock(clock)),
+            #[cfg(feature = "kraken")]
+            ExchangeImpl::Kraken(e) => ExchangeImpl::Kraken(e.with_clock(clock)),
+            #[cfg(feature = "kucoin")]
+            ExchangeImpl::KuCoin(e) => ExchangeImpl::KuCoin(e.with_clock(clock)),
+            #[cfg(feature = "okx")]
+            ExchangeImpl::Okx(e) => ExchangeImpl::Okx(e.with_clock(clock)),
+        }
+    }
+
+    /// Applies the remaps configured for this exchange when translating its
+    /// tickers to canonical symbols.
+    pub fn with_symbol_remaps(self, remaps: &SymbolRemaps) -> Self {
+        use crate::types::Exchange as Venue;
+        match self {
+            #[cfg(feature = "binance")]
+            ExchangeImpl::Binance(e) => {
+                ExchangeImpl::Binance(e.with_symbol_remaps(remaps.for_exchange(Venue::Binance)))
+            }
+            #[cfg(feature = "bitstamp")]
+            ExchangeImpl::Bitstamp(e) => {
+                ExchangeImpl::Bitstamp(e.with_symbol_remaps(remaps.for_exchange(Venue::Bitstamp)))
+            }
+            #[cfg(feature = "bybit")]
+            ExchangeImpl::Bybit(e) => {
+                ExchangeImpl::Bybit(e.with_symbol_remaps(remaps.for_exchange(Venue::Bybit)))
+            }
+            #[cfg(feature = "coinbase")]
+            ExchangeImpl::Coinbase(e) => {
+                ExchangeImpl::Coinbase(e.with_symbol_remaps(remaps.for_exchange(Venue::Coinbase)))
+            }
+            #[cfg(feature = "gateio")]
+            ExchangeImpl::Gateio(e) => {
+                ExchangeImpl::Gateio(e.with_symbol_remaps(remaps.for_exchange(Venue::Gateio)))
+            }
+            #[cfg(feature = "htx")]
+            ExchangeImpl::Htx(e) => {
+                ExchangeImpl::Htx(e.with_symbol_remaps(remaps.for_exchange(Venue::Htx)))
+            }
+            #[cfg(feature = "hyperliquid")]
+            ExchangeImpl::Hyperliquid(e) => ExchangeImpl::Hyperliquid(
+                e.with_symbol_remaps(remaps.for_exchange(Venue::Hyperliquid)),
+            ),
+            #[cfg(feature = "kraken")]
+            ExchangeImpl::Kraken(e) => {
+                ExchangeImpl::Kraken(e.with_symbol_remaps(remaps.for_exchange(Venue::Kraken)))
+            }
+            #[cfg(feature = "kucoin")]
+            ExchangeImpl::KuCoin(e) => {
+                ExchangeImpl::KuCoin(e.with_symbol_remaps(remaps.for_exchange(Venue::KuCoin)))
+            }
+            #[cfg(feature = "okx")]
+            ExchangeImpl::Okx(e) => {
+                ExchangeImpl::Okx(e.with_symbol_remaps(remaps.for_exchange(Venue::Okx)))
+            }
+        }
+    }
+
+    /// Prices top-of-book updates with `price_method` on exchanges whose feeds
+    /// carry a bid and ask; Hyperliquid's allMids only has mids.
+    pub fn with_price_method(self, price_method: PriceMethod) -> Self {
+        match self {
+            #[cfg(feature = "binance")]
+            ExchangeImpl::Binance(e) => ExchangeImpl::Binance(e.with_price_method(price_method)),
+            #[cfg(feature = "bitstamp")]
+            ExchangeImpl::Bitstamp(e) => ExchangeImpl::Bitstamp(e.with_price_method(price_method)),
+            #[cfg(feature = "bybit")]
+            ExchangeImpl::Bybit(e) => ExchangeImpl::Bybit(e.with_price_method(price_method)),
+            #[cfg(feature = "coinbase")]
+            ExchangeImpl::Coinbase(e) => ExchangeImpl::Coinbase(e.with_price_method(price_method)),
+            #[cfg(feature = "gateio")]
+            ExchangeImpl::Gateio(e) => ExchangeImpl::Gateio(e.with_price_method(price_method)),
+            #[cfg(feature = "htx")]
+            ExchangeImpl::Htx(e) => ExchangeImpl::Htx(e.with_price_method(price_method)),
+            #[cfg(feature = "kraken")]
+            ExchangeImpl::Kraken(e) => ExchangeImpl::Kraken(e.with_price_method(price_method)),
+            #[cfg(feature = "kucoin")]
+            ExchangeImpl::KuCoin(e) => ExchangeImpl::KuCoin(e.with_price_method(price_method)),
+            #[cfg(feature = "okx")]
+            ExchangeImpl::Okx(e) => ExchangeImpl::Okx(e.with_price_method(price_method)),
+            #[allow(unreachable_patterns)]
+            other => {
+                if price_method != PriceMethod::Mid {
+                    warn!(
+                        "{} does not support {:?} pricing, using the mid",
+                        other.get_name(),
+                        price_method
+                    );
+                }
+                other
+            }
+        }
+    }
+
+    /// Also subscribes to the trade stream and emits `PriceKind::LastTrade`
+    /// updates on exchanges that support it; the rest are returned unchanged.
+    pub fn with_trades(self) -> Self {
+        match self {
+            #[cfg(feature = "binance")]
+            ExchangeImpl::Binance(e) => ExchangeImpl::Binance(e.with_trades()),
+            #[cfg(feature = "bybit")]
+            ExchangeImpl::Bybit(e) => ExchangeImpl::Bybit(e.with_trades()),
+            #[cfg(feature = "coinbase")]
+            ExchangeImpl::Coinbase(e) => ExchangeImpl::Coinbase(e.with_trades()),
+            #[allow(unreachable_patterns)]
+            other => {
+                warn!("{} does not support trade streams", other.get_name());
+                other
+            }
+        }
+    }
+
+    /// Levels per side of the order book subscription; only Bybit has a
+    /// choice.
+    #[cfg_attr(not(feature = "bybit"), allow(unused_variables))]
+    pub fn with_book_depth(self, depth: u32) -> Self {
+        match self {
+            #[cfg(feature = "bybit")]
+            ExchangeImpl::Bybit(e) => ExchangeImpl::Bybit(e.with_book_depth(depth)),
+            #[allow(unreachable_patterns)]
+            other => other,
+        }
+    }
+
+    /// Fixed prices for pegged pairs whose ticker stays silent, keyed by
+    /// canonical symbol; only Coinbase uses them.
+    #[cfg_attr(not(feature = "coinbase"), allow(unused_variables))]
+    pub fn with_peg_fallbacks(self, peg_fallbacks: &HashMap<String, Decimal>) -> Self {
+        match self {
+            #[cfg(feature = "coinbase")]
+            ExchangeImpl::Coinbase(e) => {
+                ExchangeImpl::Coinbase(e.with_peg_fallbacks(peg_fallbacks.clone()))
+            }
+            #[allow(unreachable_patterns)]
+            other => other,
+        }
+    }
+
+    /// Streams funding rates to `sender` on exchanges that list perps; the
+    /// rest are returned unchanged.
+    #[cfg_attr(not(feature = "hyperliquid"), allow(unused_variables))]
+    pub fn with_funding(self, sender: Sender<FundingUpdate>) -> Self {
+        match self {
+            #[cfg(feature = "hyperliquid")]
+            ExchangeImpl::Hyperliquid(e) => ExchangeImpl::Hyperliquid(e.with_funding(sender)),
+            #[allow(unreachable_patterns)]
+            other => {
+                warn!("{} does not support funding rates", other.get_name());
+                other
+            }
+        }
+    }
 }
 
 #[async_trait]
 impl Exchange for ExchangeImpl {
     async fn init(&mut self) -> Result<()> {
         match self {
+            #[cfg(feature = "binance")]
             ExchangeImpl::Binance(e) => e.init().await,
+            #[cfg(feature = "bitstamp")]
+            ExchangeImpl::Bitstamp(e) => e.init().await,
+            #[cfg(feature = "bybit")]
             ExchangeImpl::Bybit(e) => e.init().await,
+            #[cfg(feature = "coinbase")]
             ExchangeImpl::Coinbase(e) => e.init().await,
+            #[cfg(feature = "gateio")]
+            ExchangeImpl::Gateio(e) => e.init().await,
+            #[cfg(feature = "htx")]
+            ExchangeImpl::Htx(e) => e.init().await,
+            #[cfg(feature = "hyperliquid")]
             ExchangeImpl::Hyperliquid(e) => e.init().await,
+            #[cfg(feature = "kraken")]
+            ExchangeImpl::Kraken(e) => e.init().await,
+            #[cfg(feature = "kucoin")]
+            ExchangeImpl::KuCoin(e) => e.init().await,
+            #[cfg(feature = "okx")]
+            ExchangeImpl::Okx(e) => e.init().await,
         }
     }
 
-    async fn listen(&self, price_sender: Sender<PriceUpdate>) -> Result<()> {
+    async fn listen(
+        &self,
+        price_sender: Sender<PriceUpdate>,
+        shutdown: watch::Receiver<bool>,
+    ) -> Result<(), ExchangeError> {
+        // Exchanges that can't change subscriptions on a live connection
+        // reconnect to pick up new pairs
+        if !self.resubscribes_live() {
+            let mut pairs = self.tracked_pairs().watch();
+            let listen = self.listen_to(price_sender, shutdown);
+            return tokio::select! {
+                result = listen => result,
+                _ = pairs.changed() => Err(ExchangeError::PairsChanged),
+            };
+        }
+        self.listen_to(price_sender, shutdown).await
+    }
+
+    fn tracked_pairs(&self) -> &TrackedPairs {
         match self {
-            ExchangeImpl::Binance(e) => e.listen(price_sender).await,
-            ExchangeImpl::Bybit(e) => e.listen(price_sender).await,
-            ExchangeImpl::Coinbase(e) => e.listen(price_sender).await,
-            ExchangeImpl::Hyperliquid(e) => e.listen(price_sender).await,
+            #[cfg(feature = "binance")]
+            ExchangeImpl::Binance(e) => e.tracked_pairs(),
+            #[cfg(feature = "bitstamp")]
+            ExchangeImpl::Bitstamp(e) => e.tracked_pairs(),
+            #[cfg(feature = "bybit")]
+            ExchangeImpl::Bybit(e) => e.tracked_pairs(),
+            #[cfg(feature = "coinbase")]
+            ExchangeImpl::Coinbase(e) => e.tracked_pairs(),
+            #[cfg(feature = "gateio")]
+            ExchangeImpl::Gateio(e) => e.tracked_pairs(),
+            #[cfg(feature = "htx")]
+            ExchangeImpl::Htx(e) => e.tracked_pairs(),
+            #[cfg(feature = "hyperliquid")]
+            ExchangeImpl::Hyperliquid(e) => e.tracked_pairs(),
+            #[cfg(feature = "kraken")]
+            ExchangeImpl::Kraken(e) => e.tracked_pairs(),
+            #[cfg(feature = "kucoin")]
+            ExchangeImpl::KuCoin(e) => e.tracked_pairs(),
+            #[cfg(feature = "okx")]
+            ExchangeImpl::Okx(e) => e.tracked_pairs(),
         }
     }
 
-    fn get_trading_pairs(&self) -> &[TradingPair] {
+    fn resubscribes_live(&self) -> bool {
         match self {
-            ExchangeImpl::Binance(e) => e.get_trading_pairs(),
-            ExchangeImpl::Bybit(e) => e.get_trading_pairs(),
-            ExchangeImpl::Coinbase(e) => e.get_trading_pairs(),
-            ExchangeImpl::Hyperliquid(e) => e.get_trading_pairs(),
+            #[cfg(feature = "binance")]
+            ExchangeImpl::Binance(e) => e.resubscribes_live(),
+            #[cfg(feature = "bitstamp")]
+            ExchangeImpl::Bitstamp(e) => e.resubscribes_live(),
+            #[cfg(feature = "bybit")]
+            ExchangeImpl::Bybit(e) => e.resubscribes_live(),
+            #[cfg(feature = "coinbase")]
+            ExchangeImpl::Coinbase(e) => e.resubscribes_live(),
+            #[cfg(feature = "gateio")]
+            ExchangeImpl::Gateio(e) => e.resubscribes_live(),
+            #[cfg(feature = "htx")]
+            ExchangeImpl::Htx(e) => e.resubscribes_live(),
+            #[cfg(feature = "hyperliquid")]
+            ExchangeImpl::Hyperliquid(e) => e.resubscribes_live(),
+            #[cfg(feature = "kraken")]
+            ExchangeImpl::Kraken(e) => e.resubscribes_live(),
+            #[cfg(feature = "kucoin")]
+            ExchangeImpl::KuCoin(e) => e.resubscribes_live(),
+            #[cfg(feature = "okx")]
+            ExchangeImpl::Okx(e) => e.resubscribes_live(),
         }
     }
 
     fn get_name(&self) -> &'static str {
         match self {
+            #[cfg(feature = "binance")]
             ExchangeImpl::Binance(e) => e.get_name(),
+            #[cfg(feature = "bitstamp")]
+            ExchangeImpl::Bitstamp(e) => e.get_name(),
+            #[cfg(feature = "bybit")]
             ExchangeImpl::Bybit(e) => e.get_name(),
+            #[cfg(feature = "coinbase")]
             ExchangeImpl::Coinbase(e) => e.get_name(),
+            #[cfg(feature = "gateio")]
+            ExchangeImpl::Gateio(e) => e.get_name(),
+            #[cfg(feature = "htx")]
+            ExchangeImpl::Htx(e) => e.get_name(),
+            #[cfg(feature = "hyperliquid")]
             ExchangeImpl::Hyperliquid(e) => e.get_name(),
+            #[cfg(feature = "kraken")]
+            ExchangeImpl::Kraken(e) => e.get_name(),
+            #[cfg(feature = "kucoin")]
+            ExchangeImpl::KuCoin(e) => e.get_name(),
+            #[cfg(feature = "okx")]
+            ExchangeImpl::Okx(e) => e.get_name(),
         }
     }
 
     async fn is_healthy(&self) -> bool {
         match self {
+            #[cfg(feature = "binance")]
             ExchangeImpl::Binance(e) => e.is_healthy().await,
+            #[cfg(feature = "bitstamp")]
+            ExchangeImpl::Bitstamp(e) => e.is_healthy().await,
+            #[cfg(feature = "bybit")]
             ExchangeImpl::Bybit(e) => e.is_healthy().await,
+            #[cfg(feature = "coinbase")]
             ExchangeImpl::Coinbase(e) => e.is_healthy().await,
+            #[cfg(feature = "gateio")]
+            ExchangeImpl::Gateio(e) => e.is_healthy().await,
+            #[cfg(feature = "htx")]
+            ExchangeImpl::Htx(e) => e.is_healthy().await,
+            #[cfg(feature = "hyperliquid")]
             ExchangeImpl::Hyperliquid(e) => e.is_healthy().await,
+            #[cfg(feature = "kraken")]
+            ExchangeImpl::Kraken(e) => e.is_healthy().await,
+            #[cfg(feature = "kucoin")]
+            ExchangeImpl::KuCoin(e) => e.is_healthy().await,
+            #[cfg(feature = "okx")]
+            ExchangeImpl::Okx(e) => e.is_healthy().await,
+        }
+    }
+
+    fn ticks_last_interval(&self) -> u64 {
+        match self {
+            #[cfg(feature = "binance")]
+            ExchangeImpl::Binance(e) => e.ticks_last_interval(),
+            #[cfg(feature = "bitstamp")]
+            ExchangeImpl::Bitstamp(e) => e.ticks_last_interval(),
+            #[cfg(feature = "bybit")]
+            ExchangeImpl::Bybit(e) => e.ticks_last_interval(),
+            #[cfg(feature = "coinbase")]
+            ExchangeImpl::Coinbase(e) => e.ticks_last_interval(),
+            #[cfg(feature = "gateio")]
+            ExchangeImpl::Gateio(e) => e.ticks_last_interval(),
+            #[cfg(feature = "htx")]
+            ExchangeImpl::Htx(e) => e.ticks_last_interval(),
+            #[cfg(feature = "hyperliquid")]
+            ExchangeImpl::Hyperliquid(e) => e.ticks_last_interval(),
+            #[cfg(feature = "kraken")]
+            ExchangeImpl::Kraken(e) => e.ticks_last_interval(),
+            #[cfg(feature = "kucoin")]
+            ExchangeImpl::KuCoin(e) => e.ticks_last_interval(),
+            #[cfg(feature = "okx")]
+            ExchangeImpl::Okx(e) => e.ticks_last_interval(),
+        }
+    }
+
+    fn ws_state(&self) -> ConnState {
+        match self {
+            #[cfg(feature = "binance")]
+            ExchangeImpl::Binance(e) => e.ws_state(),
+            #[cfg(feature = "bitstamp")]
+            ExchangeImpl::Bitstamp(e) => e.ws_state(),
+            #[cfg(feature = "bybit")]
+            ExchangeImpl::Bybit(e) => e.ws_state(),
+            #[cfg(feature = "coinbase")]
+            ExchangeImpl::Coinbase(e) => e.ws_state(),
+            #[cfg(feature = "gateio")]
+            ExchangeImpl::Gateio(e) => e.ws_state(),
+            #[cfg(feature = "htx")]
+            ExchangeImpl::Htx(e) => e.ws_state(),
+            #[cfg(feature = "hyperliquid")]
+            ExchangeImpl::Hyperliquid(e) => e.ws_state(),
+            #[cfg(feature = "kraken")]
+            ExchangeImpl::Kraken(e) => e.ws_state(),
+            #[cfg(feature = "kucoin")]
+            ExchangeImpl::KuCoin(e) => e.ws_state(),
+            #[cfg(feature = "okx")]
+            ExchangeImpl::Okx(e) => e.ws_state(),
+        }
+    }
+
+    async fn fetch_snapshot(&self) -> Result<Vec<PriceUpdate>> {
+        match self {
+            #[cfg(feature = "binance")]
+            ExchangeImpl::Binance(e) => e.fetch_snapshot().await,
+            #[cfg(feature = "bitstamp")]
+            ExchangeImpl::Bitstamp(e) => e.fetch_snapshot().await,
+            #[cfg(feature = "bybit")]
+            ExchangeImpl::Bybit(e) => e.fetch_snapshot().await,
+            #[cfg(feature = "coinbase")]
+            ExchangeImpl::Coinbase(e) => e.fetch_snapshot().await,
+            #[cfg(feature = "gateio")]
+            ExchangeImpl::Gateio(e) => e.fetch_snapshot().await,
+            #[cfg(feature = "htx")]
+            ExchangeImpl::Htx(e) => e.fetch_snapshot().await,
+            #[cfg(feature = "hyperliquid")]
+            ExchangeImpl::Hyperliquid(e) => e.fetch_snapshot().await,
+            #[cfg(feature = "kraken")]
+            ExchangeImpl::Kraken(e) => e.fetch_snapshot().await,
+            #[cfg(feature = "kucoin")]
+            ExchangeImpl::KuCoin(e) => e.fetch_snapshot().await,
+            #[cfg(feature = "okx")]
+            ExchangeImpl::Okx(e) => e.fetch_snapshot().await,
         }
     }
 }
@@ -69,10 +604,38 @@ impl Exchange for ExchangeImpl {
 #[async_trait]
 pub trait Exchange: Send + Sync + Clone {
     async fn init(&mut self) -> Result<()>;
-    async fn listen(&self, price_sender: Sender<PriceUpdate>) -> Result<()>;
-    fn get_trading_pairs(&self) -> &[TradingPair];
+    // Returns Ok(()) after closing the connection once `shutdown` is set
+    async fn listen(
+        &self,
+        price_sender: Sender<PriceUpdate>,
+        shutdown: watch::Receiver<bool>,
+    ) -> Result<(), ExchangeError>;
+    // Shared with the running listener; see `resubscribe`
+    fn tracked_pairs(&self) -> &TrackedPairs;
+    fn get_trading_pairs(&self) -> Vec<TradingPair> {
+        self.tracked_pairs().get()
+    }
+    /// Replaces the pairs to track. A running listener subscribes and
+    /// unsubscribes on its live connection where `resubscribes_live`,
+    /// otherwise it reconnects with the new pairs.
+    fn resubscribe(&self, pairs: Vec<TradingPair>) {
+        self.tracked_pairs().set(pairs);
+    }
+    // Whether `listen` applies pair changes without reconnecting
+    fn resubscribes_live(&self) -> bool {
+        false
+    }
     fn get_name(&self) -> &'static str;
+    // Recently sent prices and has been ticking within the last interval
     async fn is_healthy(&self) -> bool;
+    // Price updates sent in the last complete `ticks::TICK_INTERVAL_SECS`
+    fn ticks_last_interval(&self) -> u64;
+    // State of the current websocket connection, `Closed` between attempts
+    fn ws_state(&self) -> ConnState;
+    // One-shot REST prices used to seed Redis before the websocket warms up
+    async fn fetch_snapshot(&self) -> Result<Vec<PriceUpdate>> {
+        Ok(Vec::new())
+    }
 }
 
 pub async fn create_exchange(
@@ -80,18 +643,49 @@ pub async fn create_exchange(
     trading_pairs: Vec<TradingPair>,
 ) -> Result<ExchangeImpl> {
     match exchange_type {
+        #[cfg(feature = "binance")]
         crate::types::Exchange::Binance => Ok(ExchangeImpl::Binance(
             binance::BinanceExchange::new(trading_pairs),
         )),
+        #[cfg(feature = "bitstamp")]
+        crate::types::Exchange::Bitstamp => Ok(ExchangeImpl::Bitstamp(
+            bitstamp::BitstampExchange::new(trading_pairs),
+        )),
+        #[cfg(feature = "bybit")]
         crate::types::Exchange::Bybit => Ok(ExchangeImpl::Bybit(bybit::BybitExchange::new(
             trading_pairs,
         ))),
+        #[cfg(feature = "coinbase")]
         crate::types::Exchange::Coinbase => Ok(ExchangeImpl::Coinbase(
             coinbase::CoinbaseExchange::new(trading_pairs),
         )),
+        #[cfg(feature = "gateio")]
+        crate::types::Exchange::Gateio => Ok(ExchangeImpl::Gateio(gateio::GateioExchange::new(
+            trading_pairs,
+        ))),
+        #[cfg(feature = "htx")]
+        crate::types::Exchange::Htx => Ok(ExchangeImpl::Htx(htx::HtxExchange::new(trading_pairs))),
+        #[cfg(feature = "hyperliquid")]
         crate::types::Exchange::Hyperliquid => Ok(ExchangeImpl::Hyperliquid(
             hyperliquid::HyperliquidExchange::new(trading_pairs),
         )),
+        #[cfg(feature = "kraken")]
+        crate::types::Exchange::Kraken => Ok(ExchangeImpl::Kraken(kraken::KrakenExchange::new(
+            trading_pairs,
+        ))),
+        #[cfg(feature = "kucoin")]
+        crate::types::Exchange::KuCoin => Ok(ExchangeImpl::KuCoin(kucoin::KucoinExchange::new(
+            trading_pairs,
+        ))),
+        #[cfg(feature = "okx")]
+        crate::types::Exchange::Okx => Ok(ExchangeImpl::Okx(okx::OkxExchange::new(trading_pairs))),
         crate::types::Exchange::UniswapV2 => Err(anyhow!("UniswapV2 exchange not implemented yet")),
+        // Left out of this build by its cargo feature
+        #[allow(unreachable_patterns)]
+        other => Err(anyhow!(
+            "{} support is not compiled in, rebuild with --features {}",
+            other.as_str(),
+            other.as_str()
+        )),
     }
 }
@@ -0,0 +1,293 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use log::{error, info};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+use tokio::sync::{mpsc::Sender, watch};
+
+use super::error::ExchangeError;
+use super::pairs::TrackedPairs;
+use super::remap::SymbolRemaps;
+use super::subscription::SubscriptionBuilder;
+use super::ticks::TickCounter;
+use super::ws_stream::{ConnState, SharedConnState, WsStream, WsStreamConfig};
+use super::{canonical_symbols, heartbeat_is_recent, Exchange, DEFAULT_HEARTBEAT_TIMEOUT};
+use crate::clock::{system_clock, SharedClock};
+use crate::types::{system_time_from_millis, PriceKind, PriceMethod, PriceUpdate, TradingPair};
+
+pub struct OkxExchange {
+    trading_pairs: TrackedPairs,
+    last_heartbeat: AtomicI64,
+    ticks: TickCounter,
+    ws_state: SharedConnState,
+    remaps: SymbolRemaps,
+    clock: SharedClock,
+    // Longest gap between messages before `is_healthy` fails
+    heartbeat_timeout: Duration,
+    price_method: PriceMethod,
+}
+
+impl Clone for OkxExchange {
+    fn clone(&self) -> Self {
+        Self {
+            trading_pairs: self.trading_pairs.clone(),
+            last_heartbeat: AtomicI64::new(self.last_heartbeat.load(Ordering::SeqCst)),
+            ticks: self.ticks.clone(),
+            ws_state: self.ws_state.clone(),
+            remaps: self.remaps.clone(),
+            clock: self.clock.clone(),
+            heartbeat_timeout: self.heartbeat_timeout,
+            price_method: self.price_method,
+        }
+    }
+}
+
+// Both the tickers channel and the REST endpoint wrap tickers in `data`
+#[derive(Debug, Deserialize)]
+struct OkxTickerMessage {
+    data: Vec<OkxTicker>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OkxTicker {
+    inst_id: String,
+    bid_px: String,
+    bid_sz: String,
+    ask_px: String,
+    ask_sz: String,
+    // Milliseconds since epoch, as a string
+    ts: String,
+}
+
+impl OkxTicker {
+    fn price(&self, method: PriceMethod) -> Option<Decimal> {
+        let best_bid = Decimal::from_str(&self.bid_px).ok()?;
+        let best_ask = Decimal::from_str(&self.ask_px).ok()?;
+        let sizes = Decimal::from_str(&self.bid_sz)
+            .ok()
+            .zip(Decimal::from_str(&self.ask_sz).ok());
+        Some(method.price(best_bid, best_ask, sizes))
+    }
+}
+
+impl OkxExchange {
+    pub fn new(trading_pairs: Vec<TradingPair>) -> Self {
+        Self {
+            trading_pairs: TrackedPairs::new(trading_pairs),
+            last_heartbeat: AtomicI64::new(Utc::now().timestamp()),
+            ticks: TickCounter::new(),
+            ws_state: SharedConnState::default(),
+            remaps: SymbolRemaps::default(),
+            clock: system_clock(),
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+            price_method: PriceMethod::default(),
+        }
+    }
+
+    /// How long the feed may go without a message before it is unhealthy.
+    pub fn with_heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    /// Time heartbeats and tick windows by `clock` instead of the system
+    /// clock.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.last_heartbeat = AtomicI64::new(clock.timestamp());
+        self.ticks = TickCounter::with_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    /// Translate assets OKX lists under its own tickers.
+    pub fn with_symbol_remaps(mut self, remaps: SymbolRemaps) -> Self {
+        self.remaps = remaps;
+        self
+    }
+
+    /// How to price the top of book; the mid unless set.
+    pub fn with_price_method(mut self, price_method: PriceMethod) -> Self {
+        self.price_method = price_method;
+        self
+    }
+
+    fn get_websocket_url(&self) -> String {
+        "wss://ws.okx.com:8443/ws/v5/public".to_string()
+    }
+
+    fn create_subscription_message(&self) -> String {
+        SubscriptionBuilder::new(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_okx_symbol()
+        })
+        .batched(|symbols| {
+            let args = symbols
+                .iter()
+                .map(|symbol| serde_json::json!({ "channel": "tickers", "instId": symbol }))
+                .collect::<Vec<_>>();
+            serde_json::json!({
+                "op": "subscribe",
+                "args": args
+            })
+        })
+    }
+
+    // One {"event":"subscribe"} per instrument; the first will do
+    fn is_subscription_ack(text: &str) -> Result<bool, ExchangeError> {
+        Ok(serde_json::from_str::<serde_json::Value>(text)
+            .is_ok_and(|message| message["event"] == "subscribe"))
+    }
+
+    // Subscription acks, errors and notices
+    fn is_event(text: &str) -> bool {
+        serde_json::from_str::<serde_json::Value>(text)
+            .is_ok_and(|message| message.get("event").is_some())
+    }
+
+    fn update_heartbeat(&self) {
+        self.last_heartbeat
+            .store(self.clock.timestamp(), Ordering::SeqCst);
+    }
+}
+
+#[async_trait]
+impl Exchange for OkxExchange {
+    async fn init(&mut self) -> Result<()> {
+        // OKX doesn't require initialization
+        Ok(())
+    }
+
+    async fn listen(
+        &self,
+        price_sender: Sender<PriceUpdate>,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> Result<(), ExchangeError> {
+        let subscription_msg = self.create_subscription_message();
+        let mut ws = WsStream::connect_and_subscribe_with(
+            &self.get_websocket_url(),
+            WsStreamConfig {
+                state: self.ws_state.clone(),
+                ..Default::default()
+            },
+            vec![subscription_msg.clone()],
+            Self::is_subscription_ack,
+        )
+        .await?;
+        info!("Subscribed to OKX: {}", subscription_msg);
+
+        let symbols = canonical_symbols(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_okx_symbol()
+        });
+
+        self.update_heartbeat();
+
+        while let Some(text) = ws.read_text_or_shutdown(&mut shutdown).await? {
+            // Subscription acks and errors are `event` messages without `data`
+            if let Ok(message) = serde_json::from_str::<OkxTickerMessage>(&text) {
+                ws.record_decoded();
+                for ticker in message.data {
+                    let canonical = match symbols.get(&ticker.inst_id) {
+                        Some(canonical) => canonical,
+                        None => continue,
+                    };
+                    if let Some(price) = ticker.price(self.price_method) {
+                        let update = PriceUpdate {
+                            symbol: canonical.symbol.clone(),
+                            price,
+                            timestamp: Utc::now().into(),
+                            source: "okx".to_string(),
+                            kind: PriceKind::TopOfBook,
+                            bid: Decimal::from_str(&ticker.bid_px).ok(),
+                            ask: Decimal::from_str(&ticker.ask_px).ok(),
+                            exchange_timestamp: ticker.ts.parse().ok().map(system_time_from_millis),
+                        }
+                        .scaled(canonical.price_scale);
+
+                        if let Err(e) = price_sender.send(update).await {
+                            error!("Failed to send price update: {}", e);
+                            return Err(ExchangeError::ChannelClosed);
+                        }
+
+                        self.update_heartbeat();
+                        self.ticks.record();
+                    }
+                }
+            } else if Self::is_event(&text) {
+                ws.record_decoded();
+            } else {
+                ws.record_decode_failure(&text)?;
+            }
+        }
+
+        if *shutdown.borrow() {
+            ws.close().await?;
+            return Ok(());
+        }
+
+        Err(ExchangeError::StreamEnded)
+    }
+
+    fn tracked_pairs(&self) -> &TrackedPairs {
+        &self.trading_pairs
+    }
+
+    fn get_name(&self) -> &'static str {
+        "okx"
+    }
+
+    async fn fetch_snapshot(&self) -> Result<Vec<PriceUpdate>> {
+        let symbols = canonical_symbols(&self.trading_pairs.get(), &self.remaps, |pair| {
+            pair.to_okx_symbol()
+        });
+
+        let response: OkxTickerMessage = reqwest::Client::new()
+            .get("https://www.okx.com/api/v5/market/tickers")
+            .query(&[("instType", "SPOT")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let mut updates = Vec::new();
+        for ticker in response.data {
+            if let (Some(canonical), Some(price)) = (
+                symbols.get(&ticker.inst_id),
+                ticker.price(self.price_method),
+            ) {
+                updates.push(
+                    PriceUpdate {
+                        symbol: canonical.symbol.clone(),
+                        price,
+                        timestamp: Utc::now().into(),
+                        source: "okx".to_string(),
+                        kind: PriceKind::TopOfBook,
+                        bid: Decimal::from_str(&ticker.bid_px).ok(),
+                        ask: Decimal::from_str(&ticker.ask_px).ok(),
+                        exchange_timestamp: None,
+                    }
+                    .scaled(canonical.price_scale),
+                );
+            }
+        }
+        Ok(updates)
+    }
+
+    async fn is_healthy(&self) -> bool {
+        // A live socket isn't enough; prices must actually be arriving
+        heartbeat_is_recent(&self.last_heartbeat, &self.clock, self.heartbeat_timeout)
+            && self.ticks.is_active()
+    }
+
+    fn ticks_last_interval(&self) -> u64 {
+        self.ticks.last_interval()
+    }
+
+    fn ws_state(&self) -> ConnState {
+        self.ws_state.get()
+    }
+}
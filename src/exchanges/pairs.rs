@@ -0,0 +1,37 @@
+use std::sync::Arc;
+use tokio::sync::watch;
+
+use crate::types::TradingPair;
+
+/// The pairs an exchange tracks, shared between the exchange and its running
+/// listener so they can change without rebuilding the exchange. Clones share
+/// the same pairs.
+#[derive(Debug, Clone)]
+pub struct TrackedPairs(Arc<watch::Sender<Vec<TradingPair>>>);
+
+impl TrackedPairs {
+    pub fn new(pairs: Vec<TradingPair>) -> Self {
+        Self(Arc::new(watch::channel(pairs).0))
+    }
+
+    pub fn get(&self) -> Vec<TradingPair> {
+        self.0.borrow().clone()
+    }
+
+    pub fn set(&self, pairs: Vec<TradingPair>) {
+        self.0.send_replace(pairs);
+    }
+
+    /// Notified on every `set` after this call.
+    pub fn watch(&self) -> watch::Receiver<Vec<TradingPair>> {
+        self.0.subscribe()
+    }
+}
+
+/// Splits a change of subscriptions from `old` to `new` into those to
+/// subscribe to and those to unsubscribe from.
+pub fn diff_subscriptions(old: &[String], new: &[String]) -> (Vec<String>, Vec<String>) {
+    let added = new.iter().filter(|s| !old.contains(s)).cloned().collect();
+    let removed = old.iter().filter(|s| !new.contains(s)).cloned().collect();
+    (added, removed)
+}
@@ -0,0 +1,82 @@
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::types::{Exchange, TradingPair};
+
+fn default_price_scale() -> Decimal {
+    Decimal::ONE
+}
+
+// A base asset an exchange lists under its own ticker, e.g. Hyperliquid's
+// `kSHIB`, which is quoted per 1000 SHIB
+#[derive(Debug, Clone, Deserialize)]
+pub struct SymbolRemap {
+    pub exchange: Exchange,
+    // Ticker as the exchange spells it, case preserved
+    pub native: String,
+    // Base asset of the configured trading pair
+    pub canonical: String,
+    // Multiplies the exchange's price into a price per canonical unit
+    #[serde(default = "default_price_scale")]
+    pub price_scale: Decimal,
+}
+
+/// Exchange-specific tickers keyed by exchange and native ticker.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolRemaps(Arc<HashMap<(Exchange, String), SymbolRemap>>);
+
+impl SymbolRemaps {
+    pub fn new(remaps: &[SymbolRemap]) -> Self {
+        Self(Arc::new(
+            remaps
+                .iter()
+                .map(|remap| {
+                    let remap = SymbolRemap {
+                        canonical: remap.canonical.to_uppercase(),
+                        ..remap.clone()
+                    };
+                    ((remap.exchange, remap.native.clone()), remap)
+                })
+                .collect(),
+        ))
+    }
+
+    /// Just the remaps that apply to `exchange`.
+    pub fn for_exchange(&self, exchange: Exchange) -> Self {
+        Self(Arc::new(
+            self.0
+                .iter()
+                .filter(|((remapped, _), _)| *remapped == exchange)
+                .map(|(key, remap)| (key.clone(), remap.clone()))
+                .collect(),
+        ))
+    }
+
+    fn find(&self, pair: &TradingPair) -> Option<&SymbolRemap> {
+        self.0.values().find(|remap| remap.canonical == pair.base)
+    }
+
+    /// `pair` with its base replaced by the exchange's own ticker, if remapped.
+    pub fn native_pair(&self, pair: &TradingPair) -> TradingPair {
+        match self.find(pair) {
+            Some(remap) => TradingPair {
+                base: remap.native.clone(),
+                quote: pair.quote.clone(),
+            },
+            None => pair.clone(),
+        }
+    }
+
+    pub fn native_pairs(&self, pairs: &[TradingPair]) -> Vec<TradingPair> {
+        pairs.iter().map(|pair| self.native_pair(pair)).collect()
+    }
+
+    /// Factor from the exchange's price for `pair` to the canonical price.
+    pub fn price_scale(&self, pair: &TradingPair) -> Decimal {
+        self.find(pair)
+            .map(|remap| remap.price_scale)
+            .unwrap_or(Decimal::ONE)
+    }
+}
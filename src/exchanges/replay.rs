@@ -0,0 +1,228 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use log::{error, info};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{Duration, SystemTime};
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::{mpsc::Sender, watch};
+use tokio::time::sleep;
+
+use super::error::ExchangeError;
+use super::pairs::TrackedPairs;
+use super::ticks::TickCounter;
+use super::ws_stream::ConnState;
+use super::{heartbeat_is_recent, Exchange, DEFAULT_HEARTBEAT_TIMEOUT};
+use crate::clock::{system_clock, SharedClock};
+use crate::types::{PriceUpdate, TradingPair};
+
+/// How fast `FileReplayExchange` emits the recorded updates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplayPacing {
+    // Sleep out the gaps between the recorded timestamps
+    RealTime,
+    #[default]
+    AsFastAsPossible,
+}
+
+/// Replays a newline-delimited JSON file of `PriceUpdate`s, e.g. captured in
+/// production, so an incident can be reproduced offline. Not one of the
+/// configurable exchanges: build it directly and hand it to
+/// `PricePublisher::replay`.
+///
+/// Timestamps are shifted so the first update is stamped with the time the
+/// replay started, keeping the recorded gaps, so freshness checks treat the
+/// prices as live. `listen` returns Ok once the file is exhausted.
+pub struct FileReplayExchange {
+    path: PathBuf,
+    // Only updates for these pairs are emitted; everything when empty
+    trading_pairs: TrackedPairs,
+    pacing: ReplayPacing,
+    last_heartbeat: AtomicI64,
+    ticks: TickCounter,
+    clock: SharedClock,
+    // Longest gap between updates before `is_healthy` fails
+    heartbeat_timeout: Duration,
+}
+
+impl Clone for FileReplayExchange {
+    fn clone(&self) -> Self {
+        Self {
+            path: self.path.clone(),
+            trading_pairs: self.trading_pairs.clone(),
+            pacing: self.pacing,
+            last_heartbeat: AtomicI64::new(self.last_heartbeat.load(Ordering::SeqCst)),
+            ticks: self.ticks.clone(),
+            clock: self.clock.clone(),
+            heartbeat_timeout: self.heartbeat_timeout,
+        }
+    }
+}
+
+impl FileReplayExchange {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let clock = system_clock();
+        Self {
+            path: path.into(),
+            trading_pairs: TrackedPairs::new(Vec::new()),
+            pacing: ReplayPacing::default(),
+            last_heartbeat: AtomicI64::new(clock.timestamp()),
+            ticks: TickCounter::with_clock(clock.clone()),
+            clock,
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+        }
+    }
+
+    /// Only replays updates for `trading_pairs`.
+    pub fn with_trading_pairs(mut self, trading_pairs: Vec<TradingPair>) -> Self {
+        self.trading_pairs = TrackedPairs::new(trading_pairs);
+        self
+    }
+
+    /// As fast as possible unless set.
+    pub fn with_pacing(mut self, pacing: ReplayPacing) -> Self {
+        self.pacing = pacing;
+        self
+    }
+
+    /// How long the replay may go without an update before it is unhealthy.
+    pub fn with_heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    /// Time heartbeats and tick windows by `clock` instead of the system
+    /// clock.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.last_heartbeat = AtomicI64::new(clock.timestamp());
+        self.ticks = TickCounter::with_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    fn update_heartbeat(&self) {
+        self.last_heartbeat
+            .store(self.clock.timestamp(), Ordering::SeqCst);
+    }
+}
+
+// Moves `time` by the difference between the replay's start and the first
+// recorded timestamp
+fn rebase(time: SystemTime, first: SystemTime, started: SystemTime) -> SystemTime {
+    match time.duration_since(first) {
+        Ok(after) => started + after,
+        Err(e) => started.checked_sub(e.duration()).unwrap_or(started),
+    }
+}
+
+#[async_trait]
+impl Exchange for FileReplayExchange {
+    async fn init(&mut self) -> Result<()> {
+        // Fail early on a missing file rather than on the first listen
+        File::open(&self.path)
+            .await
+            .with_context(|| format!("Failed to open replay file {}", self.path.display()))?;
+        Ok(())
+    }
+
+    async fn listen(
+        &self,
+        price_sender: Sender<PriceUpdate>,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> Result<(), ExchangeError> {
+        let file = File::open(&self.path)
+            .await
+            .with_context(|| format!("Failed to open replay file {}", self.path.display()))?;
+        let mut lines = BufReader::new(file).lines();
+        info!("Replaying price updates from {}", self.path.display());
+
+        let symbols: HashSet<String> = self
+            .trading_pairs
+            .get()
+            .iter()
+            .map(|pair| pair.to_canonical_symbol())
+            .collect();
+        let started = SystemTime::now();
+        let mut first: Option<SystemTime> = None;
+        let mut previous: Option<SystemTime> = None;
+        let mut line_number = 0;
+        let mut replayed = 0;
+
+        while let Some(line) = lines
+            .next_line()
+            .await
+            .with_context(|| format!("Failed to read replay file {}", self.path.display()))?
+        {
+            line_number += 1;
+            if *shutdown.borrow() {
+                return Ok(());
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut update: PriceUpdate = serde_json::from_str(&line).with_context(|| {
+                format!("Invalid update at {}:{}", self.path.display(), line_number)
+            })?;
+            if !symbols.is_empty() && !symbols.contains(&update.symbol) {
+                continue;
+            }
+
+            if self.pacing == ReplayPacing::RealTime {
+                let gap = previous
+                    .and_then(|previous| update.timestamp.duration_since(previous).ok())
+                    .unwrap_or(Duration::ZERO);
+                tokio::select! {
+                    _ = sleep(gap) => {}
+                    _ = shutdown.changed() => return Ok(()),
+                }
+            }
+            previous = Some(update.timestamp);
+
+            let first = *first.get_or_insert(update.timestamp);
+            update.timestamp = rebase(update.timestamp, first, started);
+            update.exchange_timestamp = update
+                .exchange_timestamp
+                .map(|timestamp| rebase(timestamp, first, started));
+
+            if let Err(e) = price_sender.send(update).await {
+                error!("Failed to send price update: {}", e);
+                return Err(ExchangeError::ChannelClosed);
+            }
+            replayed += 1;
+
+            self.update_heartbeat();
+            self.ticks.record();
+        }
+
+        info!(
+            "Replayed {} price updates from {}",
+            replayed,
+            self.path.display()
+        );
+        Ok(())
+    }
+
+    fn tracked_pairs(&self) -> &TrackedPairs {
+        &self.trading_pairs
+    }
+
+    fn get_name(&self) -> &'static str {
+        "replay"
+    }
+
+    async fn is_healthy(&self) -> bool {
+        heartbeat_is_recent(&self.last_heartbeat, &self.clock, self.heartbeat_timeout)
+            && self.ticks.is_active()
+    }
+
+    fn ticks_last_interval(&self) -> u64 {
+        self.ticks.last_interval()
+    }
+
+    // Reads a file, there is no websocket
+    fn ws_state(&self) -> ConnState {
+        ConnState::Closed
+    }
+}
@@ -0,0 +1,69 @@
+use serde_json::Value;
+
+use super::remap::SymbolRemaps;
+use crate::types::TradingPair;
+
+/// Builds an exchange's subscribe payloads from its tracked pairs.
+///
+/// Each pair is remapped and spelled the exchange's way once, with
+/// duplicates dropped (first one wins, as in `canonical_symbols`). The
+/// exchange then only supplies its request shape: one request for every
+/// symbol (`batched`), one per symbol (`per_symbol`), or a list of topic
+/// names built from symbols and channels (`topics`).
+#[derive(Debug, Clone)]
+pub struct SubscriptionBuilder {
+    symbols: Vec<String>,
+}
+
+impl SubscriptionBuilder {
+    pub fn new<F>(pairs: &[TradingPair], remaps: &SymbolRemaps, to_native: F) -> Self
+    where
+        F: Fn(&TradingPair) -> String,
+    {
+        let mut symbols: Vec<String> = Vec::new();
+        for pair in remaps.native_pairs(pairs) {
+            let symbol = to_native(&pair);
+            if !symbols.contains(&symbol) {
+                symbols.push(symbol);
+            }
+        }
+        Self { symbols }
+    }
+
+    /// Native symbols in configured order.
+    pub fn symbols(&self) -> &[String] {
+        &self.symbols
+    }
+
+    /// A topic name for every symbol and channel, grouped by symbol.
+    /// `topic` gets the channel and then the symbol.
+    pub fn topics<F>(&self, channels: &[&str], topic: F) -> Vec<String>
+    where
+        F: Fn(&str, &str) -> String,
+    {
+        self.symbols
+            .iter()
+            .flat_map(|symbol| channels.iter().map(|channel| topic(channel, symbol)))
+            .collect()
+    }
+
+    /// A single request covering every symbol.
+    pub fn batched<F>(&self, request: F) -> String
+    where
+        F: FnOnce(&[String]) -> Value,
+    {
+        request(&self.symbols).to_string()
+    }
+
+    /// One request per symbol, for exchanges that take a single channel per
+    /// subscribe.
+    pub fn per_symbol<F>(&self, request: F) -> Vec<String>
+    where
+        F: Fn(&str) -> Value,
+    {
+        self.symbols
+            .iter()
+            .map(|symbol| request(symbol).to_string())
+            .collect()
+    }
+}
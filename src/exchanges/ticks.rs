@@ -0,0 +1,89 @@
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+use crate::clock::{system_clock, SharedClock};
+
+/// Length of the windows `TickCounter` counts over, in seconds.
+pub const TICK_INTERVAL_SECS: i64 = 10;
+
+/// Counts data messages (ticks that produced a `PriceUpdate`) in fixed
+/// windows, so a feed whose socket stays up while its subscription has
+/// silently stopped delivering shows up as idle. Pings, pongs and acks
+/// don't count.
+pub struct TickCounter {
+    clock: SharedClock,
+    window_start: AtomicI64,
+    current: AtomicU64,
+    // Ticks in the last complete window
+    previous: AtomicU64,
+}
+
+impl TickCounter {
+    pub fn new() -> Self {
+        Self::with_clock(system_clock())
+    }
+
+    /// A counter whose windows follow `clock`.
+    pub fn with_clock(clock: SharedClock) -> Self {
+        Self {
+            window_start: AtomicI64::new(clock.timestamp()),
+            clock,
+            current: AtomicU64::new(0),
+            previous: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self) {
+        self.roll();
+        self.current.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Ticks in the last complete `TICK_INTERVAL_SECS` window.
+    pub fn last_interval(&self) -> u64 {
+        self.roll();
+        self.previous.load(Ordering::Relaxed)
+    }
+
+    /// True if anything was counted in this window or the one before.
+    pub fn is_active(&self) -> bool {
+        self.roll();
+        self.current.load(Ordering::Relaxed) > 0 || self.previous.load(Ordering::Relaxed) > 0
+    }
+
+    // Moves on to the window containing now. Concurrent rolls may misplace a
+    // tick at the boundary, which is fine for a health signal.
+    fn roll(&self) {
+        let now = self.clock.timestamp();
+        let start = self.window_start.load(Ordering::Relaxed);
+        let elapsed = now - start;
+        if elapsed < TICK_INTERVAL_SECS {
+            return;
+        }
+        let count = self.current.swap(0, Ordering::Relaxed);
+        // A window with no rolls in between was idle
+        let previous = if elapsed < 2 * TICK_INTERVAL_SECS {
+            count
+        } else {
+            0
+        };
+        self.previous.store(previous, Ordering::Relaxed);
+        self.window_start
+            .store(now - elapsed % TICK_INTERVAL_SECS, Ordering::Relaxed);
+    }
+}
+
+impl Default for TickCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for TickCounter {
+    fn clone(&self) -> Self {
+        Self {
+            clock: self.clock.clone(),
+            window_start: AtomicI64::new(self.window_start.load(Ordering::Relaxed)),
+            current: AtomicU64::new(self.current.load(Ordering::Relaxed)),
+            previous: AtomicU64::new(self.previous.load(Ordering::Relaxed)),
+        }
+    }
+}
@@ -1,56 +1,410 @@
 use anyhow::{anyhow, Result};
+use base64::Engine;
+use flate2::read::{DeflateDecoder, GzDecoder};
 use futures_util::{
     stream::{SplitSink, SplitStream},
     SinkExt, StreamExt,
 };
 use log::{error, info, warn};
+use serde::Serialize;
+use std::io::Read;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
-use tokio::time::{timeout, Duration};
+use tokio::sync::watch;
+use tokio::time::{sleep, timeout, Duration, Instant};
+use tokio_socks::tcp::Socks5Stream;
 use tokio_tungstenite::{
-    connect_async,
+    client_async_tls, connect_async,
     tungstenite::{protocol::Message, Error as WsError},
     MaybeTlsStream, WebSocketStream as TungsteniteStream,
 };
 use url::Url;
 
-const PING_INTERVAL: Duration = Duration::from_secs(30);
-const PING_TIMEOUT: Duration = Duration::from_secs(10);
-const CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
+use super::error::ExchangeError;
+
+#[derive(Debug, Clone)]
+pub struct WsStreamConfig {
+    // Idle time before we send our own ping
+    pub ping_interval: Duration,
+    // How long to wait for the pong before giving up on the connection
+    pub ping_timeout: Duration,
+    pub connection_timeout: Duration,
+    // How long connect_and_subscribe waits for the exchange to confirm
+    pub subscribe_timeout: Duration,
+    // Pacing for our outbound text messages; unlimited when None
+    pub send_rate_limit: Option<RateLimit>,
+    // socks5://, socks5h:// or http:// proxy to tunnel the TCP connection
+    // through; defaults to HTTPS_PROXY or ALL_PROXY from the environment
+    pub proxy: Option<Url>,
+    // Where the stream reports its `ConnState`; pass an exchange's own to
+    // read the state from outside the listener
+    pub state: SharedConnState,
+    // Set for feeds that send compressed binary frames, which `read_text`
+    // then decompresses; includes the frames read while waiting for the ack
+    pub compression: Option<Compression>,
+    // Consecutive messages the exchange can't parse before the connection
+    // is failed; see `WsStream::record_decode_failure`
+    pub max_decode_failures: u32,
+}
+
+/// Where a `WsStream` is in its lifecycle. Every transition is logged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnState {
+    // Opening the TCP, TLS and websocket connections
+    Connecting,
+    // Connected, waiting for the exchange to confirm the subscription
+    Subscribing,
+    Live,
+    // Close frame sent
+    Closing,
+    #[default]
+    Closed,
+}
+
+impl ConnState {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => ConnState::Connecting,
+            1 => ConnState::Subscribing,
+            2 => ConnState::Live,
+            3 => ConnState::Closing,
+            _ => ConnState::Closed,
+        }
+    }
+}
+
+/// A connection's `ConnState`, shared between the `WsStream` and the
+/// exchange that opened it. Starts out `Closed`.
+#[derive(Debug, Clone)]
+pub struct SharedConnState(Arc<AtomicU8>);
+
+impl SharedConnState {
+    pub fn get(&self) -> ConnState {
+        ConnState::from_u8(self.0.load(Ordering::SeqCst))
+    }
+
+    // Moves to `state`, logging the transition against `url`
+    fn transition(&self, url: &str, state: ConnState) {
+        let previous = ConnState::from_u8(self.0.swap(state as u8, Ordering::SeqCst));
+        if previous != state {
+            info!("WebSocket {}: {:?} -> {:?}", url, previous, state);
+        }
+    }
+}
+
+impl Default for SharedConnState {
+    fn default() -> Self {
+        Self(Arc::new(AtomicU8::new(ConnState::Closed as u8)))
+    }
+}
+
+// At most `messages` sends per `per`, allowing bursts of up to `messages`
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub messages: u32,
+    pub per: Duration,
+}
+
+// Token bucket backing a RateLimit
+struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            tokens: limit.messages as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    // Waits until a token is available, then takes it
+    async fn acquire(&mut self) {
+        let capacity = self.limit.messages as f64;
+        let tokens_per_sec = capacity / self.limit.per.as_secs_f64();
+        loop {
+            let now = Instant::now();
+            let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * tokens_per_sec).min(capacity);
+            self.last_refill = now;
+
+            if self.tokens >= 1.0 {
+                self.tokens -= 1.0;
+                return;
+            }
+            sleep(Duration::from_secs_f64(
+                (1.0 - self.tokens) / tokens_per_sec,
+            ))
+            .await;
+        }
+    }
+}
+
+impl Default for WsStreamConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(30),
+            ping_timeout: Duration::from_secs(10),
+            connection_timeout: Duration::from_secs(30),
+            subscribe_timeout: Duration::from_secs(10),
+            send_rate_limit: None,
+            proxy: proxy_from_env(),
+            state: SharedConnState::default(),
+            compression: None,
+            max_decode_failures: 100,
+        }
+    }
+}
+
+// HTTPS_PROXY wins over ALL_PROXY since every exchange feed is wss://
+fn proxy_from_env() -> Option<Url> {
+    ["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| match Url::parse(&value) {
+            Ok(url) => Some(url),
+            Err(e) => {
+                warn!("Ignoring invalid proxy URL {}: {}", value, e);
+                None
+            }
+        })
+}
+
+// Opens a TCP connection to `host:port` through `proxy`. The returned stream
+// is a plain tunnel, ready for the TLS and websocket handshakes.
+async fn connect_via_proxy(proxy: &Url, host: &str, port: u16) -> Result<TcpStream> {
+    let proxy_host = proxy
+        .host_str()
+        .ok_or_else(|| anyhow!("Proxy URL has no host: {}", proxy))?;
+    let proxy_port = proxy
+        .port_or_known_default()
+        .unwrap_or(match proxy.scheme() {
+            "http" => 8080,
+            _ => 1080,
+        });
+    let credentials = match proxy.username() {
+        "" => None,
+        username => Some((username, proxy.password().unwrap_or_default())),
+    };
+
+    match proxy.scheme() {
+        "socks5" | "socks5h" => {
+            let proxy_addr = (proxy_host, proxy_port);
+            let stream = match credentials {
+                Some((username, password)) => {
+                    Socks5Stream::connect_with_password(
+                        proxy_addr,
+                        (host, port),
+                        username,
+                        password,
+                    )
+                    .await?
+                }
+                None => Socks5Stream::connect(proxy_addr, (host, port)).await?,
+            };
+            Ok(stream.into_inner())
+        }
+        "http" => {
+            let mut stream = TcpStream::connect((proxy_host, proxy_port)).await?;
+            let mut request = format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n");
+            if let Some((username, password)) = credentials {
+                let token = base64::engine::general_purpose::STANDARD
+                    .encode(format!("{}:{}", username, password));
+                request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+            }
+            request.push_str("\r\n");
+            stream.write_all(request.as_bytes()).await?;
+
+            // Read the response headers byte by byte so nothing past them is
+            // consumed before the TLS handshake
+            let mut response = Vec::new();
+            while !response.ends_with(b"\r\n\r\n") {
+                if response.len() > 8192 {
+                    return Err(anyhow!("Proxy CONNECT response too long"));
+                }
+                response.push(stream.read_u8().await?);
+            }
+            let status_line = String::from_utf8_lossy(&response);
+            let status_line = status_line.lines().next().unwrap_or_default();
+            if status_line.split_whitespace().nth(1) != Some("200") {
+                return Err(anyhow!("Proxy refused CONNECT: {}", status_line));
+            }
+            Ok(stream)
+        }
+        other => Err(anyhow!("Unsupported proxy scheme: {}", other)),
+    }
+}
+
+// Encoding of binary frames on feeds that compress their payloads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    // Raw deflate without a zlib header, as sent by OKX
+    Deflate,
+}
+
+impl Compression {
+    fn decompress(&self, data: &[u8]) -> Result<String> {
+        let mut text = String::new();
+        match self {
+            Compression::Gzip => GzDecoder::new(data).read_to_string(&mut text)?,
+            Compression::Deflate => DeflateDecoder::new(data).read_to_string(&mut text)?,
+        };
+        Ok(text)
+    }
+}
 
 pub struct WsStream {
+    url: String,
     write: SplitSink<TungsteniteStream<MaybeTlsStream<TcpStream>>, Message>,
     read: SplitStream<TungsteniteStream<MaybeTlsStream<TcpStream>>>,
+    config: WsStreamConfig,
+    compression: Option<Compression>,
+    send_limiter: Option<TokenBucket>,
+    // Messages in a row that no exchange message type matched
+    decode_failures: u32,
 }
 
 impl WsStream {
-    pub async fn connect(url: &str) -> Result<Self> {
-        let url = Url::parse(url)?;
+    pub async fn connect(url: &str) -> Result<Self, ExchangeError> {
+        Self::connect_with(url, WsStreamConfig::default()).await
+    }
+
+    /// Connects to a feed that sends compressed binary frames; `read_text`
+    /// decompresses them and returns the decoded text.
+    pub async fn connect_compressed(
+        url: &str,
+        compression: Compression,
+    ) -> Result<Self, ExchangeError> {
+        let mut ws = Self::connect(url).await?;
+        ws.compression = Some(compression);
+        Ok(ws)
+    }
+
+    pub async fn connect_with(url: &str, config: WsStreamConfig) -> Result<Self, ExchangeError> {
+        let ws = Self::open(url, config).await?;
+        ws.transition(ConnState::Live);
+        Ok(ws)
+    }
+
+    // Connects, leaving the state at Connecting for the caller to move on
+    async fn open(url: &str, config: WsStreamConfig) -> Result<Self, ExchangeError> {
+        config.state.transition(url, ConnState::Connecting);
+        let result = Self::open_connection(url, config.clone()).await;
+        if result.is_err() {
+            config.state.transition(url, ConnState::Closed);
+        }
+        result
+    }
+
+    async fn open_connection(url: &str, config: WsStreamConfig) -> Result<Self, ExchangeError> {
+        let display_url = url.to_string();
+        let url = Url::parse(url).map_err(|e| ExchangeError::Connect(e.to_string()))?;
 
         // Add connection timeout
-        let connect_fut = connect_async(url);
-        let (ws_stream, _) = match timeout(CONNECTION_TIMEOUT, connect_fut).await {
+        let connect_fut = async {
+            match &config.proxy {
+                Some(proxy) => {
+                    let host = url
+                        .host_str()
+                        .ok_or_else(|| anyhow!("WebSocket URL has no host: {}", url))?;
+                    let port = url.port_or_known_default().unwrap_or(443);
+                    let tunnel = connect_via_proxy(proxy, host, port).await?;
+                    Ok(client_async_tls(url.as_str(), tunnel).await?)
+                }
+                None => Ok::<_, anyhow::Error>(connect_async(url.as_str()).await?),
+            }
+        };
+        let (ws_stream, _) = match timeout(config.connection_timeout, connect_fut).await {
             Ok(Ok(stream)) => stream,
-            Ok(Err(e)) => return Err(anyhow!("WebSocket connection error: {}", e)),
-            Err(_) => return Err(anyhow!("WebSocket connection timeout")),
+            Ok(Err(e)) => return Err(ExchangeError::Connect(e.to_string())),
+            Err(_) => return Err(ExchangeError::ConnectTimeout),
         };
 
         let (write, read) = ws_stream.split();
-        Ok(Self { write, read })
+        Ok(Self {
+            url: display_url,
+            write,
+            read,
+            send_limiter: config.send_rate_limit.map(TokenBucket::new),
+            compression: config.compression,
+            decode_failures: 0,
+            config,
+        })
     }
 
-    pub async fn send_message(&mut self, msg: Message) -> Result<()> {
-        self.write
-            .send(msg)
-            .await
-            .map_err(|e| anyhow!("Send error: {}", e))
+    /// Connects, sends each subscription message and waits for a message
+    /// accepted by `is_ack`, so callers only start trusting data once the
+    /// exchange has confirmed the subscription. Anything received before the
+    /// ack is discarded; an `Err` from `is_ack` (e.g. a rejected
+    /// subscription) fails the connection immediately.
+    pub async fn connect_and_subscribe<F>(
+        url: &str,
+        messages: Vec<String>,
+        is_ack: F,
+    ) -> Result<Self, ExchangeError>
+    where
+        F: Fn(&str) -> Result<bool, ExchangeError>,
+    {
+        Self::connect_and_subscribe_with(url, WsStreamConfig::default(), messages, is_ack).await
+    }
+
+    pub async fn connect_and_subscribe_with<F>(
+        url: &str,
+        config: WsStreamConfig,
+        messages: Vec<String>,
+        is_ack: F,
+    ) -> Result<Self, ExchangeError>
+    where
+        F: Fn(&str) -> Result<bool, ExchangeError>,
+    {
+        let subscribe_timeout = config.subscribe_timeout;
+        let mut ws = Self::open(url, config).await?;
+        ws.transition(ConnState::Subscribing);
+        for message in messages {
+            ws.send_text(message).await?;
+        }
+
+        let wait_for_ack = async {
+            loop {
+                if let Some(text) = ws.read_text().await? {
+                    if is_ack(&text)? {
+                        return Ok::<_, ExchangeError>(());
+                    }
+                }
+            }
+        };
+        match timeout(subscribe_timeout, wait_for_ack).await {
+            Ok(result) => result?,
+            Err(_) => return Err(ExchangeError::SubscribeTimeout),
+        }
+
+        ws.transition(ConnState::Live);
+        Ok(ws)
     }
 
-    pub async fn send_text(&mut self, text: String) -> Result<()> {
+    pub async fn send_message(&mut self, msg: Message) -> Result<(), ExchangeError> {
+        Ok(self.write.send(msg).await?)
+    }
+
+    /// Sends a text frame, first waiting on the connection's rate limit if
+    /// one is configured. Control frames are never delayed.
+    pub async fn send_text(&mut self, text: String) -> Result<(), ExchangeError> {
+        if let Some(limiter) = &mut self.send_limiter {
+            limiter.acquire().await;
+        }
         self.send_message(Message::Text(text)).await
     }
 
-    pub async fn read_message(&mut self) -> Result<Option<Message>> {
-        match timeout(PING_INTERVAL, self.read.next()).await {
+    pub async fn read_message(&mut self) -> Result<Option<Message>, ExchangeError> {
+        match timeout(self.config.ping_interval, self.read.next()).await {
             Ok(Some(Ok(msg))) => {
                 match msg {
                     Message::Ping(data) => {
@@ -64,9 +418,9 @@ impl WsStream {
                         // Ignore pongs
                         Ok(None)
                     }
-                    Message::Close(frame) => {
-                        Err(anyhow!("WebSocket closed by server: {:?}", frame))
-                    }
+                    Message::Close(frame) => Err(ExchangeError::ServerClose(
+                        frame.map(|frame| frame.into_owned()),
+                    )),
                     _ => Ok(Some(msg)),
                 }
             }
@@ -75,9 +429,9 @@ impl WsStream {
                     warn!("WebSocket protocol error: {}", e);
                     Ok(None)
                 }
-                _ => Err(anyhow!("WebSocket error: {}", e)),
+                _ => Err(e.into()),
             },
-            Ok(None) => Err(anyhow!("WebSocket stream ended")),
+            Ok(None) => Err(ExchangeError::StreamEnded),
             Err(_) => {
                 // Send ping on timeout
                 if let Err(e) = self.send_message(Message::Ping(vec![])).await {
@@ -85,20 +439,90 @@ impl WsStream {
                 }
 
                 // Wait for pong response
-                match timeout(PING_TIMEOUT, self.read.next()).await {
+                match timeout(self.config.ping_timeout, self.read.next()).await {
                     Ok(Some(Ok(Message::Pong(_)))) => Ok(None),
-                    _ => Err(anyhow!("WebSocket ping timeout")),
+                    _ => Err(ExchangeError::PingTimeout),
                 }
             }
         }
     }
 
-    pub async fn read_text(&mut self) -> Result<Option<String>> {
+    pub async fn read_text(&mut self) -> Result<Option<String>, ExchangeError> {
         while let Some(msg) = self.read_message().await? {
-            if let Message::Text(text) = msg {
-                return Ok(Some(text));
+            match msg {
+                Message::Text(text) => return Ok(Some(text)),
+                Message::Binary(data) => {
+                    // Binary frames are only meaningful on compressed feeds
+                    if let Some(compression) = self.compression {
+                        match compression.decompress(&data) {
+                            Ok(text) => return Ok(Some(text)),
+                            Err(e) => warn!("Failed to decompress {:?} frame: {}", compression, e),
+                        }
+                    }
+                }
+                _ => {}
             }
         }
         Ok(None)
     }
+
+    /// Like `read_text`, but returns `Ok(None)` as soon as `shutdown` is set.
+    pub async fn read_text_or_shutdown(
+        &mut self,
+        shutdown: &mut watch::Receiver<bool>,
+    ) -> Result<Option<String>, ExchangeError> {
+        if *shutdown.borrow() {
+            return Ok(None);
+        }
+        tokio::select! {
+            _ = shutdown.changed() => Ok(None),
+            text = self.read_text() => text,
+        }
+    }
+
+    /// Marks a message the exchange recognized, data or control, resetting
+    /// the count of consecutive decode failures.
+    pub fn record_decoded(&mut self) {
+        self.decode_failures = 0;
+    }
+
+    /// Marks a message none of the exchange's message types matched. After
+    /// `max_decode_failures` in a row, typically an exchange schema change,
+    /// logs the message and fails the connection rather than staying
+    /// connected to a feed that never publishes.
+    pub fn record_decode_failure(&mut self, text: &str) -> Result<(), ExchangeError> {
+        self.decode_failures += 1;
+        if self.decode_failures < self.config.max_decode_failures {
+            return Ok(());
+        }
+        error!(
+            "WebSocket {}: {} consecutive messages failed to parse, reconnecting. Last: {}",
+            self.url,
+            self.decode_failures,
+            text.chars().take(500).collect::<String>()
+        );
+        Err(ExchangeError::Undecodable(self.decode_failures))
+    }
+
+    pub fn state(&self) -> ConnState {
+        self.config.state.get()
+    }
+
+    fn transition(&self, state: ConnState) {
+        self.config.state.transition(&self.url, state);
+    }
+
+    /// Sends a Close frame and waits for the sink to flush.
+    pub async fn close(mut self) -> Result<(), ExchangeError> {
+        self.transition(ConnState::Closing);
+        self.send_message(Message::Close(None)).await?;
+        Ok(self.write.flush().await?)
+    }
+}
+
+// However the stream ends, its connection is gone once it's dropped
+impl Drop for WsStream {
+    fn drop(&mut self) {
+        self.transition(ConnState::Closed);
+    }
 }
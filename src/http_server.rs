@@ -0,0 +1,127 @@
+use anyhow::Result;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use log::info;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::publisher::PricePublisher;
+use crate::types::serialize_unix_timestamp;
+
+pub const DEFAULT_HTTP_PORT: u16 = 8080;
+
+#[derive(Debug, Serialize)]
+struct SourcePrice {
+    price: Decimal,
+    #[serde(serialize_with = "serialize_unix_timestamp")]
+    timestamp: SystemTime,
+}
+
+// Reads HTTP_PORT from the environment, falling back to DEFAULT_HTTP_PORT
+pub fn port_from_env() -> u16 {
+    std::env::var("HTTP_PORT")
+        .ok()
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(DEFAULT_HTTP_PORT)
+}
+
+async fn prices_to_json(publisher: &PricePublisher) -> serde_json::Result<String> {
+    let prices: HashMap<String, HashMap<String, SourcePrice>> = publisher
+        .get_latest_prices()
+        .await
+        .into_iter()
+        .map(|(symbol, sources)| {
+            let sources = sources
+                .into_iter()
+                .map(|(source, (price, timestamp))| (source, SourcePrice { price, timestamp }))
+                .collect();
+            (symbol, sources)
+        })
+        .collect();
+    serde_json::to_string(&prices)
+}
+
+async fn health_to_json(publisher: &PricePublisher) -> serde_json::Result<String> {
+    serde_json::to_string(&publisher.get_exchange_health().await)
+}
+
+fn metrics_response(body: Result<String>) -> Response<Body> {
+    match body {
+        Ok(text) => Response::builder()
+            .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(Body::from(text))
+            .unwrap(),
+        Err(e) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from(e.to_string()))
+            .unwrap(),
+    }
+}
+
+// Probe responses carry no body worth parsing, just the status
+fn probe_response(ok: bool) -> Response<Body> {
+    let (status, body) = if ok {
+        (StatusCode::OK, "ok")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "not ready")
+    };
+    Response::builder()
+        .status(status)
+        .body(Body::from(body))
+        .unwrap()
+}
+
+fn json_response(body: serde_json::Result<String>) -> Response<Body> {
+    match body {
+        Ok(json) => Response::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(json))
+            .unwrap(),
+        Err(e) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from(e.to_string()))
+            .unwrap(),
+    }
+}
+
+async fn handle_request(
+    publisher: Arc<PricePublisher>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/prices") => json_response(prices_to_json(&publisher).await),
+        (&Method::GET, "/health") => json_response(health_to_json(&publisher).await),
+        (&Method::GET, "/metrics") => metrics_response(publisher.render_metrics().await),
+        // Answered by the same runtime as the feeds, so a response means the
+        // event loop is turning
+        (&Method::GET, "/live") => probe_response(true),
+        (&Method::GET, "/ready") => probe_response(publisher.is_ready().await),
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap(),
+    };
+    Ok(response)
+}
+
+pub async fn serve(publisher: Arc<PricePublisher>, port: u16) -> Result<()> {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+
+    let make_service = make_service_fn(move |_| {
+        let publisher = publisher.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                handle_request(publisher.clone(), req)
+            }))
+        }
+    });
+
+    info!("HTTP server listening on {}", addr);
+    Server::bind(&addr).serve(make_service).await?;
+    Ok(())
+}
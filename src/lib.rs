@@ -0,0 +1,12 @@
+pub mod clock;
+pub mod config;
+pub mod exchanges;
+pub mod http_server;
+pub mod log_file;
+pub mod metrics;
+pub mod publisher;
+pub mod queue;
+pub mod redis_client;
+pub mod sink;
+pub mod task;
+pub mod types;
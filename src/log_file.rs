@@ -0,0 +1,83 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// An append-only log file that rolls over by size.
+///
+/// Once a write would take the file past `max_bytes`, it is renamed to
+/// `{path}.1`, any `{path}.1` to `{path}.2` and so on, and a fresh file is
+/// started. At most `max_files` rolled files are kept; the oldest is deleted.
+/// A single write larger than `max_bytes` still goes through whole, so lines
+/// are never split across files.
+pub struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: File,
+    // Bytes in the current file, including what was there when it was opened
+    size: u64,
+}
+
+impl RotatingFile {
+    pub fn open(path: impl Into<PathBuf>, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        let path = path.into();
+        let file = Self::open_append(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_bytes,
+            max_files,
+            file,
+            size,
+        })
+    }
+
+    fn open_append(path: &Path) -> io::Result<File> {
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+
+    fn rolled_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            // Nothing to keep; start the file over
+            self.file = File::create(&self.path)?;
+        } else {
+            let oldest = self.rolled_path(self.max_files);
+            if oldest.exists() {
+                fs::remove_file(&oldest)?;
+            }
+            for index in (1..self.max_files).rev() {
+                let from = self.rolled_path(index);
+                if from.exists() {
+                    fs::rename(&from, self.rolled_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rolled_path(1))?;
+            self.file = Self::open_append(&self.path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        // Whole, so a partial write can't be followed by a rotation mid-line
+        self.file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
@@ -1,21 +1,121 @@
-use anyhow::Result;
-use chrono::Local;
+use anyhow::{anyhow, Result};
+use chrono::{Local, SecondsFormat};
+use clap::Parser;
 use env_logger::Builder;
+use log::kv::{self, Key, Value, VisitSource};
 use log::{info, warn, LevelFilter};
 use redis::AsyncCommands;
-use std::fs::{self, OpenOptions};
+use rust_decimal::Decimal;
+use std::fs;
 use std::io::Write;
+use std::str::FromStr;
 use std::sync::Arc;
 use tokio::{
     self,
-    time::{sleep, Duration},
+    sync::{broadcast::error::RecvError, watch},
+    time::{sleep, timeout, Duration},
 };
 
-mod exchanges;
-mod publisher;
-mod types;
+use price_publisher::log_file::RotatingFile;
+use price_publisher::redis_client::{RedisClient, RedisConnection};
+use price_publisher::task::spawn_named;
+use price_publisher::types::{Exchange, RedisKeyFormat, TradingPair};
+use price_publisher::{config, http_server, publisher};
 
-fn init_logger() {
+// How long to wait for websockets to close and Redis to flush on Ctrl+C
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+// How often the LIVENESS_FILE is rewritten while prices are flowing
+const LIVENESS_INTERVAL: Duration = Duration::from_secs(5);
+// An exchange only keeps the liveness file fresh if it sent a price this recently
+const LIVENESS_MAX_PRICE_AGE: Duration = Duration::from_secs(30);
+// Redis set of symbols for the monitor to watch, editable live with SADD/SREM
+const MONITOR_SYMBOLS_KEY: &str = "config:symbols";
+// Log file size cap and rolled files kept, unless LOG_MAX_SIZE_MB and
+// LOG_MAX_FILES say otherwise
+const DEFAULT_LOG_MAX_SIZE_MB: u64 = 100;
+const DEFAULT_LOG_MAX_FILES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    // LOG_FORMAT=json switches to one JSON object per line for log shippers
+    fn from_env() -> Self {
+        match std::env::var("LOG_FORMAT") {
+            Ok(format) if format.eq_ignore_ascii_case("json") => LogFormat::Json,
+            _ => LogFormat::Text,
+        }
+    }
+}
+
+// Copies a record's key-value pairs into a JSON log line
+struct JsonFields<'a>(&'a mut serde_json::Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        self.0.insert(key.to_string(), value.to_string().into());
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+#[command(about = "Publishes exchange prices to Redis")]
+struct Cli {
+    /// Symbol to track, e.g. BTCUSDT (repeatable; replaces the configured pairs)
+    #[arg(long = "symbol")]
+    symbols: Vec<String>,
+    /// Exchange to start, e.g. binance (repeatable; replaces the configured exchanges)
+    #[arg(long = "exchange")]
+    exchanges: Vec<Exchange>,
+    #[arg(long, default_value = "info")]
+    log_level: LevelFilter,
+    #[arg(long)]
+    redis_url: Option<String>,
+    /// Print one price per symbol as JSON and exit instead of running forever
+    #[arg(long)]
+    once: bool,
+    /// How long --once waits for every symbol to have a price
+    #[arg(long, default_value_t = 30)]
+    once_timeout_secs: u64,
+}
+
+// Quotes used to split --symbol values back into base and quote
+const QUOTE_HINTS: &[&str] = &["USDT", "USDC", "USD", "EUR", "BTC", "ETH"];
+
+impl Cli {
+    fn apply(&self, config: &mut config::Config) -> Result<()> {
+        if !self.symbols.is_empty() {
+            config.trading_pairs = self
+                .symbols
+                .iter()
+                .map(|symbol| {
+                    TradingPair::from_symbol(symbol, QUOTE_HINTS)
+                        .ok_or_else(|| anyhow!("Can't split {} into base and quote", symbol))
+                })
+                .collect::<Result<_>>()?;
+        }
+        if !self.exchanges.is_empty() {
+            config.exchanges = self.exchanges.clone();
+        }
+        if let Some(redis_url) = &self.redis_url {
+            config.redis_url = redis_url.clone();
+        }
+        Ok(())
+    }
+}
+
+// Parses env var `name`, or `default` when unset or invalid
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn init_logger(level: LevelFilter) {
     // Create the base logs directory if it doesn't exist
     let logs_dir = "logs";
     fs::create_dir_all(logs_dir).expect("Failed to create logs directory");
@@ -27,49 +127,111 @@ fn init_logger() {
     // Create the log file path
     let filename = format!("{}/price_publisher.out", date_dir,);
 
-    let file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(filename)
+    let max_size_mb = env_or("LOG_MAX_SIZE_MB", DEFAULT_LOG_MAX_SIZE_MB);
+    let max_files = env_or("LOG_MAX_FILES", DEFAULT_LOG_MAX_FILES);
+    let file = RotatingFile::open(filename, max_size_mb.saturating_mul(1024 * 1024), max_files)
         .expect("Failed to open log file");
 
+    let format = LogFormat::from_env();
+
     Builder::new()
-        .format(|buf, record| {
-            writeln!(
+        .format(move |buf, record| match format {
+            LogFormat::Text => writeln!(
                 buf,
                 "{} [{}] - {}",
                 Local::now().format("%Y%m%d %H:%M:%S%.6f"),
                 record.level(),
                 record.args()
-            )
+            ),
+            LogFormat::Json => {
+                let mut line = serde_json::Map::new();
+                line.insert(
+                    "timestamp".to_string(),
+                    Local::now()
+                        .to_rfc3339_opts(SecondsFormat::Micros, true)
+                        .into(),
+                );
+                line.insert("level".to_string(), record.level().as_str().into());
+                line.insert("target".to_string(), record.target().into());
+                line.insert("message".to_string(), record.args().to_string().into());
+                // Structured fields such as `exchange` and `symbol`
+                let _ = record.key_values().visit(&mut JsonFields(&mut line));
+                writeln!(buf, "{}", serde_json::Value::Object(line))
+            }
         })
-        .filter(None, LevelFilter::Info)
+        .filter(None, level)
         .target(env_logger::Target::Pipe(Box::new(file)))
         .init();
 }
 
-async fn monitor_redis_updates(redis_client: redis::Client, symbols: Vec<String>) -> Result<()> {
-    let mut conn = redis_client.get_async_connection().await?;
-    let mut last_prices: std::collections::HashMap<String, f64> = std::collections::HashMap::new();
+// Pairs named in MONITOR_SYMBOLS_KEY, as `BTCUSDT` or `BTC/USDT`. Entries
+// that can't be split into base and quote are warned about once.
+async fn monitored_pairs(
+    conn: &mut RedisConnection,
+    unparseable: &mut std::collections::HashSet<String>,
+) -> Result<Vec<TradingPair>> {
+    let members: Vec<String> = conn.smembers(MONITOR_SYMBOLS_KEY).await?;
+    let mut pairs: Vec<TradingPair> = members
+        .into_iter()
+        .filter_map(|symbol| {
+            let pair = symbol
+                .parse()
+                .ok()
+                .or_else(|| TradingPair::from_symbol(&symbol, QUOTE_HINTS));
+            if pair.is_none() && unparseable.insert(symbol.clone()) {
+                warn!(
+                    "Ignoring {} in {}: can't split it into base and quote",
+                    symbol, MONITOR_SYMBOLS_KEY
+                );
+            }
+            pair
+        })
+        .collect();
+    pairs.sort_by_key(|pair| pair.to_canonical_symbol());
+    pairs.dedup();
+    Ok(pairs)
+}
+
+// Reads back the keys the publisher writes, named with the same `key_format`.
+// The symbols in MONITOR_SYMBOLS_KEY are re-read every pass; while the set
+// is empty or missing, `default_pairs` are watched.
+async fn monitor_redis_updates(
+    redis_client: RedisClient,
+    default_pairs: Vec<TradingPair>,
+    key_format: RedisKeyFormat,
+) -> Result<()> {
+    let mut conn = redis_client.get_connection().await?;
+    let mut last_prices: std::collections::HashMap<String, Decimal> =
+        std::collections::HashMap::new();
+    let mut unparseable = std::collections::HashSet::new();
 
     loop {
-        for symbol in &symbols {
-            let redis_key = format!("price:{}", symbol);
+        let mut pairs = monitored_pairs(&mut conn, &mut unparseable).await?;
+        if pairs.is_empty() {
+            pairs = default_pairs.clone();
+        }
+
+        for pair in &pairs {
+            let symbol = &pair.to_canonical_symbol();
+            let redis_key = key_format.price_key(pair);
             let price: Option<String> = conn.get(&redis_key).await?;
 
             if let Some(price_str) = price {
-                if let Ok(price) = price_str.parse::<f64>() {
+                if let Ok(price) = Decimal::from_str(&price_str) {
                     if let Some(last_price) = last_prices.get(symbol) {
-                        let change = ((price - last_price) / last_price * 100.0).abs();
-                        if change > 0.1 {
+                        let change = (price - last_price)
+                            .checked_div(*last_price)
+                            .map(|ratio| (ratio * Decimal::ONE_HUNDRED).abs())
+                            .unwrap_or_default();
+                        if change > Decimal::new(1, 1) {
                             // Log if price changed more than 0.1%
                             info!(
-                                "{}: {:.8} (changed {:.2}% from {:.8})",
+                                "{}: {} (changed {:.2}% from {})",
                                 symbol, price, change, last_price
                             );
                         }
                     } else {
-                        info!("Initial {} price: {:.8}", symbol, price);
+                        info!("Initial {} price: {}", symbol, price);
                     }
                     last_prices.insert(symbol.clone(), price);
                 }
@@ -78,7 +240,7 @@ async fn monitor_redis_updates(redis_client: redis::Client, symbols: Vec<String>
             }
 
             // Also read and log the sources
-            let sources_key = format!("price:{}:sources", symbol);
+            let sources_key = format!("{}:sources", redis_key);
             let sources: Option<String> = conn.get(&sources_key).await?;
             if let Some(sources) = sources {
                 info!("{} sources: {}", symbol, sources);
@@ -88,6 +250,22 @@ async fn monitor_redis_updates(redis_client: redis::Client, symbols: Vec<String>
     }
 }
 
+// Logs each exchange connecting or disconnecting as it happens, between the
+// periodic health reports
+async fn log_health_events(publisher: Arc<publisher::PricePublisher>) {
+    let mut events = publisher.health_events();
+    loop {
+        match events.recv().await {
+            Ok(event) if event.connected => info!("{} connected", event.exchange),
+            Ok(event) => warn!("{} disconnected", event.exchange),
+            Err(RecvError::Lagged(missed)) => {
+                warn!("Missed {} exchange health events", missed)
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
 async fn monitor_exchange_health(publisher: Arc<publisher::PricePublisher>) {
     let mut interval = tokio::time::interval(Duration::from_secs(10));
     loop {
@@ -98,10 +276,17 @@ async fn monitor_exchange_health(publisher: Arc<publisher::PricePublisher>) {
         info!("\n=== Exchange Health Report ===");
         for (exchange, metrics) in health {
             info!(
-                "{}: Connected={}, Errors={}, Last Update={:?}",
+                "{}: Connected={}, Errors={}, Reconnects={}, Connected Since={:?}, Latency={}ms (avg {}ms), Last Update={:?}",
                 exchange,
                 metrics.is_connected,
                 metrics.error_count,
+                metrics.reconnect_count,
+                metrics.connected_since.map(|since| since
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs()),
+                metrics.last_message_latency.as_millis(),
+                metrics.avg_message_latency.as_millis(),
                 metrics
                     .last_update
                     .duration_since(std::time::UNIX_EPOCH)
@@ -114,46 +299,128 @@ async fn monitor_exchange_health(publisher: Arc<publisher::PricePublisher>) {
         for (symbol, sources) in prices {
             info!("{}:", symbol);
             for (source, (price, timestamp)) in sources {
-                let age = std::time::SystemTime::now()
-                    .duration_since(timestamp)
-                    .unwrap()
-                    .as_secs();
-                info!("  {}: {:.8} ({}s old)", source, price, age);
+                let age = publisher.age_of(timestamp).as_secs();
+                info!("  {}: {} ({}s old)", source, price, age);
+            }
+            if let Some(consensus) = publisher.get_consensus_price(&symbol).await {
+                info!("  consensus: {}", consensus);
             }
         }
         info!("===========================\n");
     }
 }
 
+// Writes the current unix time to `path` every LIVENESS_INTERVAL while at
+// least one exchange is connected and sending prices, so a supervisor can
+// restart the process once the file's mtime goes stale
+async fn write_liveness_file(publisher: Arc<publisher::PricePublisher>, path: String) {
+    let mut interval = tokio::time::interval(LIVENESS_INTERVAL);
+    let mut was_live = true;
+    loop {
+        interval.tick().await;
+        let live = publisher.is_sending_prices(LIVENESS_MAX_PRICE_AGE).await;
+        if live != was_live {
+            if live {
+                info!("Prices flowing again, resuming writes to {}", path);
+            } else {
+                warn!("No exchange is sending prices, no longer touching {}", path);
+            }
+            was_live = live;
+        }
+        if !live {
+            continue;
+        }
+        if let Err(e) = tokio::fs::write(&path, Local::now().timestamp().to_string()).await {
+            warn!("Failed to write liveness file {}: {}", path, e);
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
     // Initialize logging
-    init_logger();
+    init_logger(cli.log_level);
+
+    // Serves task instrumentation on 127.0.0.1:6669 (TOKIO_CONSOLE_BIND to
+    // change) for `tokio-console`
+    #[cfg(feature = "tokio-console")]
+    console_subscriber::ConsoleLayer::builder()
+        .with_default_env()
+        .init();
 
     info!("Starting price publisher test app...");
 
+    // Load trading pairs and enabled exchanges
+    let mut config = config::Config::load(config::DEFAULT_CONFIG_PATH)?;
+    cli.apply(&mut config)?;
+
+    // NO_REDIS=1 runs the feeds and aggregation without a Redis server
+    let no_redis = std::env::var("NO_REDIS").is_ok_and(|value| value == "1");
+
     // Create the publisher
-    let publisher = Arc::new(publisher::PricePublisher::new().await?);
+    let publisher = if no_redis {
+        Arc::new(publisher::PricePublisher::new_without_redis(&config).await?)
+    } else {
+        Arc::new(publisher::PricePublisher::new(&config).await?)
+    };
+
+    if cli.once {
+        let prices = publisher
+            .snapshot(
+                &config.symbols(),
+                Duration::from_secs(cli.once_timeout_secs),
+            )
+            .await?;
+        println!("{}", serde_json::to_string(&prices)?);
+        return Ok(());
+    }
 
     // Get Redis client for monitoring
-    let redis_url = "redis://127.0.0.1/";
-    let redis_client = redis::Client::open(redis_url)?;
+    let redis_client = if no_redis {
+        None
+    } else {
+        Some(RedisClient::open(&config.redis_url)?)
+    };
 
-    // Define symbols to monitor
-    let symbols = vec![
-        "BTCUSDT".to_string(),
-        "ETHUSDT".to_string(),
-        "SOLUSDT".to_string(),
-    ];
+    // Monitor the same pairs the publisher tracks, under the same key names
+    let pairs = config.trading_pairs.clone();
+    let key_format = config.redis_key_format();
 
     // Spawn monitoring tasks
-    let redis_monitor = tokio::spawn(monitor_redis_updates(redis_client, symbols));
+    let redis_monitor = spawn_named("redis-monitor", async move {
+        match redis_client {
+            Some(redis_client) => monitor_redis_updates(redis_client, pairs, key_format).await,
+            // Nothing to monitor without Redis
+            None => std::future::pending().await,
+        }
+    });
+    let publisher_clone = publisher.clone();
+    let health_events = spawn_named("health-events", log_health_events(publisher_clone));
     let publisher_clone = publisher.clone();
-    let health_monitor = tokio::spawn(monitor_exchange_health(publisher_clone));
+    let health_monitor = spawn_named("health-monitor", monitor_exchange_health(publisher_clone));
+
+    // LIVENESS_FILE=/path is touched while prices are flowing, for external watchdogs
+    let liveness_file = std::env::var("LIVENESS_FILE").ok();
+    let publisher_clone = publisher.clone();
+    let liveness_writer = spawn_named("liveness-writer", async move {
+        match liveness_file {
+            Some(path) => write_liveness_file(publisher_clone, path).await,
+            None => std::future::pending().await,
+        }
+    });
+
+    // Serve latest prices and health over HTTP
+    let http_server = spawn_named(
+        "http-server",
+        http_server::serve(publisher.clone(), http_server::port_from_env()),
+    );
 
     // Run the publisher
-    let publisher_handle = tokio::spawn(async move {
-        if let Err(e) = publisher.run().await {
+    let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+    let mut publisher_handle = spawn_named("publisher", async move {
+        if let Err(e) = publisher.run(shutdown_receiver).await {
             warn!("Publisher exited with error: {}", e);
         }
     });
@@ -171,10 +438,30 @@ async fn main() -> Result<()> {
         _ = health_monitor => {
             warn!("Health monitor exited unexpectedly");
         }
-        _ = publisher_handle => {
+        _ = health_events => {
+            warn!("Health event logger exited unexpectedly");
+        }
+        _ = liveness_writer => {
+            warn!("Liveness file writer exited unexpectedly");
+        }
+        result = http_server => {
+            warn!("HTTP server exited unexpectedly: {:?}", result);
+        }
+        _ = &mut publisher_handle => {
             warn!("Publisher exited unexpectedly");
+            return Ok(());
         }
     }
 
+    // Signal shutdown and let the publisher close websockets and flush Redis
+    let _ = shutdown_sender.send(true);
+    match timeout(SHUTDOWN_TIMEOUT, publisher_handle).await {
+        Ok(_) => info!("Publisher shut down cleanly"),
+        Err(_) => warn!(
+            "Publisher did not shut down within {}s",
+            SHUTDOWN_TIMEOUT.as_secs()
+        ),
+    }
+
     Ok(())
 }
@@ -0,0 +1,116 @@
+use anyhow::Result;
+use prometheus::{
+    Encoder, Gauge, GaugeVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use rust_decimal::prelude::ToPrimitive;
+use std::time::Duration;
+
+use crate::publisher::ExchangeHealth;
+use crate::types::PriceUpdate;
+
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    price_updates_total: IntCounterVec,
+    price_last: GaugeVec,
+    price_age_seconds: GaugeVec,
+    exchange_connected: IntGaugeVec,
+    exchange_error_count: IntGaugeVec,
+    queue_utilization: Gauge,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self> {
+        let registry = Registry::new();
+
+        let price_updates_total = IntCounterVec::new(
+            Opts::new("price_updates_total", "Price updates received"),
+            &["exchange", "symbol"],
+        )?;
+        let price_last = GaugeVec::new(
+            Opts::new("price_last", "Last price received"),
+            &["exchange", "symbol"],
+        )?;
+        let price_age_seconds = GaugeVec::new(
+            Opts::new(
+                "price_age_seconds",
+                "Seconds since the source last updated the price",
+            ),
+            &["exchange", "symbol"],
+        )?;
+        let exchange_connected = IntGaugeVec::new(
+            Opts::new(
+                "exchange_connected",
+                "Whether the exchange feed is connected",
+            ),
+            &["exchange"],
+        )?;
+        let exchange_error_count = IntGaugeVec::new(
+            Opts::new("exchange_error_count", "Consecutive exchange feed errors"),
+            &["exchange"],
+        )?;
+
+        let queue_utilization = Gauge::new(
+            "price_queue_utilization",
+            "Fraction of the price update queue in use",
+        )?;
+
+        registry.register(Box::new(price_updates_total.clone()))?;
+        registry.register(Box::new(price_last.clone()))?;
+        registry.register(Box::new(price_age_seconds.clone()))?;
+        registry.register(Box::new(exchange_connected.clone()))?;
+        registry.register(Box::new(exchange_error_count.clone()))?;
+        registry.register(Box::new(queue_utilization.clone()))?;
+
+        Ok(Self {
+            registry,
+            price_updates_total,
+            price_last,
+            price_age_seconds,
+            exchange_connected,
+            exchange_error_count,
+            queue_utilization,
+        })
+    }
+
+    pub fn record_price_update(&self, update: &PriceUpdate) {
+        let labels = [update.source.as_str(), update.symbol.as_str()];
+        self.price_updates_total.with_label_values(&labels).inc();
+        if let Some(price) = update.price.to_f64() {
+            self.price_last.with_label_values(&labels).set(price);
+        }
+    }
+
+    pub fn record_price_age(&self, exchange: &str, symbol: &str, age: Duration) {
+        self.price_age_seconds
+            .with_label_values(&[exchange, symbol])
+            .set(age.as_secs_f64());
+    }
+
+    // Drops an evicted source's per-symbol series so it doesn't linger
+    pub fn remove_price_source(&self, exchange: &str, symbol: &str) {
+        let labels = [exchange, symbol];
+        let _ = self.price_last.remove_label_values(&labels);
+        let _ = self.price_age_seconds.remove_label_values(&labels);
+    }
+
+    pub fn record_exchange_health(&self, exchange: &str, health: &ExchangeHealth) {
+        self.exchange_connected
+            .with_label_values(&[exchange])
+            .set(health.is_connected as i64);
+        self.exchange_error_count
+            .with_label_values(&[exchange])
+            .set(health.error_count as i64);
+    }
+
+    pub fn record_queue_utilization(&self, utilization: f64) {
+        self.queue_utilization.set(utilization);
+    }
+
+    // Renders all registered metrics in the Prometheus text exposition format
+    pub fn encode(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
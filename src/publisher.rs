@@ -1,90 +1,428 @@
 use anyhow::{anyhow, Result};
-use log::{error, info, warn};
-use redis::AsyncCommands;
-use tokio::sync::mpsc;
+use log::{debug, error, info, warn};
+use rand::Rng;
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use tokio::sync::{broadcast, mpsc, watch, Mutex};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::RwLock;
 use tokio::time::interval;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
 
+use crate::clock::{system_clock, SharedClock};
+use crate::config::{Config, HealthConfig};
+use crate::exchanges::error::ExchangeError;
+use crate::exchanges::remap::SymbolRemaps;
+use crate::exchanges::ticks::TICK_INTERVAL_SECS;
+use crate::exchanges::ws_stream::ConnState;
 use crate::exchanges::{self, Exchange, ExchangeImpl};
-use crate::types::{self, PriceUpdate, TradingPair};
+use crate::metrics::Metrics;
+use crate::queue::UpdateQueue;
+use crate::sink::{
+    additional_sinks, read_wal_tail, NullSink, PriceBatch, PriceSink, RedisSink, SymbolSnapshot,
+};
+use crate::task::spawn_named;
+use crate::types::{
+    serialize_duration_millis, serialize_optional_unix_timestamp, serialize_unix_timestamp,
+    FundingUpdate, OhlcBar, PriceKind, PriceMeta, PriceUpdate, TradingPair,
+};
 
 const CHANNEL_SIZE: usize = 1000;
-const REDIS_PRICE_EXPIRY: usize = 60; // 60 seconds
-const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
-const STALE_PRICE_THRESHOLD: Duration = Duration::from_secs(30);
+// How much of the end of the WAL is read back on startup
+const WAL_RECOVERY_BYTES: u64 = 1024 * 1024;
+// Queue fill level past which the health check warns
+const QUEUE_WARN_UTILIZATION: f64 = 0.8;
+// Updates a `subscribe` receiver can fall behind by before it starts missing them
+const BROADCAST_CAPACITY: usize = 1000;
+// Transitions a `health_events` receiver can fall behind by
+const HEALTH_EVENT_CAPACITY: usize = 100;
+const LATENCY_EWMA_ALPHA: f64 = 0.1;
+// How often each exchange may be warned about clock skew
+const CLOCK_SKEW_WARN_INTERVAL: Duration = Duration::from_secs(60);
+const OHLC_INTERVAL: Duration = Duration::from_secs(60);
+// Updates arriving within this window are flushed to the sink in one batch
+const SINK_BATCH_WINDOW: Duration = Duration::from_millis(50);
+// How often `snapshot` checks whether every requested symbol has a price
+const SNAPSHOT_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
-#[derive(Debug, Clone)]
+// Latest price and receive time, keyed by source exchange
+type SourcePrices = HashMap<String, (Decimal, SystemTime)>;
+// Latest bid and ask behind each source's price, where the feed has them
+type SourceQuotes = HashMap<String, (Option<Decimal>, Option<Decimal>)>;
+
+fn is_fresh(timestamp: SystemTime, now: SystemTime, stale_after: Duration) -> bool {
+    now.duration_since(timestamp)
+        .map(|elapsed| elapsed <= stale_after)
+        .unwrap_or(true)
+}
+
+// Prices fresher than `stale_after`, optionally leaving out one source
+fn fresh_prices(
+    sources: &SourcePrices,
+    exclude_source: Option<&str>,
+    stale_after: Duration,
+    now: SystemTime,
+) -> Vec<Decimal> {
+    sources
+        .iter()
+        .filter(|(source, _)| Some(source.as_str()) != exclude_source)
+        .filter(|(_, (_, timestamp))| is_fresh(*timestamp, now, stale_after))
+        .map(|(_, (price, _))| *price)
+        .collect()
+}
+
+// Start of the OHLC_INTERVAL bar containing `timestamp`
+fn bar_start(timestamp: SystemTime) -> SystemTime {
+    let since_epoch = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let interval = OHLC_INTERVAL.as_secs();
+    UNIX_EPOCH + Duration::from_secs(since_epoch.as_secs() / interval * interval)
+}
+
+fn deviation_pct(price: Decimal, reference: Decimal) -> Option<Decimal> {
+    (price - reference)
+        .checked_div(reference)
+        .map(|ratio| (ratio * Decimal::ONE_HUNDRED).abs())
+}
+
+fn median(prices: &mut [Decimal]) -> Option<Decimal> {
+    if prices.is_empty() {
+        return None;
+    }
+    prices.sort();
+    let mid = prices.len() / 2;
+    if prices.len().is_multiple_of(2) {
+        Some((prices[mid - 1] + prices[mid]) / Decimal::TWO)
+    } else {
+        Some(prices[mid])
+    }
+}
+
+// Median after dropping the single price furthest from the median, so one bad
+// feed can't drag it; the plain median with fewer than three prices
+fn trimmed_median(prices: &mut Vec<Decimal>) -> Option<Decimal> {
+    let center = median(prices)?;
+    if prices.len() >= 3 {
+        let worst = prices
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, price)| (**price - center).abs())
+            .map(|(index, _)| index)?;
+        prices.remove(worst);
+        return median(prices);
+    }
+    Some(center)
+}
+
+// Exponential backoff from the configured base delay, capped at its max
+// delay, with the upper half randomized so exchanges don't reconnect in
+// lockstep
+fn reconnect_delay(health: &HealthConfig, consecutive_failures: u32) -> Duration {
+    let backoff = health
+        .reconnect_base_delay()
+        .saturating_mul(2u32.saturating_pow(consecutive_failures))
+        .min(health.reconnect_max_delay());
+    let half = backoff / 2;
+    half + half.mul_f64(rand::thread_rng().gen::<f64>())
+}
+
+// Window the exchanges' first connects are spread over on startup
+const STARTUP_STAGGER_WINDOW: Duration = Duration::from_secs(2);
+
+// Delay before the `index`th of `count` exchanges first connects: its slot
+// of STARTUP_STAGGER_WINDOW plus jitter within the slot, so a restart
+// doesn't open every DNS lookup and TLS handshake at once. The first
+// exchange connects straight away.
+fn startup_delay(index: usize, count: usize) -> Duration {
+    if index == 0 || count == 0 {
+        return Duration::ZERO;
+    }
+    let slot = STARTUP_STAGGER_WINDOW / count as u32;
+    slot * index as u32 + slot.mul_f64(rand::thread_rng().gen::<f64>())
+}
+
+// Extra doublings of backoff after a connection couldn't be established,
+// since DNS and TLS problems rarely clear up within seconds
+const CONNECT_FAILURE_BACKOFF_STEPS: u32 = 2;
+// Extra doublings after a policy or application close (1008, 4000-4999);
+// reconnecting straight away would likely be refused the same way
+const POLICY_CLOSE_BACKOFF_STEPS: u32 = 5;
+
+/// How a listener's exit should be handled by the reconnect loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedFailure {
+    // The server closed with 1000 or 1001, e.g. a scheduled restart, or we
+    // dropped the connection to change subscriptions; reconnect right away
+    ClosedNormally,
+    // The server closed over policy or auth (1008, 4000-4999)
+    ClosedByPolicy,
+    // The server hung up; routine for long-lived sockets, not a fault
+    Disconnected,
+    // The connection couldn't be established (DNS, TCP, TLS, timeout)
+    ConnectFailed,
+    // Anything else: protocol errors, rejected subscriptions, ping timeouts
+    Fault,
+}
+
+impl FeedFailure {
+    pub fn classify(error: &ExchangeError) -> Self {
+        match error {
+            ExchangeError::ServerClose(_) => match error.close_code() {
+                Some(CloseCode::Normal | CloseCode::Away) => FeedFailure::ClosedNormally,
+                Some(CloseCode::Policy | CloseCode::Library(4000..=4999)) => {
+                    FeedFailure::ClosedByPolicy
+                }
+                _ => FeedFailure::Disconnected,
+            },
+            ExchangeError::StreamEnded => FeedFailure::Disconnected,
+            ExchangeError::PairsChanged => FeedFailure::ClosedNormally,
+            ExchangeError::Connect(_) | ExchangeError::ConnectTimeout => FeedFailure::ConnectFailed,
+            _ => FeedFailure::Fault,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct ExchangeHealth {
+    #[serde(serialize_with = "serialize_unix_timestamp")]
     pub last_update: SystemTime,
     pub is_connected: bool,
     pub error_count: u32,
+    // Exchange event time to receipt; only tracked for feeds that send one
+    #[serde(serialize_with = "serialize_duration_millis")]
+    pub last_message_latency: Duration,
+    #[serde(serialize_with = "serialize_duration_millis")]
+    pub avg_message_latency: Duration,
+    // Times the listener has been restarted after its first connection
+    pub reconnect_count: u32,
+    // Receipt of the first price on the current connection; None while down
+    #[serde(serialize_with = "serialize_optional_unix_timestamp")]
+    pub connected_since: Option<SystemTime>,
+    // Price updates the feed sent in the last `TICK_INTERVAL_SECS`; zero on
+    // a connected feed means its subscription has gone quiet
+    pub ticks_last_interval: u64,
+    // Basis points this exchange's fresh price is off the reference
+    // exchange's, per symbol; empty without a reference
+    pub reference_deviation_bps: HashMap<String, Decimal>,
+    // Updates from this exchange waiting to be processed
+    pub queued_updates: usize,
+    // Current websocket state, e.g. `subscribing` for a feed whose
+    // subscription was never confirmed
+    pub ws_state: ConnState,
+}
+
+/// An exchange going from disconnected to connected or back, as sent to
+/// `PricePublisher::health_events` receivers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HealthEvent {
+    pub exchange: String,
+    pub connected: bool,
+    #[serde(serialize_with = "serialize_unix_timestamp")]
+    pub at: SystemTime,
+}
+
+// Sink writes held back by `min_write_interval_ms`
+#[derive(Default)]
+struct WriteThrottle {
+    last_write: HashMap<String, Instant>,
+    // Latest update per source and kind for each symbol waiting on its interval
+    held: HashMap<String, Vec<PriceUpdate>>,
 }
 
+// All shared state is behind Arc so clones can be moved into spawned tasks
+#[derive(Clone)]
 pub struct PricePublisher {
-    exchanges: Vec<Arc<ExchangeImpl>>,
-    redis_client: redis::Client,
+    // Initialized exchanges; ones that failed `init` join once a retry succeeds
+    exchanges: Arc<std::sync::RwLock<Vec<Arc<ExchangeImpl>>>>,
+    // Exchanges that failed `init`, taken by `run` to retry in the background
+    failed_exchanges: Arc<Mutex<Vec<ExchangeImpl>>>,
+    // Redis in production, plus NATS if configured; every batch goes to each.
+    // Shared so clones write through the same connections
+    sinks: Vec<Arc<dyn PriceSink>>,
     health_metrics: Arc<RwLock<HashMap<String, ExchangeHealth>>>,
-    latest_prices: Arc<RwLock<HashMap<String, HashMap<String, (f64, SystemTime)>>>>,
+    latest_prices: Arc<RwLock<HashMap<String, SourcePrices>>>,
+    latest_quotes: Arc<RwLock<HashMap<String, SourceQuotes>>>,
+    // Most recent last-trade price per symbol, across sources
+    latest_trades: Arc<RwLock<HashMap<String, (Decimal, SystemTime)>>>,
+    // Current one-minute bar per symbol
+    ohlc_bars: Arc<RwLock<HashMap<String, OhlcBar>>>,
+    // Exponential moving average of the published ticks per symbol, kept
+    // only when `ema_alpha` is set
+    ema_alpha: Option<Decimal>,
+    ema_state: Arc<RwLock<HashMap<String, Decimal>>>,
+    metrics: Metrics,
+    // Configured pairs plus any added with `add_pair`, and their canonical
+    // symbols; both are only written while holding the pairs lock
+    trading_pairs: Arc<std::sync::RwLock<Vec<TradingPair>>>,
+    tracked_symbols: Arc<std::sync::RwLock<HashSet<String>>>,
+    // Updates deviating from the other sources' median by more than this
+    // percentage are recorded but not published
+    pub outlier_threshold_pct: Decimal,
+    // Sources further apart than this halt `price:{symbol}`
+    pub max_source_spread_pct: Decimal,
+    // Updates whose bid and ask are further apart than this percentage, or
+    // locked or crossed, are dropped
+    pub max_book_spread_pct: Decimal,
+    halted_symbols: Arc<RwLock<HashSet<String>>>,
+    // Updates within this of the source's previous price aren't republished
+    pub duplicate_price_epsilon: Decimal,
+    // Exchanges in order of preference for `price:{symbol}`
+    pub source_priority: Vec<String>,
+    // Trust weight per exchange for the weighted consensus; median when empty
+    pub weights: HashMap<String, f64>,
+    // Fresh sources needed for a consensus to be published
+    pub min_consensus_sources: usize,
+    // Exchange the others' deviations are measured against
+    pub reference_source: Option<String>,
+    default_price_expiry: Duration,
+    // Receipt time and exchange event time further apart than this are
+    // warned about as clock skew
+    max_clock_skew: Duration,
+    // When each exchange was last warned about clock skew
+    clock_skew_warned: Arc<std::sync::Mutex<HashMap<String, Instant>>>,
+    // Sources silent for longer than this are evicted from latest_prices
+    source_max_age: Duration,
+    symbol_price_expiry: Arc<HashMap<String, Duration>>,
+    symbol_price_scale: Arc<HashMap<String, Decimal>>,
+    // Inclusive [min, max] per symbol; prices outside are dropped
+    price_bounds: Arc<HashMap<String, (Decimal, Decimal)>>,
+    symbol_price_decimals: Arc<HashMap<String, u32>>,
+    // Minimum time between sink writes for a symbol; zero writes every batch
+    default_write_interval: Duration,
+    symbol_write_interval: Arc<HashMap<String, Duration>>,
+    write_throttle: Arc<Mutex<WriteThrottle>>,
+    // Taken by `run`; only present when some exchange streams funding
+    funding_receiver: Arc<Mutex<Option<mpsc::Receiver<FundingUpdate>>>>,
+    // Feed updates waiting to be processed; drops stale ones when full
+    queue: Arc<UpdateQueue>,
+    // Every published update, for embedders reading prices in-process
+    update_sender: broadcast::Sender<PriceUpdate>,
+    // Every change in an exchange's `is_connected`
+    health_sender: broadcast::Sender<HealthEvent>,
+    // Wall-clock time for staleness and health; the system clock outside tests
+    clock: SharedClock,
+    // WAL whose tail seeds latest_prices when `run` starts
+    wal_path: Option<PathBuf>,
+    health: HealthConfig,
 }
 
 impl PricePublisher {
-    pub async fn new() -> Result<Self> {
-        // Initialize Redis client without authentication
-        let redis_url = "redis://127.0.0.1/";
-        let redis_client = redis::Client::open(redis_url)?;
-
-        // Test the connection
-        let mut conn = redis_client.get_async_connection().await?;
-        redis::cmd("PING").query_async(&mut conn).await?;
-        info!("Successfully connected to Redis");
-
-        // Define trading pairs to track
-        let trading_pairs = vec![
-            TradingPair::new("BTC", "USDT"),
-            TradingPair::new("ETH", "USDT"),
-            TradingPair::new("SOL", "USDT"),
-            TradingPair::new("USDC", "USDT"), // For Coinbase special case
-        ];
+    pub async fn new(config: &Config) -> Result<Self> {
+        let sink = RedisSink::connect(&config.redis_url)
+            .await?
+            .with_key_format(config.redis_key_format());
+        let mut sinks: Vec<Arc<dyn PriceSink>> = vec![Arc::new(sink)];
+        sinks.extend(additional_sinks(config).await?);
+        Self::with_sinks(config, sinks).await
+    }
+
+    /// A publisher that keeps the in-memory prices and health up to date but
+    /// never touches Redis, for developing without a Redis server.
+    pub async fn new_without_redis(config: &Config) -> Result<Self> {
+        warn!("Running without Redis, price writes will be skipped");
+        let mut sinks: Vec<Arc<dyn PriceSink>> = vec![Arc::new(NullSink)];
+        sinks.extend(additional_sinks(config).await?);
+        Self::with_sinks(config, sinks).await
+    }
+
+    /// A publisher that hands published prices to `sink` instead of Redis,
+    /// e.g. a `MemorySink` in tests.
+    pub async fn with_sink(config: &Config, sink: Arc<dyn PriceSink>) -> Result<Self> {
+        Self::with_sinks(config, vec![sink]).await
+    }
+
+    /// A publisher that fans every batch out to all of `sinks`.
+    pub async fn with_sinks(config: &Config, sinks: Vec<Arc<dyn PriceSink>>) -> Result<Self> {
+        Self::with_sinks_and_clock(config, sinks, system_clock()).await
+    }
+
+    /// A publisher, and its exchanges, that judge staleness and health by
+    /// `clock`, e.g. a `MockClock` tests can advance without sleeping.
+    pub async fn with_sinks_and_clock(
+        config: &Config,
+        sinks: Vec<Arc<dyn PriceSink>>,
+        clock: SharedClock,
+    ) -> Result<Self> {
+        let trading_pairs = config.trading_pairs.clone();
         info!("Initializing with trading pairs: {:?}", trading_pairs);
 
         // Initialize exchanges
         let mut exchanges: Vec<Arc<ExchangeImpl>> = Vec::new();
+        let mut failed_exchanges = Vec::new();
         let mut health_metrics = HashMap::new();
 
+        // Funding rates get their own channel so they never queue behind prices
+        let (funding_sender, funding_receiver) = if config.hyperliquid_funding {
+            let (sender, receiver) = mpsc::channel(CHANNEL_SIZE);
+            (Some(sender), Some(receiver))
+        } else {
+            (None, None)
+        };
+
+        let remaps = SymbolRemaps::new(&config.symbol_remaps);
+
         // Create exchange instances
-        let exchange_types = [
-            types::Exchange::Binance,
-            types::Exchange::Bybit,
-            types::Exchange::Coinbase,
-            types::Exchange::Hyperliquid,
-        ];
-
-        for exchange_type in exchange_types.iter() {
+        for exchange_type in config.exchanges.iter() {
             match exchanges::create_exchange(*exchange_type, trading_pairs.clone()).await {
                 Ok(mut exchange) => {
+                    exchange = exchange
+                        .with_clock(clock.clone())
+                        .with_heartbeat_timeout(config.health.heartbeat_timeout())
+                        .with_symbol_remaps(&remaps)
+                        .with_price_method(config.price_method)
+                        .with_book_depth(config.bybit_book_depth)
+                        .with_peg_fallbacks(&config.coinbase_peg_fallbacks);
+                    if let Some(depth) = config.depth_weighting {
+                        exchange = exchange.with_depth_weighting(depth);
+                    }
+                    if config.trade_prices {
+                        exchange = exchange.with_trades();
+                    }
+                    if let (Some(sender), crate::types::Exchange::Hyperliquid) =
+                        (&funding_sender, exchange_type)
+                    {
+                        exchange = exchange.with_funding(sender.clone());
+                    }
                     let exchange_name = exchange_type.as_str().to_string();
                     if let Err(e) = exchange.init().await {
-                        error!("Failed to initialize {}: {}", exchange_name, e);
+                        error!("Failed to initialize {}, will retry: {}", exchange_name, e);
                         health_metrics.insert(
                             exchange_name,
                             ExchangeHealth {
-                                last_update: SystemTime::now(),
+                                last_update: clock.now(),
                                 is_connected: false,
                                 error_count: 1,
+                                last_message_latency: Duration::ZERO,
+                                avg_message_latency: Duration::ZERO,
+                                reconnect_count: 0,
+                                connected_since: None,
+                                ticks_last_interval: 0,
+                                reference_deviation_bps: HashMap::new(),
+                                queued_updates: 0,
+                                ws_state: ConnState::Closed,
                             },
                         );
+                        failed_exchanges.push(exchange);
                         continue;
                     }
                     health_metrics.insert(
                         exchange_name,
                         ExchangeHealth {
-                            last_update: SystemTime::now(),
+                            last_update: clock.now(),
                             is_connected: true,
                             error_count: 0,
+                            last_message_latency: Duration::ZERO,
+                            avg_message_latency: Duration::ZERO,
+                            reconnect_count: 0,
+                            connected_since: None,
+                            ticks_last_interval: 0,
+                            reference_deviation_bps: HashMap::new(),
+                            queued_updates: 0,
+                            ws_state: ConnState::Closed,
                         },
                     );
                     exchanges.push(Arc::new(exchange));
@@ -94,32 +432,373 @@ impl PricePublisher {
                     health_metrics.insert(
                         exchange_type.as_str().to_string(),
                         ExchangeHealth {
-                            last_update: SystemTime::now(),
+                            last_update: clock.now(),
                             is_connected: false,
                             error_count: 1,
+                            last_message_latency: Duration::ZERO,
+                            avg_message_latency: Duration::ZERO,
+                            reconnect_count: 0,
+                            connected_since: None,
+                            ticks_last_interval: 0,
+                            reference_deviation_bps: HashMap::new(),
+                            queued_updates: 0,
+                            ws_state: ConnState::Closed,
                         },
                     );
                 }
             }
         }
 
-        if exchanges.is_empty() {
+        // Exchanges that only failed `init` can still come up on a retry
+        if exchanges.is_empty() && failed_exchanges.is_empty() {
             return Err(anyhow!("No exchanges were successfully initialized"));
         }
 
         Ok(Self {
-            exchanges,
-            redis_client,
+            exchanges: Arc::new(std::sync::RwLock::new(exchanges)),
+            failed_exchanges: Arc::new(Mutex::new(failed_exchanges)),
+            sinks,
             health_metrics: Arc::new(RwLock::new(health_metrics)),
             latest_prices: Arc::new(RwLock::new(HashMap::new())),
+            latest_quotes: Arc::new(RwLock::new(HashMap::new())),
+            latest_trades: Arc::new(RwLock::new(HashMap::new())),
+            ohlc_bars: Arc::new(RwLock::new(HashMap::new())),
+            ema_alpha: config.ema_alpha,
+            ema_state: Arc::new(RwLock::new(HashMap::new())),
+            metrics: Metrics::new()?,
+            trading_pairs: Arc::new(std::sync::RwLock::new(config.trading_pairs.clone())),
+            tracked_symbols: Arc::new(std::sync::RwLock::new(
+                config.symbols().into_iter().collect(),
+            )),
+            outlier_threshold_pct: config.outlier_threshold_pct,
+            max_source_spread_pct: config.max_source_spread_pct,
+            max_book_spread_pct: config.max_book_spread_pct,
+            halted_symbols: Arc::new(RwLock::new(HashSet::new())),
+            duplicate_price_epsilon: config.duplicate_price_epsilon,
+            source_priority: config
+                .source_priority
+                .iter()
+                .map(|exchange| exchange.as_str().to_string())
+                .collect(),
+            min_consensus_sources: config.min_consensus_sources,
+            weights: config
+                .source_weights
+                .iter()
+                .map(|(exchange, weight)| (exchange.as_str().to_string(), *weight))
+                .collect(),
+            reference_source: config
+                .reference_exchange
+                .map(|exchange| exchange.as_str().to_string()),
+            default_price_expiry: Duration::from_secs(config.price_expiry_secs),
+            max_clock_skew: Duration::from_millis(config.max_clock_skew_ms),
+            clock_skew_warned: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            source_max_age: Duration::from_secs(config.source_max_age_secs),
+            symbol_price_expiry: Arc::new(
+                config
+                    .symbol_price_expiry_secs
+                    .iter()
+                    .map(|(symbol, secs)| (symbol.clone(), Duration::from_secs(*secs)))
+                    .collect(),
+            ),
+            symbol_price_scale: Arc::new(config.symbol_price_scale.clone()),
+            price_bounds: Arc::new(config.symbol_price_bounds.clone()),
+            symbol_price_decimals: Arc::new(config.symbol_price_decimals.clone()),
+            default_write_interval: Duration::from_millis(config.min_write_interval_ms),
+            symbol_write_interval: Arc::new(
+                config
+                    .symbol_min_write_interval_ms
+                    .iter()
+                    .map(|(symbol, millis)| (symbol.clone(), Duration::from_millis(*millis)))
+                    .collect(),
+            ),
+            write_throttle: Arc::new(Mutex::new(WriteThrottle::default())),
+            funding_receiver: Arc::new(Mutex::new(funding_receiver)),
+            queue: Arc::new(UpdateQueue::new(CHANNEL_SIZE)),
+            update_sender: broadcast::channel(BROADCAST_CAPACITY).0,
+            health_sender: broadcast::channel(HEALTH_EVENT_CAPACITY).0,
+            clock,
+            wal_path: config.wal_path.clone(),
+            health: config.health.clone(),
         })
     }
 
+    /// Receives every update the publisher writes to its sink, for using the
+    /// publisher as a library. A receiver that falls more than
+    /// `BROADCAST_CAPACITY` updates behind misses the oldest ones and gets
+    /// `RecvError::Lagged`; the publisher never waits for it.
+    pub fn subscribe(&self) -> broadcast::Receiver<PriceUpdate> {
+        self.update_sender.subscribe()
+    }
+
+    /// Receives a `HealthEvent` each time an exchange connects or
+    /// disconnects, for edge-triggered alerting instead of polling
+    /// `get_exchange_health`. Repeated reports of the same state send
+    /// nothing; a receiver more than `HEALTH_EVENT_CAPACITY` events behind
+    /// gets `RecvError::Lagged`.
+    pub fn health_events(&self) -> broadcast::Receiver<HealthEvent> {
+        self.health_sender.subscribe()
+    }
+
+    // Sets `is_connected`, telling `health_events` receivers if it changed
+    fn set_connected(
+        &self,
+        exchange: &str,
+        metrics: &mut ExchangeHealth,
+        connected: bool,
+        at: SystemTime,
+    ) {
+        if metrics.is_connected != connected {
+            // Only fails when nobody is subscribed
+            let _ = self.health_sender.send(HealthEvent {
+                exchange: exchange.to_string(),
+                connected,
+                at,
+            });
+        }
+        metrics.is_connected = connected;
+    }
+
+    // Hands published updates to `subscribe` receivers, logging when a slow
+    // receiver is about to miss some
+    fn broadcast_updates(&self, updates: &[PriceUpdate]) {
+        let mut dropped = 0;
+        for update in updates {
+            if self.update_sender.len() >= BROADCAST_CAPACITY {
+                dropped += 1;
+            }
+            // Only fails when nobody is subscribed
+            let _ = self.update_sender.send(update.clone());
+        }
+        if dropped > 0 {
+            warn!(
+                "Subscriber lagging, dropped {} price updates from the broadcast",
+                dropped
+            );
+        }
+    }
+
+    /// True if `symbol` belongs to a configured trading pair. Exchanges
+    /// already translate through `canonical_symbols`, but broad subscriptions
+    /// such as Hyperliquid's allMids rely on this as a backstop.
+    pub fn is_tracked(&self, symbol: &str) -> bool {
+        self.tracked_symbols.read().unwrap().contains(symbol)
+    }
+
+    /// Starts tracking `pair` on every exchange without restarting. Binance
+    /// and Bybit subscribe on their open connection; the other exchanges
+    /// reconnect. Returns false if the pair was already tracked.
+    pub fn add_pair(&self, pair: TradingPair) -> bool {
+        let mut pairs = self.trading_pairs.write().unwrap();
+        if pairs.contains(&pair) {
+            return false;
+        }
+        info!("Adding trading pair {}", pair);
+        self.tracked_symbols
+            .write()
+            .unwrap()
+            .insert(pair.to_canonical_symbol());
+        pairs.push(pair);
+        self.resubscribe(&pairs);
+        true
+    }
+
+    /// Stops tracking `pair` on every exchange and forgets its prices.
+    /// Returns false if the pair wasn't tracked.
+    pub async fn remove_pair(&self, pair: &TradingPair) -> bool {
+        let symbol = pair.to_canonical_symbol();
+        {
+            let mut pairs = self.trading_pairs.write().unwrap();
+            let Some(index) = pairs.iter().position(|tracked| tracked == pair) else {
+                return false;
+            };
+            info!("Removing trading pair {}", pair);
+            pairs.remove(index);
+            self.tracked_symbols.write().unwrap().remove(&symbol);
+            self.resubscribe(&pairs);
+        }
+
+        self.latest_prices.write().await.remove(&symbol);
+        self.latest_quotes.write().await.remove(&symbol);
+        self.latest_trades.write().await.remove(&symbol);
+        self.ohlc_bars.write().await.remove(&symbol);
+        self.ema_state.write().await.remove(&symbol);
+        self.halted_symbols.write().await.remove(&symbol);
+        self.write_throttle.lock().await.held.remove(&symbol);
+        true
+    }
+
+    /// The trading pairs currently tracked.
+    pub fn trading_pairs(&self) -> Vec<TradingPair> {
+        self.trading_pairs.read().unwrap().clone()
+    }
+
+    // The exchanges currently running, including any that joined after a
+    // retried `init`
+    fn exchanges(&self) -> Vec<Arc<ExchangeImpl>> {
+        self.exchanges.read().unwrap().clone()
+    }
+
+    fn resubscribe(&self, pairs: &[TradingPair]) {
+        for exchange in self.exchanges() {
+            exchange.resubscribe(pairs.to_vec());
+        }
+    }
+
+    /// How long `symbol`'s Redis keys live without an update.
+    pub fn price_expiry(&self, symbol: &str) -> Duration {
+        self.symbol_price_expiry
+            .get(symbol)
+            .copied()
+            .unwrap_or(self.default_price_expiry)
+    }
+
+    /// Minimum time between sink writes for `symbol`.
+    pub fn write_interval(&self, symbol: &str) -> Duration {
+        self.symbol_write_interval
+            .get(symbol)
+            .copied()
+            .unwrap_or(self.default_write_interval)
+    }
+
+    fn throttles_writes(&self) -> bool {
+        !self.default_write_interval.is_zero()
+            || self
+                .symbol_write_interval
+                .values()
+                .any(|interval| !interval.is_zero())
+    }
+
+    // Holds back updates for symbols written less than their write interval
+    // ago, keeping only the latest per source and kind, and returns what is
+    // due now: unthrottled updates plus the held ones of symbols whose
+    // interval has passed. `force` releases everything held.
+    async fn take_due_writes(&self, updates: Vec<PriceUpdate>, force: bool) -> Vec<PriceUpdate> {
+        if !self.throttles_writes() {
+            return updates;
+        }
+
+        let now = Instant::now();
+        let mut throttle = self.write_throttle.lock().await;
+        let mut due = Vec::with_capacity(updates.len());
+        for update in updates {
+            if self.write_interval(&update.symbol).is_zero() {
+                due.push(update);
+                continue;
+            }
+            let held = throttle.held.entry(update.symbol.clone()).or_default();
+            held.retain(|queued| queued.source != update.source || queued.kind != update.kind);
+            held.push(update);
+        }
+
+        let ready: Vec<String> = throttle
+            .held
+            .keys()
+            .filter(|symbol| {
+                force
+                    || throttle.last_write.get(*symbol).is_none_or(|written| {
+                        now.duration_since(*written) >= self.write_interval(symbol)
+                    })
+            })
+            .cloned()
+            .collect();
+        for symbol in ready {
+            if let Some(held) = throttle.held.remove(&symbol) {
+                due.extend(held);
+            }
+            throttle.last_write.insert(symbol, now);
+        }
+        due
+    }
+
+    /// Writes held-back updates whose write interval has passed. `run` calls
+    /// this periodically so a symbol's last update lands even if nothing
+    /// follows it.
+    pub async fn flush_held_writes(&self) {
+        let updates = self.take_due_writes(Vec::new(), false).await;
+        if !updates.is_empty() {
+            self.write_updates(updates, HashSet::new()).await;
+        }
+    }
+
+    // Records how long after the exchange's event time the update was
+    // received, warns when the gap is too large for network delay (usually a
+    // drifting local clock), and makes the exchange's time the update's
+    // timestamp. Feeds without event times keep their receipt time.
+    async fn adopt_exchange_time(&self, mut update: PriceUpdate) -> PriceUpdate {
+        let Some(exchange_timestamp) = update.exchange_timestamp else {
+            return update;
+        };
+
+        let latency = update
+            .timestamp
+            .duration_since(exchange_timestamp)
+            .unwrap_or_default();
+        if let Some(health) = self.health_metrics.write().await.get_mut(&update.source) {
+            health.last_message_latency = latency;
+            // Exponentially weighted so a single slow message doesn't dominate
+            health.avg_message_latency = if health.avg_message_latency.is_zero() {
+                latency
+            } else {
+                health.avg_message_latency.mul_f64(1.0 - LATENCY_EWMA_ALPHA)
+                    + latency.mul_f64(LATENCY_EWMA_ALPHA)
+            };
+        }
+
+        // Exchange time ahead of ours can only be clock skew
+        let skew = match update.timestamp.duration_since(exchange_timestamp) {
+            Ok(behind) => behind,
+            Err(ahead) => ahead.duration(),
+        };
+        if skew > self.max_clock_skew {
+            self.warn_clock_skew(&update, skew);
+        }
+
+        update.timestamp = exchange_timestamp;
+        update
+    }
+
+    // At most once per CLOCK_SKEW_WARN_INTERVAL per exchange
+    fn warn_clock_skew(&self, update: &PriceUpdate, skew: Duration) {
+        let mut warned = self.clock_skew_warned.lock().unwrap();
+        if warned
+            .get(&update.source)
+            .is_some_and(|at| at.elapsed() < CLOCK_SKEW_WARN_INTERVAL)
+        {
+            return;
+        }
+        warned.insert(update.source.clone(), Instant::now());
+        warn!(
+            exchange = update.source.as_str(), symbol = update.symbol.as_str();
+            "Local clock and {} event time differ by {}ms, check the system clock",
+            update.source,
+            skew.as_millis()
+        );
+    }
+
+    // Applies the symbol's configured price scale, if any, so every source
+    // is compared and published in the same units
+    fn rescale(&self, update: PriceUpdate) -> PriceUpdate {
+        match self.symbol_price_scale.get(&update.symbol) {
+            Some(scale) => update.scaled(*scale),
+            None => update,
+        }
+    }
+
+    // Sources go stale well before the key expires so consumers never read a
+    // price we've already stopped trusting
+    fn stale_threshold(&self, symbol: &str) -> Duration {
+        self.price_expiry(symbol) / 2
+    }
+
     async fn update_health_metrics(&self, exchange: &str, is_healthy: bool, had_error: bool) {
         let mut health_metrics = self.health_metrics.write().await;
         if let Some(metrics) = health_metrics.get_mut(exchange) {
-            metrics.last_update = SystemTime::now();
-            metrics.is_connected = is_healthy;
+            let now = self.clock.now();
+            metrics.last_update = now;
+            self.set_connected(exchange, metrics, is_healthy, now);
+            if !is_healthy {
+                metrics.connected_since = None;
+            }
             if had_error {
                 metrics.error_count += 1;
             } else {
@@ -128,152 +807,1135 @@ impl PricePublisher {
         }
     }
 
+    // Marks a feed as down without counting it as an error
+    async fn mark_disconnected(&self, exchange: &str) {
+        if let Some(metrics) = self.health_metrics.write().await.get_mut(exchange) {
+            let now = self.clock.now();
+            metrics.last_update = now;
+            self.set_connected(exchange, metrics, false, now);
+            metrics.connected_since = None;
+        }
+    }
+
+    async fn record_reconnect(&self, exchange: &str) {
+        if let Some(metrics) = self.health_metrics.write().await.get_mut(exchange) {
+            metrics.reconnect_count = metrics.reconnect_count.saturating_add(1);
+        }
+    }
+
     async fn run_health_checks(&self) {
-        let mut interval = interval(HEALTH_CHECK_INTERVAL);
+        let mut interval = interval(self.health.check_interval());
 
         loop {
             interval.tick().await;
-            let health_metrics = self.health_metrics.read().await;
-            let latest_prices = self.latest_prices.read().await;
+            self.check_health().await;
+            self.evict_stale_sources().await;
+        }
+    }
 
-            for (exchange, metrics) in health_metrics.iter() {
-                // Check connection status
-                if !metrics.is_connected {
-                    warn!("{} is disconnected", exchange);
+    /// Removes sources that haven't updated within `source_max_age_secs` from
+    /// the in-memory prices, so dead feeds drop out of `get_latest_prices`
+    /// and memory stays bounded. Returns how many were evicted.
+    pub async fn evict_stale_sources(&self) -> usize {
+        let now = self.clock.now();
+        let mut latest_prices = self.latest_prices.write().await;
+        let mut latest_quotes = self.latest_quotes.write().await;
+        let mut evicted = 0;
+
+        for (symbol, sources) in latest_prices.iter_mut() {
+            sources.retain(|source, (_, timestamp)| {
+                if is_fresh(*timestamp, now, self.source_max_age) {
+                    return true;
+                }
+                info!(
+                    exchange = source.as_str(), symbol = symbol.as_str();
+                    "Evicting {} from {} prices, no update in {}s",
+                    source,
+                    symbol,
+                    self.source_max_age.as_secs()
+                );
+                if let Some(quotes) = latest_quotes.get_mut(symbol) {
+                    quotes.remove(source);
                 }
+                self.metrics.remove_price_source(source, symbol);
+                evicted += 1;
+                false
+            });
+        }
+        latest_prices.retain(|_, sources| !sources.is_empty());
+        latest_quotes.retain(|_, quotes| !quotes.is_empty());
+
+        evicted
+    }
+
+    /// Logs a warning for every disconnected, erroring, silent or stale
+    /// exchange and every stale source price. Run each
+    /// `health.check_interval_secs` by the health-check task.
+    pub async fn check_health(&self) {
+        let health_metrics = self.health_metrics.read().await;
+        let latest_prices = self.latest_prices.read().await;
+
+        for (exchange, metrics) in health_metrics.iter() {
+            // Check connection status
+            if !metrics.is_connected {
+                warn!(exchange = exchange.as_str(); "{} is disconnected", exchange);
+            }
+
+            // Check error count
+            if metrics.error_count > self.health.max_error_count {
+                error!(
+                    exchange = exchange.as_str();
+                    "{} has high error count: {}", exchange, metrics.error_count
+                );
+            }
 
-                // Check error count
-                if metrics.error_count > 5 {
-                    error!("{} has high error count: {}", exchange, metrics.error_count);
+            // Check last update time
+            if let Ok(elapsed) = self.clock.now().duration_since(metrics.last_update) {
+                if elapsed > self.health.stale_exchange_threshold() {
+                    warn!(
+                        exchange = exchange.as_str();
+                        "{} hasn't updated in {} seconds",
+                        exchange,
+                        elapsed.as_secs()
+                    );
                 }
+            }
+        }
+
+        let utilization = self.queue.utilization();
+        if utilization >= QUEUE_WARN_UTILIZATION {
+            warn!(
+                "Update queue at {:.0}% of capacity, the sink is falling behind",
+                utilization * 100.0
+            );
+        }
 
-                // Check last update time
-                if let Ok(elapsed) = SystemTime::now().duration_since(metrics.last_update) {
-                    if elapsed > STALE_PRICE_THRESHOLD {
+        // A socket can stay up with pongs flowing after its subscription
+        // silently stopped delivering
+        for exchange in self.exchanges() {
+            let exchange_name = exchange.get_name();
+            let connected = health_metrics
+                .get(exchange_name)
+                .is_some_and(|metrics| metrics.is_connected);
+            if connected && exchange.ticks_last_interval() == 0 {
+                warn!(
+                    exchange = exchange_name;
+                    "{} is connected but sent no prices in the last {} seconds",
+                    exchange_name,
+                    TICK_INTERVAL_SECS
+                );
+            }
+        }
+
+        // Check for stale prices
+        for (symbol, sources) in latest_prices.iter() {
+            let stale_after = self.stale_threshold(symbol);
+            for (source, (_, timestamp)) in sources.iter() {
+                if let Ok(elapsed) = self.clock.now().duration_since(*timestamp) {
+                    if elapsed > stale_after {
                         warn!(
-                            "{} hasn't updated in {} seconds",
-                            exchange,
+                            exchange = source.as_str(), symbol = symbol.as_str();
+                            "Stale price for {}/{}: {} seconds old",
+                            symbol,
+                            source,
                             elapsed.as_secs()
                         );
                     }
                 }
             }
+        }
+    }
+
+    // Broadcasts the batch, then writes the updates that aren't held back by
+    // `min_write_interval_ms` to the sinks
+    async fn flush_batch(&self, updates: Vec<PriceUpdate>) {
+        self.broadcast_updates(&updates);
+        let updates = self.take_due_writes(updates, false).await;
+
+        // Halted symbols are always included: the update that tripped the
+        // breaker is often dropped as an outlier, and the flag must still land
+        let halted = self.halted_symbols.read().await.clone();
+        self.write_updates(updates, halted).await;
+    }
+
+    // Builds the price, consensus and OHLC snapshot of every symbol in
+    // `updates` or `extra_symbols`, and hands the batch to the sinks
+    async fn write_updates(&self, updates: Vec<PriceUpdate>, extra_symbols: HashSet<String>) {
+        let mut symbols = extra_symbols;
+        symbols.extend(updates.iter().map(|update| update.symbol.clone()));
+        if symbols.is_empty() {
+            return;
+        }
+
+        let mut batch = PriceBatch {
+            updates,
+            symbols: Vec::with_capacity(symbols.len()),
+        };
+        for symbol in symbols {
+            batch.symbols.push(self.symbol_snapshot(symbol).await);
+        }
+
+        let writes = self.sinks.iter().map(|sink| sink.write_batch(&batch));
+        for result in futures::future::join_all(writes).await {
+            if let Err(e) = result {
+                error!("Failed to write price batch: {}", e);
+            }
+        }
+    }
+
+    async fn symbol_snapshot(&self, symbol: String) -> SymbolSnapshot {
+        let preferred = match self.preferred_price(&symbol).await {
+            Some((source, price, timestamp)) => {
+                let (bid, ask) = self
+                    .latest_quotes
+                    .read()
+                    .await
+                    .get(&symbol)
+                    .and_then(|quotes| quotes.get(&source))
+                    .copied()
+                    .unwrap_or_default();
+                Some(PriceMeta::new(bid, ask, price, source, timestamp))
+            }
+            None => None,
+        };
+
+        let last_trade = self
+            .latest_trades
+            .read()
+            .await
+            .get(&symbol)
+            .filter(|(_, timestamp)| {
+                is_fresh(*timestamp, self.clock.now(), self.stale_threshold(&symbol))
+            })
+            .map(|(price, _)| *price);
+
+        let pair = self
+            .trading_pairs
+            .read()
+            .unwrap()
+            .iter()
+            .find(|pair| pair.to_canonical_symbol() == symbol)
+            .cloned();
+
+        SymbolSnapshot {
+            pair,
+            expiry: self.price_expiry(&symbol),
+            last_trade,
+            decimals: self.symbol_price_decimals.get(&symbol).copied(),
+            preferred,
+            consensus: self.consensus(&symbol).await,
+            insufficient_sources: !self.has_consensus_quorum(&symbol).await,
+            robust_consensus: self.compute_robust_consensus(&symbol).await,
+            deviations: self.compute_deviations(&symbol).await,
+            ohlc: self.ohlc_bars.read().await.get(&symbol).copied(),
+            ema: self.ema_state.read().await.get(&symbol).copied(),
+            halted: self.is_halted(&symbol).await,
+            symbol,
+        }
+    }
+
+    // Restores the last known book price per source from the WAL, so a
+    // restart doesn't start blind. Nothing is republished; prices that are
+    // already stale simply don't count.
+    async fn seed_from_wal(&self) {
+        let Some(path) = &self.wal_path else {
+            return;
+        };
+        let updates = match read_wal_tail(path, WAL_RECOVERY_BYTES) {
+            Ok(updates) => updates,
+            Err(e) => {
+                warn!("Failed to read WAL {}: {}", path.display(), e);
+                return;
+            }
+        };
+
+        let mut seeded = 0;
+        let mut latest_prices = self.latest_prices.write().await;
+        let mut latest_quotes = self.latest_quotes.write().await;
+        for update in updates {
+            if update.kind == PriceKind::LastTrade || !self.is_tracked(&update.symbol) {
+                continue;
+            }
+            let sources = latest_prices.entry(update.symbol.clone()).or_default();
+            if sources
+                .get(&update.source)
+                .is_some_and(|(_, timestamp)| *timestamp > update.timestamp)
+            {
+                continue;
+            }
+            sources.insert(update.source.clone(), (update.price, update.timestamp));
+            latest_quotes
+                .entry(update.symbol.clone())
+                .or_default()
+                .insert(update.source.clone(), (update.bid, update.ask));
+            seeded += 1;
+        }
+        info!("Seeded {} prices from WAL {}", seeded, path.display());
+    }
+
+    // Seeds prices from each exchange's REST snapshot so the sink is
+    // populated before the first websocket tick arrives
+    async fn seed_from_snapshots(&self) {
+        let exchanges = self.exchanges();
+        let snapshots =
+            futures::future::join_all(exchanges.iter().map(|exchange| async move {
+                (exchange.get_name(), exchange.fetch_snapshot().await)
+            }))
+            .await;
 
-            // Check for stale prices
-            for (symbol, sources) in latest_prices.iter() {
-                for (source, (_, timestamp)) in sources.iter() {
-                    if let Ok(elapsed) = SystemTime::now().duration_since(*timestamp) {
-                        if elapsed > STALE_PRICE_THRESHOLD {
-                            warn!(
-                                "Stale price for {}/{}: {} seconds old",
-                                symbol,
-                                source,
-                                elapsed.as_secs()
-                            );
+        let mut published = Vec::new();
+        for (exchange_name, snapshot) in snapshots {
+            match snapshot {
+                Ok(updates) => {
+                    if !updates.is_empty() {
+                        info!(
+                            "Seeded {} prices from {} snapshot",
+                            updates.len(),
+                            exchange_name
+                        );
+                    }
+                    for update in updates {
+                        let update = self.rescale(update);
+                        if self.process_update(&update).await {
+                            published.push(update);
                         }
                     }
                 }
+                Err(e) => warn!("Failed to fetch {} snapshot: {}", exchange_name, e),
             }
         }
+
+        self.flush_batch(published).await;
     }
 
-    async fn write_to_redis(&self, update: &PriceUpdate) -> Result<()> {
-        let mut conn = self.redis_client.get_async_connection().await?;
+    // The weighted consensus when weights are configured, falling back to
+    // the median while none of the weighted sources is fresh. None without
+    // a quorum of fresh sources.
+    async fn consensus(&self, symbol: &str) -> Option<Decimal> {
+        if !self.has_consensus_quorum(symbol).await {
+            return None;
+        }
+        if self.weights.is_empty() {
+            return self.compute_consensus(symbol).await;
+        }
+        match self.compute_weighted_consensus(symbol).await {
+            Some(price) => Some(price),
+            None => self.compute_consensus(symbol).await,
+        }
+    }
 
-        // Write the latest price
-        let price_key = format!("price:{}", update.symbol);
-        conn.set_ex(&price_key, update.price.to_string(), REDIS_PRICE_EXPIRY)
-            .await?;
+    /// Average of the fresh sources for `symbol`, each weighted by its entry
+    /// in `weights`. Weights are renormalized over whichever sources are
+    /// fresh; sources without a positive weight are left out.
+    pub async fn compute_weighted_consensus(&self, symbol: &str) -> Option<Decimal> {
+        let latest_prices = self.latest_prices.read().await;
+        let now = self.clock.now();
+        let stale_after = self.stale_threshold(symbol);
 
-        // Write source information
-        let sources_key = format!("price:{}:sources", update.symbol);
-        let timestamp = update
-            .timestamp
-            .duration_since(std::time::UNIX_EPOCH)?
-            .as_secs();
-        let source_info = format!("{}:{:.8}:{}", update.source, update.price, timestamp);
-        conn.set_ex(&sources_key, source_info, REDIS_PRICE_EXPIRY)
-            .await?;
+        let mut weighted_sum = Decimal::ZERO;
+        let mut total_weight = Decimal::ZERO;
+        for (source, (price, timestamp)) in latest_prices.get(symbol)? {
+            if !is_fresh(*timestamp, now, stale_after) {
+                continue;
+            }
+            let weight = match self
+                .weights
+                .get(source)
+                .and_then(|weight| Decimal::from_f64(*weight))
+            {
+                Some(weight) if weight > Decimal::ZERO => weight,
+                _ => continue,
+            };
+            weighted_sum += *price * weight;
+            total_weight += weight;
+        }
+        weighted_sum.checked_div(total_weight)
+    }
 
-        Ok(())
+    /// Each fresh source's deviation from the fresh `reference_source` price
+    /// for `symbol`, in signed basis points. Empty without a reference or
+    /// while the reference's own price is stale.
+    pub async fn compute_deviations(&self, symbol: &str) -> HashMap<String, Decimal> {
+        let Some(reference_source) = &self.reference_source else {
+            return HashMap::new();
+        };
+        let latest_prices = self.latest_prices.read().await;
+        let Some(sources) = latest_prices.get(symbol) else {
+            return HashMap::new();
+        };
+        let now = self.clock.now();
+        let stale_after = self.stale_threshold(symbol);
+
+        let reference = match sources.get(reference_source) {
+            Some((price, timestamp)) if is_fresh(*timestamp, now, stale_after) => *price,
+            _ => return HashMap::new(),
+        };
+        sources
+            .iter()
+            .filter(|(source, _)| *source != reference_source)
+            .filter(|(_, (_, timestamp))| is_fresh(*timestamp, now, stale_after))
+            .filter_map(|(source, (price, _))| {
+                (*price - reference).checked_div(reference).map(|ratio| {
+                    (
+                        source.clone(),
+                        (ratio * Decimal::new(10_000, 0)).round_dp(2),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Whether at least `min_consensus_sources` sources for `symbol` are
+    /// fresh. Without a quorum no consensus is published, since one source
+    /// would only be relabeled as the consensus.
+    pub async fn has_consensus_quorum(&self, symbol: &str) -> bool {
+        let latest_prices = self.latest_prices.read().await;
+        let fresh = latest_prices.get(symbol).map_or(0, |sources| {
+            fresh_prices(
+                sources,
+                None,
+                self.stale_threshold(symbol),
+                self.clock.now(),
+            )
+            .len()
+        });
+        fresh >= self.min_consensus_sources
+    }
+
+    /// Median price across all sources for `symbol` that are still fresh
+    /// (younger than half the symbol's expiry).
+    pub async fn compute_consensus(&self, symbol: &str) -> Option<Decimal> {
+        let latest_prices = self.latest_prices.read().await;
+        let mut prices = fresh_prices(
+            latest_prices.get(symbol)?,
+            None,
+            self.stale_threshold(symbol),
+            self.clock.now(),
+        );
+        median(&mut prices)
+    }
+
+    /// Median of the fresh sources for `symbol` once the single most deviant
+    /// one is dropped, when there are at least three; otherwise the plain
+    /// median (the mean of two).
+    pub async fn compute_robust_consensus(&self, symbol: &str) -> Option<Decimal> {
+        let latest_prices = self.latest_prices.read().await;
+        let mut prices = fresh_prices(
+            latest_prices.get(symbol)?,
+            None,
+            self.stale_threshold(symbol),
+            self.clock.now(),
+        );
+        trimmed_median(&mut prices)
+    }
+
+    /// Source, price and timestamp of the fresh source for `symbol` that ranks
+    /// highest in `source_priority`, skipping sources that are outliers against
+    /// the consensus. Unlisted sources rank last; ties go to the most recent.
+    pub async fn preferred_price(&self, symbol: &str) -> Option<(String, Decimal, SystemTime)> {
+        let consensus = self.compute_consensus(symbol).await;
+        let latest_prices = self.latest_prices.read().await;
+        let now = self.clock.now();
+        let stale_after = self.stale_threshold(symbol);
+
+        latest_prices
+            .get(symbol)?
+            .iter()
+            .filter(|(_, (_, timestamp))| is_fresh(*timestamp, now, stale_after))
+            .filter(|(_, (price, _))| match consensus {
+                Some(consensus) => deviation_pct(*price, consensus)
+                    .map(|deviation| deviation <= self.outlier_threshold_pct)
+                    .unwrap_or(true),
+                None => true,
+            })
+            .min_by_key(|(source, (_, timestamp))| {
+                (self.source_rank(source), std::cmp::Reverse(*timestamp))
+            })
+            .map(|(source, (price, timestamp))| (source.clone(), *price, *timestamp))
+    }
+
+    /// Halts `symbol` when its lowest and highest fresh source prices are more
+    /// than `max_source_spread_pct` apart, and lifts the halt once they
+    /// reconverge. Returns whether the symbol is halted.
+    pub async fn check_consistency(&self, symbol: &str) -> bool {
+        let spread = {
+            let latest_prices = self.latest_prices.read().await;
+            let prices = match latest_prices.get(symbol) {
+                Some(sources) => fresh_prices(
+                    sources,
+                    None,
+                    self.stale_threshold(symbol),
+                    self.clock.now(),
+                ),
+                None => Vec::new(),
+            };
+            match (prices.iter().min(), prices.iter().max()) {
+                (Some(min), Some(max)) => deviation_pct(*max, *min),
+                _ => None,
+            }
+        };
+        let disagree = spread.is_some_and(|spread| spread > self.max_source_spread_pct);
+
+        let mut halted_symbols = self.halted_symbols.write().await;
+        if disagree && halted_symbols.insert(symbol.to_string()) {
+            warn!(
+                symbol = symbol;
+                "Halting {}: sources are {:.2}% apart",
+                symbol,
+                spread.unwrap_or_default()
+            );
+        } else if !disagree && halted_symbols.remove(symbol) {
+            info!(symbol = symbol; "Resuming {}: sources back in line", symbol);
+        }
+        disagree
+    }
+
+    pub async fn is_halted(&self, symbol: &str) -> bool {
+        self.halted_symbols.read().await.contains(symbol)
+    }
+
+    // Position of `source` in `source_priority`, after every listed source if absent
+    fn source_rank(&self, source: &str) -> usize {
+        self.source_priority
+            .iter()
+            .position(|preferred| preferred == source)
+            .unwrap_or(self.source_priority.len())
+    }
+
+    // Percentage deviation of `update` from the median of the other fresh
+    // sources for the same symbol, if there are any
+    async fn deviation_from_peers(&self, update: &PriceUpdate) -> Option<Decimal> {
+        let latest_prices = self.latest_prices.read().await;
+        let mut peers = fresh_prices(
+            latest_prices.get(&update.symbol)?,
+            Some(&update.source),
+            self.stale_threshold(&update.symbol),
+            self.clock.now(),
+        );
+        let reference = median(&mut peers)?;
+        deviation_pct(update.price, reference)
+    }
+
+    /// Folds `update` into the current bar for its symbol, starting a new bar
+    /// when the update falls in a later minute.
+    pub async fn update_ohlc(&self, update: &PriceUpdate) -> OhlcBar {
+        let start = bar_start(update.timestamp);
+        let mut bars = self.ohlc_bars.write().await;
+        let bar = bars
+            .entry(update.symbol.clone())
+            .or_insert_with(|| OhlcBar::new(update.price, start));
+
+        if start > bar.start_ts {
+            *bar = OhlcBar::new(update.price, start);
+        } else if start == bar.start_ts {
+            bar.update(update.price);
+        }
+        // Late updates belonging to an earlier bar are ignored
+        *bar
+    }
+
+    // Moves the symbol's EMA towards `update`'s price; the first tick seeds it
+    async fn update_ema(&self, update: &PriceUpdate) {
+        let Some(alpha) = self.ema_alpha else {
+            return;
+        };
+        self.ema_state
+            .write()
+            .await
+            .entry(update.symbol.clone())
+            .and_modify(|ema| *ema += alpha * (update.price - *ema))
+            .or_insert(update.price);
+    }
+
+    // True if `update` repeats the stored price for its source within
+    // `duplicate_price_epsilon`. Repeats are still let through once the stored
+    // price is halfway to stale, so a flat feed keeps its Redis keys alive.
+    async fn is_repeated_price(&self, update: &PriceUpdate) -> bool {
+        let latest_prices = self.latest_prices.read().await;
+        let (price, timestamp) = match latest_prices
+            .get(&update.symbol)
+            .and_then(|sources| sources.get(&update.source))
+        {
+            Some(stored) => *stored,
+            None => return false,
+        };
+        let refresh_after = self.stale_threshold(&update.symbol) / 2;
+        (update.price - price).abs() <= self.duplicate_price_epsilon
+            && update
+                .timestamp
+                .duration_since(timestamp)
+                .map(|age| age < refresh_after)
+                .unwrap_or(true)
+    }
+
+    // Records a last-trade price. Trades never join the book prices, so they
+    // don't move the consensus, outlier checks or `price:{symbol}`.
+    async fn process_trade(&self, update: &PriceUpdate) -> bool {
+        if !self.is_tracked(&update.symbol) || !update.is_valid() {
+            return false;
+        }
+        self.latest_trades
+            .write()
+            .await
+            .insert(update.symbol.clone(), (update.price, update.timestamp));
+        true
+    }
+
+    // Records an update internally. Returns false if the update was held
+    // back from publishing.
+    async fn process_update(&self, update: &PriceUpdate) -> bool {
+        if !self.is_tracked(&update.symbol) {
+            debug!(
+                exchange = update.source.as_str(), symbol = update.symbol.as_str();
+                "Dropping untracked symbol from {}: {}", update.source, update.symbol
+            );
+            return false;
+        }
+
+        // Garbage from one feed must never reach Redis or the consensus
+        if !update.is_valid() {
+            warn!(
+                exchange = update.source.as_str(), symbol = update.symbol.as_str();
+                "Dropping invalid price from {}: {} = {} (bid {:?}, ask {:?})",
+                update.source, update.symbol, update.price, update.bid, update.ask
+            );
+            return false;
+        }
+
+        // The mid of a locked, crossed or blown-out book isn't a price
+        if let Some(spread) = update.spread_pct() {
+            if spread <= Decimal::ZERO || spread > self.max_book_spread_pct {
+                warn!(
+                    exchange = update.source.as_str(), symbol = update.symbol.as_str();
+                    "Dropping {} book from {}: {} bid {:?} ask {:?} ({:.4}% spread)",
+                    if spread <= Decimal::ZERO { "crossed" } else { "wide" },
+                    update.source, update.symbol, update.bid, update.ask, spread
+                );
+                return false;
+            }
+        }
+
+        // Catches gross feed errors, e.g. a misplaced decimal point
+        if let Some((min, max)) = self.price_bounds.get(&update.symbol) {
+            if update.price < *min || update.price > *max {
+                warn!(
+                    exchange = update.source.as_str(), symbol = update.symbol.as_str();
+                    "Dropping out-of-bounds price from {}: {} = {} (bounds [{}, {}])",
+                    update.source, update.symbol, update.price, min, max
+                );
+                return false;
+            }
+        }
+
+        // A price from an exchange means its feed is live
+        if let Some(health) = self.health_metrics.write().await.get_mut(&update.source) {
+            health.last_update = update.timestamp;
+            self.set_connected(&update.source, health, true, update.timestamp);
+            health.connected_since.get_or_insert(update.timestamp);
+        }
+
+        self.metrics.record_price_update(update);
+
+        if self.is_repeated_price(update).await {
+            return false;
+        }
+
+        // Compare against the other sources before this update joins them
+        let deviation = self.deviation_from_peers(update).await;
+
+        // Update latest prices
+        {
+            let mut latest_prices = self.latest_prices.write().await;
+            latest_prices
+                .entry(update.symbol.clone())
+                .or_default()
+                .insert(update.source.clone(), (update.price, update.timestamp));
+            self.latest_quotes
+                .write()
+                .await
+                .entry(update.symbol.clone())
+                .or_default()
+                .insert(update.source.clone(), (update.bid, update.ask));
+        }
+
+        self.check_consistency(&update.symbol).await;
+
+        if let Some(deviation) = deviation {
+            if deviation > self.outlier_threshold_pct {
+                warn!(
+                    exchange = update.source.as_str(), symbol = update.symbol.as_str();
+                    "Dropping outlier from {}: {} = {} ({:.2}% from other sources)",
+                    update.source, update.symbol, update.price, deviation
+                );
+                return false;
+            }
+        }
+
+        self.update_ohlc(update).await;
+        self.update_ema(update).await;
+
+        info!(
+            exchange = update.source.as_str(), symbol = update.symbol.as_str();
+            "Received price update from {}: {} = {}",
+            update.source, update.symbol, update.price
+        );
+        true
+    }
+
+    // Retries `init` on an exchange that failed it, backing off like a
+    // connection that can't be established. Once it succeeds the exchange
+    // joins the live set and listens like the others.
+    async fn retry_init(
+        self,
+        mut exchange: ExchangeImpl,
+        price_sender: mpsc::Sender<PriceUpdate>,
+        mut shutdown: watch::Receiver<bool>,
+    ) {
+        let exchange_name = exchange.get_name();
+        let mut attempts: u32 = 0;
+        loop {
+            let delay = reconnect_delay(
+                &self.health,
+                attempts.saturating_add(CONNECT_FAILURE_BACKOFF_STEPS),
+            );
+            info!(
+                exchange = exchange_name;
+                "Retrying {} initialization in {:.1}s",
+                exchange_name,
+                delay.as_secs_f64()
+            );
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = shutdown.changed() => return,
+            }
+
+            match exchange.init().await {
+                Ok(()) => break,
+                Err(e) => {
+                    error!(exchange = exchange_name; "Failed to initialize {}: {}", exchange_name, e);
+                    self.update_health_metrics(exchange_name, false, true).await;
+                    attempts = attempts.saturating_add(1);
+                }
+            }
+        }
+
+        // Pairs may have been added or removed while it was down; the pairs
+        // lock keeps an `add_pair` from slipping in before it joins the set
+        let exchange = Arc::new(exchange);
+        {
+            let pairs = self.trading_pairs.read().unwrap();
+            exchange.resubscribe(pairs.clone());
+            self.exchanges.write().unwrap().push(exchange.clone());
+        }
+        info!(exchange = exchange_name; "Initialized {} after {} retries", exchange_name, attempts + 1);
+
+        self.run_listener(exchange, price_sender, shutdown, Duration::ZERO)
+            .await;
+    }
+
+    // Listens on `exchange` after `startup_delay`, reconnecting with backoff
+    // whenever the feed stops, until `shutdown` is set
+    async fn run_listener(
+        self,
+        exchange: Arc<ExchangeImpl>,
+        price_sender: mpsc::Sender<PriceUpdate>,
+        mut shutdown: watch::Receiver<bool>,
+        startup_delay: Duration,
+    ) {
+        let exchange_name = exchange.get_name().to_string();
+        tokio::select! {
+            _ = tokio::time::sleep(startup_delay) => {}
+            _ = shutdown.changed() => return,
+        }
+
+        let mut consecutive_failures: u32 = 0;
+        let mut first_attempt = true;
+        loop {
+            if !first_attempt {
+                self.record_reconnect(&exchange_name).await;
+            }
+            first_attempt = false;
+            info!(exchange = exchange_name.as_str(); "Starting {} price feed", exchange_name);
+            let connected_at = Instant::now();
+            let result = exchange
+                .listen(price_sender.clone(), shutdown.clone())
+                .await;
+
+            if *shutdown.borrow() {
+                info!(exchange = exchange_name.as_str(); "Stopped {} price feed", exchange_name);
+                break;
+            }
+
+            if result.is_ok() || connected_at.elapsed() >= self.health.stable_connection_duration()
+            {
+                consecutive_failures = 0;
+            } else {
+                consecutive_failures = consecutive_failures.saturating_add(1);
+            }
+
+            let mut backoff_steps = consecutive_failures;
+            match result {
+                Ok(_) => {
+                    self.update_health_metrics(&exchange_name, true, false)
+                        .await;
+                }
+                Err(e) => match FeedFailure::classify(&e) {
+                    FeedFailure::ClosedNormally => {
+                        info!(exchange = exchange_name.as_str(); "{} closed the price feed: {}", exchange_name, e);
+                        self.mark_disconnected(&exchange_name).await;
+                        consecutive_failures = 0;
+                        backoff_steps = 0;
+                    }
+                    FeedFailure::ClosedByPolicy => {
+                        error!(exchange = exchange_name.as_str(); "{} closed the price feed over policy: {}", exchange_name, e);
+                        self.update_health_metrics(&exchange_name, false, true)
+                            .await;
+                        backoff_steps = backoff_steps.saturating_add(POLICY_CLOSE_BACKOFF_STEPS);
+                    }
+                    FeedFailure::Disconnected => {
+                        warn!(exchange = exchange_name.as_str(); "{} price feed disconnected: {}", exchange_name, e);
+                        self.mark_disconnected(&exchange_name).await;
+                    }
+                    FeedFailure::ConnectFailed => {
+                        error!(exchange = exchange_name.as_str(); "{} price feed failed to connect: {}", exchange_name, e);
+                        self.update_health_metrics(&exchange_name, false, true)
+                            .await;
+                        backoff_steps = backoff_steps.saturating_add(CONNECT_FAILURE_BACKOFF_STEPS);
+                    }
+                    FeedFailure::Fault => {
+                        error!(exchange = exchange_name.as_str(); "{} price feed error: {}", exchange_name, e);
+                        self.update_health_metrics(&exchange_name, false, true)
+                            .await;
+                    }
+                },
+            }
+
+            let delay = reconnect_delay(&self.health, backoff_steps);
+            info!(
+                exchange = exchange_name.as_str();
+                "Reconnecting to {} in {:.1}s ({} consecutive failures)",
+                exchange_name,
+                delay.as_secs_f64(),
+                consecutive_failures
+            );
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = shutdown.changed() => break,
+            }
+        }
     }
 
-    pub async fn run(&self) -> Result<()> {
+    /// Runs until `shutdown` is set, then closes every exchange connection and
+    /// flushes the remaining price updates to Redis before returning.
+    pub async fn run(&self, shutdown: watch::Receiver<bool>) -> Result<()> {
         let (price_sender, mut price_receiver) = mpsc::channel(CHANNEL_SIZE);
 
         // Spawn health check monitoring
-        // let health_check_handle = {
-        //     let publisher = self.clone();
-        //     tokio::spawn(async move {
-        //         publisher.run_health_checks().await;
-        //     })
-        // };
+        let health_check_handle = {
+            let publisher = self.clone();
+            spawn_named("health-checks", async move {
+                publisher.run_health_checks().await;
+            })
+        };
+
+        self.seed_from_wal().await;
+        self.seed_from_snapshots().await;
+
+        let funding_handle = self
+            .funding_receiver
+            .lock()
+            .await
+            .take()
+            .map(|mut receiver| {
+                let publisher = self.clone();
+                spawn_named("funding", async move {
+                    while let Some(update) = receiver.recv().await {
+                        publisher.publish_funding(&update).await;
+                    }
+                })
+            });
 
         // Spawn exchange listeners
-        for exchange in &self.exchanges {
-            let price_sender = price_sender.clone();
-            let exchange_name = exchange.get_name().to_string();
-            let health_metrics = self.health_metrics.clone();
-            let exchange = Arc::new(exchange.as_ref().clone());
+        let exchanges = self.exchanges();
+        for (index, exchange) in exchanges.iter().enumerate() {
+            spawn_named(
+                &format!("listener:{}", exchange.get_name()),
+                self.clone().run_listener(
+                    exchange.clone(),
+                    price_sender.clone(),
+                    shutdown.clone(),
+                    startup_delay(index, exchanges.len()),
+                ),
+            );
+        }
+        for exchange in std::mem::take(&mut *self.failed_exchanges.lock().await) {
+            spawn_named(
+                &format!("retry-init:{}", exchange.get_name()),
+                self.clone()
+                    .retry_init(exchange, price_sender.clone(), shutdown.clone()),
+            );
+        }
 
-            tokio::spawn(async move {
+        // The channel closes once every listener has stopped and dropped its sender
+        drop(price_sender);
+
+        // Drain the channel into the queue as fast as updates arrive, so a
+        // slow sink never blocks the feeds' `send().await`
+        self.queue.reopen();
+        let queue = self.queue.clone();
+        spawn_named("queue-feed", async move {
+            while let Some(update) = price_receiver.recv().await {
+                queue.push(update);
+            }
+            queue.close();
+        });
+
+        // Writes the last update of throttled symbols once their interval passes
+        let throttle_handle = self.throttles_writes().then(|| {
+            let publisher = self.clone();
+            spawn_named("write-throttle", async move {
+                let mut ticker = interval(SINK_BATCH_WINDOW);
                 loop {
-                    info!("Starting {} price feed", exchange_name);
-                    match exchange.listen(price_sender.clone()).await {
-                        Ok(_) => {
-                            let mut metrics = health_metrics.write().await;
-                            if let Some(m) = metrics.get_mut(&exchange_name) {
-                                m.is_connected = true;
-                                m.error_count = 0;
-                            }
-                        }
-                        Err(e) => {
-                            error!("{} price feed error: {}", exchange_name, e);
-                            let mut metrics = health_metrics.write().await;
-                            if let Some(m) = metrics.get_mut(&exchange_name) {
-                                m.is_connected = false;
-                                m.error_count += 1;
-                            }
-                        }
-                    }
-                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    ticker.tick().await;
+                    publisher.flush_held_writes().await;
                 }
-            });
+            })
+        });
+
+        // Process price updates, coalescing those that arrive within
+        // SINK_BATCH_WINDOW into a single batch
+        while let Some(updates) = self.queue.next_batch(SINK_BATCH_WINDOW).await {
+            self.publish_updates(updates).await;
         }
 
-        // Process price updates
-        while let Some(update) = price_receiver.recv().await {
-            // Update latest prices
-            {
-                let mut latest_prices = self.latest_prices.write().await;
-                latest_prices
-                    .entry(update.symbol.clone())
-                    .or_default()
-                    .insert(update.source.clone(), (update.price, update.timestamp));
+        if let Some(handle) = throttle_handle {
+            handle.abort();
+        }
+        let held = self.take_due_writes(Vec::new(), true).await;
+        self.write_updates(held, HashSet::new()).await;
+        for result in futures::future::join_all(self.sinks.iter().map(|sink| sink.sync())).await {
+            if let Err(e) = result {
+                error!("Failed to sync price sink: {}", e);
+            }
+        }
+
+        health_check_handle.abort();
+        if let Some(handle) = funding_handle {
+            handle.abort();
+        }
+        info!("All price feeds stopped and price writes flushed");
+        Ok(())
+    }
+
+    /// Runs `exchange` until it stops, publishing every update on its own as
+    /// it arrives, so a `FileReplayExchange` replays deterministically.
+    pub async fn replay<E: Exchange>(&self, exchange: &E) -> Result<()> {
+        let (price_sender, mut price_receiver) = mpsc::channel(CHANNEL_SIZE);
+        let (_shutdown_sender, shutdown) = watch::channel(false);
+        let publish = async {
+            while let Some(update) = price_receiver.recv().await {
+                self.publish_updates(vec![update]).await;
             }
+        };
+        let (result, ()) = tokio::join!(exchange.listen(price_sender, shutdown), publish);
+        Ok(result?)
+    }
 
-            // Write to Redis
-            if let Err(e) = self.write_to_redis(&update).await {
-                error!("Failed to write to Redis: {}", e);
+    /// Runs `updates` through validation, dedupe and outlier checks, as if
+    /// they had arrived together from the feeds, and writes whatever survives
+    /// to the sink.
+    pub async fn publish_updates(&self, updates: Vec<PriceUpdate>) {
+        let mut published = Vec::with_capacity(updates.len());
+        for update in updates {
+            let update = self.adopt_exchange_time(self.rescale(update)).await;
+            let accepted = match update.kind {
+                PriceKind::LastTrade => self.process_trade(&update).await,
+                _ => self.process_update(&update).await,
+            };
+            if accepted {
+                published.push(update);
             }
+        }
+        self.flush_batch(published).await;
+    }
 
-            info!(
-                "Received price update from {}: {} = {}",
-                update.source, update.symbol, update.price
-            );
+    /// Writes a funding rate to `funding:{symbol}`, with the same expiry as
+    /// the symbol's price keys. Untracked symbols are dropped.
+    pub async fn publish_funding(&self, update: &FundingUpdate) {
+        if !self.is_tracked(&update.symbol) {
+            return;
         }
 
-        // Keep the main task alive
-        loop {
-            tokio::time::sleep(Duration::from_secs(1)).await;
+        let expiry = self.price_expiry(&update.symbol);
+        let writes = self
+            .sinks
+            .iter()
+            .map(|sink| sink.write_funding(update, expiry));
+        let mut failed = false;
+        for result in futures::future::join_all(writes).await {
+            if let Err(e) = result {
+                error!("Failed to write funding for {}: {}", update.symbol, e);
+                failed = true;
+            }
+        }
+        if failed {
+            return;
+        }
+        debug!(
+            exchange = update.source.as_str(), symbol = update.symbol.as_str();
+            "Funding from {}: {} = {}",
+            update.source, update.symbol, update.funding_rate
+        );
+    }
+
+    /// Runs the feeds until every symbol in `symbols` has a preferred price or
+    /// `timeout` elapses, then shuts them down and returns what it has.
+    /// Symbols still without a price at the deadline are left out.
+    pub async fn snapshot(
+        &self,
+        symbols: &[String],
+        timeout: Duration,
+    ) -> Result<HashMap<String, Decimal>> {
+        let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+        let run_handle = {
+            let publisher = self.clone();
+            spawn_named("snapshot-run", async move {
+                publisher.run(shutdown_receiver).await
+            })
+        };
+
+        let deadline = Instant::now() + timeout;
+        let mut poll = interval(SNAPSHOT_POLL_INTERVAL);
+        let prices = loop {
+            poll.tick().await;
+            let mut prices = HashMap::new();
+            for symbol in symbols {
+                if let Some((_, price, _)) = self.preferred_price(symbol).await {
+                    prices.insert(symbol.clone(), price);
+                }
+            }
+            if prices.len() == symbols.len() || Instant::now() >= deadline {
+                break prices;
+            }
+        };
+
+        for symbol in symbols {
+            if !prices.contains_key(symbol) {
+                warn!(symbol = symbol.as_str(); "No price for {} within {}s", symbol, timeout.as_secs());
+            }
         }
+
+        let _ = shutdown_sender.send(true);
+        run_handle.await??;
+        Ok(prices)
     }
 
     pub async fn get_exchange_health(&self) -> HashMap<String, ExchangeHealth> {
-        self.health_metrics.read().await.clone()
+        let mut health_metrics = self.health_metrics.read().await.clone();
+        for exchange in self.exchanges() {
+            if let Some(health) = health_metrics.get_mut(exchange.get_name()) {
+                health.ticks_last_interval = exchange.ticks_last_interval();
+                health.queued_updates = self.queue.len_for_source(exchange.get_name());
+                health.ws_state = exchange.ws_state();
+            }
+        }
+
+        if self.reference_source.is_some() {
+            let symbols = self.tracked_symbols.read().unwrap().clone();
+            for symbol in symbols {
+                for (source, deviation) in self.compute_deviations(&symbol).await {
+                    if let Some(health) = health_metrics.get_mut(&source) {
+                        health
+                            .reference_deviation_bps
+                            .insert(symbol.clone(), deviation);
+                    }
+                }
+            }
+        }
+        health_metrics
+    }
+
+    /// True once some connected exchange has a price within its symbol's
+    /// staleness window, i.e. the publisher is serving live prices.
+    pub async fn is_ready(&self) -> bool {
+        let health = self.get_exchange_health().await;
+        let now = self.clock.now();
+        self.get_latest_prices()
+            .await
+            .iter()
+            .any(|(symbol, sources)| {
+                let stale_after = self.stale_threshold(symbol);
+                sources.iter().any(|(source, (_, timestamp))| {
+                    health.get(source).is_some_and(|health| health.is_connected)
+                        && is_fresh(*timestamp, now, stale_after)
+                })
+            })
+    }
+
+    /// True while some connected exchange has sent a price within
+    /// `max_age`. Exchange-stamped prices can be ahead of our clock; those
+    /// count as just received.
+    pub async fn is_sending_prices(&self, max_age: Duration) -> bool {
+        let now = self.clock.now();
+        self.health_metrics
+            .read()
+            .await
+            .values()
+            .any(|health| health.is_connected && is_fresh(health.last_update, now, max_age))
+    }
+
+    /// How long before now `timestamp` was, by the publisher's clock. Zero
+    /// for a timestamp ahead of it, e.g. from an exchange whose clock runs
+    /// fast.
+    pub fn age_of(&self, timestamp: SystemTime) -> Duration {
+        self.clock
+            .now()
+            .duration_since(timestamp)
+            .unwrap_or_default()
+    }
+
+    /// Fraction of the update queue in use, from 0 to 1. Near 1 the sink is
+    /// falling behind and stale updates are being dropped.
+    pub fn queue_utilization(&self) -> f64 {
+        self.queue.utilization()
+    }
+
+    /// Prometheus text exposition of the publisher's metrics, with exchange
+    /// health gauges refreshed from the current health metrics.
+    pub async fn render_metrics(&self) -> Result<String> {
+        for (exchange, health) in self.health_metrics.read().await.iter() {
+            self.metrics.record_exchange_health(exchange, health);
+        }
+        let now = self.clock.now();
+        for (symbol, sources) in self.latest_prices.read().await.iter() {
+            for (source, (_, timestamp)) in sources {
+                let age = now.duration_since(*timestamp).unwrap_or_default();
+                self.metrics.record_price_age(source, symbol, age);
+            }
+        }
+        self.metrics
+            .record_queue_utilization(self.queue.utilization());
+        self.metrics.encode()
+    }
+
+    /// Latest price and receive time from one source, without cloning the
+    /// rest of the price map.
+    pub async fn get_price(&self, symbol: &str, source: &str) -> Option<(Decimal, SystemTime)> {
+        self.latest_prices
+            .read()
+            .await
+            .get(symbol)?
+            .get(source)
+            .copied()
+    }
+
+    /// Cross-exchange consensus for `symbol`, as written to
+    /// `price:{symbol}:consensus`.
+    pub async fn get_consensus_price(&self, symbol: &str) -> Option<Decimal> {
+        self.consensus(symbol).await
     }
 
-    pub async fn get_latest_prices(&self) -> HashMap<String, HashMap<String, (f64, SystemTime)>> {
+    pub async fn get_latest_prices(&self) -> HashMap<String, SourcePrices> {
         self.latest_prices.read().await.clone()
     }
 }
@@ -0,0 +1,122 @@
+use log::warn;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::sync::Notify;
+use tokio::time::{sleep, Duration};
+
+use crate::types::PriceUpdate;
+
+/// Price updates received from the feeds and waiting to be processed.
+///
+/// The feeds' channel is drained into this queue as fast as updates arrive,
+/// so a slow sink never blocks an exchange's read loop. Once `capacity`
+/// updates are queued, a new update replaces the oldest queued one for the
+/// same symbol and source (or the oldest overall, if there is none), so the
+/// publisher falls behind on stale prices rather than stalling every feed.
+pub struct UpdateQueue {
+    capacity: usize,
+    state: Mutex<QueueState>,
+    notify: Notify,
+}
+
+#[derive(Default)]
+struct QueueState {
+    updates: VecDeque<PriceUpdate>,
+    // Dropped since the last batch was taken
+    dropped: usize,
+    closed: bool,
+}
+
+impl UpdateQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(QueueState::default()),
+            notify: Notify::new(),
+        }
+    }
+
+    pub fn push(&self, update: PriceUpdate) {
+        let mut state = self.state.lock().unwrap();
+        if state.updates.len() >= self.capacity {
+            let superseded = state.updates.iter().position(|queued| {
+                queued.symbol == update.symbol
+                    && queued.source == update.source
+                    && queued.kind == update.kind
+            });
+            match superseded {
+                Some(index) => state.updates.remove(index),
+                None => state.updates.pop_front(),
+            };
+            state.dropped += 1;
+        }
+        state.updates.push_back(update);
+        drop(state);
+        self.notify.notify_one();
+    }
+
+    /// No more updates will be pushed; `next_batch` returns None once the
+    /// queue has drained.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.notify.notify_one();
+    }
+
+    /// Accepts updates again after `close`, for a publisher that is run more
+    /// than once.
+    pub fn reopen(&self) {
+        self.state.lock().unwrap().closed = false;
+    }
+
+    /// Waits for an update, then for `window` so updates arriving close
+    /// together are handled as one batch, and takes everything queued.
+    pub async fn next_batch(&self, window: Duration) -> Option<Vec<PriceUpdate>> {
+        loop {
+            {
+                let state = self.state.lock().unwrap();
+                if !state.updates.is_empty() {
+                    break;
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.notify.notified().await;
+        }
+        sleep(window).await;
+
+        let mut state = self.state.lock().unwrap();
+        if state.dropped > 0 {
+            warn!(
+                "Update queue full, dropped {} stale price updates",
+                state.dropped
+            );
+            state.dropped = 0;
+        }
+        Some(state.updates.drain(..).collect())
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().updates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Queued updates from `source`.
+    pub fn len_for_source(&self, source: &str) -> usize {
+        self.state
+            .lock()
+            .unwrap()
+            .updates
+            .iter()
+            .filter(|update| update.source == source)
+            .count()
+    }
+
+    /// Fraction of the capacity in use, from 0 to 1.
+    pub fn utilization(&self) -> f64 {
+        self.len() as f64 / self.capacity as f64
+    }
+}
@@ -0,0 +1,140 @@
+use anyhow::Result;
+use futures::FutureExt;
+use redis::aio::{ConnectionLike, MultiplexedConnection};
+use redis::cluster::ClusterClient;
+use redis::cluster_async::ClusterConnection;
+use redis::cluster_routing::get_slot;
+use redis::{Arg, Cmd, Pipeline, RedisFuture, RedisResult, Value};
+
+/// A single Redis server, or a Redis Cluster for `redis+cluster://` and
+/// `rediss+cluster://` URLs or when `REDIS_CLUSTER=1` is set. A cluster URL
+/// names one seed node; the rest are discovered from it.
+#[derive(Clone)]
+pub enum RedisClient {
+    Single(redis::Client),
+    Cluster(ClusterClient),
+}
+
+impl RedisClient {
+    pub fn open(redis_url: &str) -> Result<Self> {
+        let cluster_env = std::env::var("REDIS_CLUSTER").is_ok_and(|value| value == "1");
+        match strip_cluster_scheme(redis_url) {
+            Some(node_url) => Ok(RedisClient::Cluster(ClusterClient::new(vec![node_url])?)),
+            None if cluster_env => Ok(RedisClient::Cluster(ClusterClient::new(vec![redis_url])?)),
+            None => Ok(RedisClient::Single(redis::Client::open(redis_url)?)),
+        }
+    }
+
+    pub fn is_cluster(&self) -> bool {
+        matches!(self, RedisClient::Cluster(_))
+    }
+
+    /// A connection that can be cloned and shared by concurrent writers.
+    pub async fn get_connection(&self) -> RedisResult<RedisConnection> {
+        match self {
+            RedisClient::Single(client) => Ok(RedisConnection::Single(
+                client.get_multiplexed_async_connection().await?,
+            )),
+            RedisClient::Cluster(client) => Ok(RedisConnection::Cluster(
+                client.get_async_connection().await?,
+            )),
+        }
+    }
+}
+
+/// The URL of one server to connect to directly, e.g. for pub/sub, which
+/// reaches every subscriber whichever cluster node it is published on.
+pub fn node_url(redis_url: &str) -> String {
+    strip_cluster_scheme(redis_url).unwrap_or_else(|| redis_url.to_string())
+}
+
+fn strip_cluster_scheme(redis_url: &str) -> Option<String> {
+    let (scheme, rest) = redis_url.split_once("+cluster://")?;
+    matches!(scheme, "redis" | "rediss").then(|| format!("{}://{}", scheme, rest))
+}
+
+/// A connection from `RedisClient`. Pipelines sent to a cluster are split
+/// by hash slot, since a cluster pipeline can only touch one slot and a
+/// batch writes keys for every symbol. Commands keep their order within a
+/// slot, so writes to any one key still land in order, but a batch is not
+/// applied atomically across slots.
+#[derive(Clone)]
+pub enum RedisConnection {
+    Single(MultiplexedConnection),
+    Cluster(ClusterConnection),
+}
+
+// Slot of a command's first argument after the name, which is the key (or
+// channel) for every command the publisher sends; None for commands without
+// one, which any node can answer
+fn command_slot(cmd: &Cmd) -> Option<u16> {
+    match cmd.args_iter().nth(1)? {
+        Arg::Simple(key) => Some(get_slot(key)),
+        Arg::Cursor => None,
+    }
+}
+
+// Sends each slot's commands as their own pipeline and returns the replies
+// in the original command order
+async fn query_by_slot(
+    conn: &mut ClusterConnection,
+    pipeline: &Pipeline,
+) -> RedisResult<Vec<Value>> {
+    let mut groups: Vec<(Option<u16>, Pipeline, Vec<usize>)> = Vec::new();
+    for (index, cmd) in pipeline.cmd_iter().enumerate() {
+        let slot = command_slot(cmd);
+        let position = match groups.iter().position(|(group, _, _)| *group == slot) {
+            Some(position) => position,
+            None => {
+                groups.push((slot, Pipeline::new(), Vec::new()));
+                groups.len() - 1
+            }
+        };
+        let (_, group, indices) = &mut groups[position];
+        group.add_command(cmd.clone());
+        indices.push(index);
+    }
+
+    let mut replies = vec![Value::Nil; pipeline.cmd_iter().count()];
+    for (_, group, indices) in groups {
+        let values = conn.req_packed_commands(&group, 0, indices.len()).await?;
+        for (index, value) in indices.into_iter().zip(values) {
+            replies[index] = value;
+        }
+    }
+    Ok(replies)
+}
+
+impl ConnectionLike for RedisConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        match self {
+            RedisConnection::Single(conn) => conn.req_packed_command(cmd),
+            RedisConnection::Cluster(conn) => conn.req_packed_command(cmd),
+        }
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        pipeline: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        match self {
+            RedisConnection::Single(conn) => conn.req_packed_commands(pipeline, offset, count),
+            RedisConnection::Cluster(conn) => {
+                // An offset means MULTI/EXEC, which can't be split
+                if offset > 0 {
+                    return conn.req_packed_commands(pipeline, offset, count);
+                }
+                query_by_slot(conn, pipeline).boxed()
+            }
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match self {
+            RedisConnection::Single(conn) => conn.get_db(),
+            RedisConnection::Cluster(conn) => conn.get_db(),
+        }
+    }
+}
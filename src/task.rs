@@ -0,0 +1,26 @@
+use std::future::Future;
+use tokio::task::JoinHandle;
+
+/// Spawns `future` on the runtime under `name`, so tokio-console can tell
+/// the publisher's tasks apart. Names are only recorded with the
+/// `tokio-console` feature and `--cfg tokio_unstable`; otherwise this is
+/// plain `tokio::spawn`.
+#[track_caller]
+pub fn spawn_named<F>(name: &str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    #[cfg(all(tokio_unstable, feature = "tokio-console"))]
+    {
+        tokio::task::Builder::new()
+            .name(name)
+            .spawn(future)
+            .expect("failed to spawn task")
+    }
+    #[cfg(not(all(tokio_unstable, feature = "tokio-console")))]
+    {
+        let _ = name;
+        tokio::spawn(future)
+    }
+}
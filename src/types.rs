@@ -1,35 +1,353 @@
-use serde::{Deserialize, Serialize};
-use std::time::SystemTime;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PriceKind {
+    // Best bid and best ask, combined according to the PriceMethod
+    TopOfBook,
+    // Mid of bid/ask VWAPs over several order book levels
+    DepthWeighted,
+    // Price of the most recent trade; kept apart from the book prices
+    LastTrade,
+}
+
+// How a top-of-book price is derived from the best bid and ask
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceMethod {
+    #[default]
+    Mid,
+    // Conservative valuation of long positions
+    Bid,
+    Ask,
+    // Mid skewed towards the side with less size, the likelier next price
+    WeightedMid,
+}
+
+impl PriceMethod {
+    /// Price for a top of book. `sizes` are the best bid and ask sizes where
+    /// the feed sends them; without them `WeightedMid` is a plain mid.
+    pub fn price(&self, bid: Decimal, ask: Decimal, sizes: Option<(Decimal, Decimal)>) -> Decimal {
+        let mid = (bid + ask) / Decimal::TWO;
+        match self {
+            PriceMethod::Mid => mid,
+            PriceMethod::Bid => bid,
+            PriceMethod::Ask => ask,
+            PriceMethod::WeightedMid => match sizes {
+                Some((bid_size, ask_size)) => (bid * ask_size + ask * bid_size)
+                    .checked_div(bid_size + ask_size)
+                    .unwrap_or(mid),
+                None => mid,
+            },
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PriceUpdate {
     pub symbol: String,
-    pub price: f64,
+    pub price: Decimal,
+    pub timestamp: SystemTime,
+    pub source: String,
+    pub kind: PriceKind,
+    // Top of book behind `price`, for feeds that publish both sides
+    pub bid: Option<Decimal>,
+    pub ask: Option<Decimal>,
+    // Event time reported by the exchange, when the feed provides one
+    pub exchange_timestamp: Option<SystemTime>,
+}
+
+impl PriceUpdate {
+    /// Multiplies the price and both sides of the book by `factor`, e.g. to
+    /// turn a per-1000-unit quote into a per-unit one.
+    pub fn scaled(mut self, factor: Decimal) -> Self {
+        if factor != Decimal::ONE {
+            self.price *= factor;
+            self.bid = self.bid.map(|bid| bid * factor);
+            self.ask = self.ask.map(|ask| ask * factor);
+        }
+        self
+    }
+
+    /// False for prices no market would quote: zero, negative or absurdly
+    /// large, including either side of the book when present.
+    pub fn is_valid(&self) -> bool {
+        // Prices above this are treated as feed errors rather than market data
+        let max_price = Decimal::new(1_000_000_000_000, 0);
+        let in_range = |price: Decimal| price > Decimal::ZERO && price < max_price;
+        in_range(self.price) && self.bid.is_none_or(in_range) && self.ask.is_none_or(in_range)
+    }
+
+    /// Spread between the bid and ask as a percentage of their mid; None
+    /// unless both sides are present. Zero or negative for a locked or
+    /// crossed book.
+    pub fn spread_pct(&self) -> Option<Decimal> {
+        let (bid, ask) = (self.bid?, self.ask?);
+        let mid = (bid + ask) / Decimal::TWO;
+        if mid <= Decimal::ZERO {
+            return None;
+        }
+        Some((ask - bid) / mid * Decimal::ONE_HUNDRED)
+    }
+}
+
+/// Version of the JSON envelope `to_wire_json` produces.
+pub const WIRE_VERSION: u32 = 1;
+
+// `{"v":1,"type":"price","data":{..}}`, so consumers can tell formats and
+// message types apart before reading the payload
+#[derive(Serialize, Deserialize)]
+struct WireEnvelope<T> {
+    v: u32,
+    #[serde(rename = "type")]
+    message_type: String,
+    data: T,
+}
+
+// PriceUpdate with times as milliseconds since epoch, which JS consumers
+// can pass straight to `new Date()`
+#[derive(Serialize, Deserialize)]
+struct WirePriceUpdate {
+    symbol: String,
+    price: Decimal,
+    timestamp: u64,
+    source: String,
+    kind: PriceKind,
+    bid: Option<Decimal>,
+    ask: Option<Decimal>,
+    exchange_timestamp: Option<u64>,
+}
+
+fn millis_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl PriceUpdate {
+    /// The versioned wire format published to Redis pub/sub and NATS:
+    /// `{"v":1,"type":"price","data":{..}}` with timestamps in milliseconds.
+    pub fn to_wire_json(&self) -> String {
+        let envelope = WireEnvelope {
+            v: WIRE_VERSION,
+            message_type: "price".to_string(),
+            data: WirePriceUpdate {
+                symbol: self.symbol.clone(),
+                price: self.price,
+                timestamp: millis_since_epoch(self.timestamp),
+                source: self.source.clone(),
+                kind: self.kind,
+                bid: self.bid,
+                ask: self.ask,
+                exchange_timestamp: self.exchange_timestamp.map(millis_since_epoch),
+            },
+        };
+        // Only strings, numbers and Decimals, which always serialize
+        serde_json::to_string(&envelope).expect("price update serializes")
+    }
+
+    /// Parses `to_wire_json` output, rejecting other versions and types.
+    pub fn from_wire_json(text: &str) -> anyhow::Result<Self> {
+        let envelope: WireEnvelope<serde_json::Value> = serde_json::from_str(text)?;
+        if envelope.v != WIRE_VERSION {
+            anyhow::bail!("Unsupported wire version {}", envelope.v);
+        }
+        if envelope.message_type != "price" {
+            anyhow::bail!("Expected a price message, got {:?}", envelope.message_type);
+        }
+        let data: WirePriceUpdate = serde_json::from_value(envelope.data)?;
+        Ok(Self {
+            symbol: data.symbol,
+            price: data.price,
+            timestamp: UNIX_EPOCH + Duration::from_millis(data.timestamp),
+            source: data.source,
+            kind: data.kind,
+            bid: data.bid,
+            ask: data.ask,
+            exchange_timestamp: data
+                .exchange_timestamp
+                .map(|millis| UNIX_EPOCH + Duration::from_millis(millis)),
+        })
+    }
+}
+
+// Quote details behind a published price
+#[derive(Debug, Clone, Serialize)]
+pub struct PriceMeta {
+    pub bid: Option<Decimal>,
+    pub ask: Option<Decimal>,
+    pub mid: Decimal,
+    // Bid/ask spread in basis points of the mid
+    pub spread_bps: Option<Decimal>,
+    pub source: String,
+    #[serde(serialize_with = "serialize_unix_timestamp")]
     pub timestamp: SystemTime,
+}
+
+impl PriceMeta {
+    pub fn new(
+        bid: Option<Decimal>,
+        ask: Option<Decimal>,
+        mid: Decimal,
+        source: String,
+        timestamp: SystemTime,
+    ) -> Self {
+        let spread_bps = match (bid, ask) {
+            (Some(bid), Some(ask)) => (ask - bid)
+                .checked_div(mid)
+                .map(|ratio| ratio * Decimal::new(10_000, 0)),
+            _ => None,
+        };
+        Self {
+            bid,
+            ask,
+            mid,
+            spread_bps,
+            source,
+            timestamp,
+        }
+    }
+}
+
+// Perpetual funding for a symbol, published separately from its price
+#[derive(Debug, Clone, Serialize)]
+pub struct FundingUpdate {
+    pub symbol: String,
+    // Rate paid per funding interval, as a fraction of position notional
+    pub funding_rate: Decimal,
+    #[serde(serialize_with = "serialize_unix_timestamp")]
+    pub next_funding_ts: SystemTime,
     pub source: String,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+// Open/high/low/close over a fixed interval starting at `start_ts`
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct OhlcBar {
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    #[serde(serialize_with = "serialize_unix_timestamp")]
+    pub start_ts: SystemTime,
+}
+
+impl OhlcBar {
+    pub fn new(price: Decimal, start_ts: SystemTime) -> Self {
+        Self {
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            start_ts,
+        }
+    }
+
+    pub fn update(&mut self, price: Decimal) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+    }
+}
+
+// Renders a SystemTime as whole seconds since the unix epoch
+pub fn serialize_unix_timestamp<S: Serializer>(
+    time: &SystemTime,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    serializer.serialize_u64(secs)
+}
+
+pub fn serialize_optional_unix_timestamp<S: Serializer>(
+    time: &Option<SystemTime>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match time {
+        Some(time) => serialize_unix_timestamp(time, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn serialize_duration_millis<S: Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_millis() as u64)
+}
+
+// Converts an exchange-provided unix timestamp in milliseconds
+pub fn system_time_from_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Exchange {
     Binance,
+    Bitstamp,
     Bybit,
     Coinbase,
+    Gateio,
+    Htx,
     Hyperliquid,
+    Kraken,
+    KuCoin,
+    Okx,
+    #[serde(rename = "univ2")]
     UniswapV2,
 }
 
 impl Exchange {
+    pub const ALL: [Exchange; 11] = [
+        Exchange::Binance,
+        Exchange::Bitstamp,
+        Exchange::Bybit,
+        Exchange::Coinbase,
+        Exchange::Gateio,
+        Exchange::Htx,
+        Exchange::Hyperliquid,
+        Exchange::Kraken,
+        Exchange::KuCoin,
+        Exchange::Okx,
+        Exchange::UniswapV2,
+    ];
+
     pub fn as_str(&self) -> &'static str {
         match self {
             Exchange::Binance => "binance",
+            Exchange::Bitstamp => "bitstamp",
             Exchange::Bybit => "bybit",
             Exchange::Coinbase => "coinbase",
+            Exchange::Gateio => "gateio",
+            Exchange::Htx => "htx",
             Exchange::Hyperliquid => "hyperliquid",
+            Exchange::Kraken => "kraken",
+            Exchange::KuCoin => "kucoin",
+            Exchange::Okx => "okx",
             Exchange::UniswapV2 => "univ2",
         }
     }
 }
 
+impl FromStr for Exchange {
+    type Err = anyhow::Error;
+
+    // Accepts the same names as the config file, e.g. "binance" or "univ2"
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let name = name.to_lowercase();
+        Exchange::ALL
+            .into_iter()
+            .find(|exchange| exchange.as_str() == name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown exchange: {}", name))
+    }
+}
+
 // Represents a trading pair (e.g., BTC/USD)
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub struct TradingPair {
@@ -45,10 +363,34 @@ impl TradingPair {
         }
     }
 
+    /// Splits a symbol such as `BTCUSDT`, `BTC-USDT` or `BTC/USDT` back into a
+    /// pair, using `quote_hints` to tell where the base ends. The longest
+    /// matching quote wins, so `["USD", "USDT"]` parses `BTCUSDT` as BTC/USDT.
+    pub fn from_symbol(symbol: &str, quote_hints: &[&str]) -> Option<TradingPair> {
+        let symbol = symbol.to_uppercase().replace(['-', '/', '_'], "");
+        quote_hints
+            .iter()
+            .map(|quote| quote.to_uppercase())
+            .filter(|quote| !quote.is_empty() && symbol.len() > quote.len())
+            .filter(|quote| symbol.ends_with(quote.as_str()))
+            .max_by_key(|quote| quote.len())
+            .map(|quote| TradingPair::new(&symbol[..symbol.len() - quote.len()], &quote))
+    }
+
+    // Symbol used for PriceUpdates and Redis keys regardless of source
+    pub fn to_canonical_symbol(&self) -> String {
+        format!("{}{}", self.base, self.quote)
+    }
+
     pub fn to_binance_symbol(&self) -> String {
         format!("{}{}", self.base, self.quote)
     }
 
+    // Bitstamp channels and REST paths use lowercase, e.g. btcusd
+    pub fn to_bitstamp_symbol(&self) -> String {
+        format!("{}{}", self.base, self.quote).to_lowercase()
+    }
+
     pub fn to_bybit_symbol(&self) -> String {
         format!("{}{}", self.base, self.quote)
     }
@@ -57,7 +399,260 @@ impl TradingPair {
         format!("{}-{}", self.base, self.quote)
     }
 
+    pub fn to_gateio_symbol(&self) -> String {
+        format!("{}_{}", self.base, self.quote)
+    }
+
+    // HTX topics use lowercase, e.g. market.btcusdt.bbo
+    pub fn to_htx_symbol(&self) -> String {
+        format!("{}{}", self.base, self.quote).to_lowercase()
+    }
+
+    pub fn to_kucoin_symbol(&self) -> String {
+        format!("{}-{}", self.base, self.quote)
+    }
+
+    pub fn to_okx_symbol(&self) -> String {
+        format!("{}-{}", self.base, self.quote)
+    }
+
+    pub fn to_kraken_symbol(&self) -> String {
+        // Kraken uses the ISO 4217-A3 style XBT code for bitcoin
+        let base = match self.base.as_str() {
+            "BTC" => "XBT",
+            other => other,
+        };
+        format!("{}/{}", base, self.quote)
+    }
+
     pub fn to_redis_key(&self) -> String {
         format!("price:{}:{}", self.base, self.quote)
     }
 }
+
+// Renders as `BTC/USDT`
+impl fmt::Display for TradingPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.base, self.quote)
+    }
+}
+
+impl FromStr for TradingPair {
+    type Err = anyhow::Error;
+
+    // Accepts `BTC/USDT` or `BTC-USDT`, in any case
+    fn from_str(pair: &str) -> Result<Self, Self::Err> {
+        match pair.split_once(['/', '-']) {
+            Some((base, quote))
+                if !base.is_empty() && !quote.is_empty() && !quote.contains(['/', '-']) =>
+            {
+                Ok(TradingPair::new(base.trim(), quote.trim()))
+            }
+            _ => Err(anyhow::anyhow!(
+                "Invalid trading pair {:?}, expected e.g. BTC/USDT",
+                pair
+            )),
+        }
+    }
+}
+
+impl Serialize for TradingPair {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+// Config files may list pairs as "BTC/USDT" strings or as the older
+// {base, quote} tables
+impl<'de> Deserialize<'de> for TradingPair {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Text(String),
+            Table { base: String, quote: String },
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Text(pair) => pair.parse().map_err(serde::de::Error::custom),
+            Repr::Table { base, quote } => Ok(TradingPair::new(&base, &quote)),
+        }
+    }
+}
+
+// Casing of the symbol parts substituted into Redis keys
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyCase {
+    #[default]
+    Upper,
+    Lower,
+}
+
+/// Renders a pair's `price:{symbol}` key from a template, so keys can follow
+/// an existing consumer's convention. `{symbol}`, `{base}` and `{quote}` are
+/// replaced with the canonical symbol and its parts, in `case`; the rest of
+/// the template is kept as written. The `:sources`, `:meta` and other
+/// suffixed keys hang off the rendered key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedisKeyFormat {
+    template: String,
+    case: KeyCase,
+}
+
+impl Default for RedisKeyFormat {
+    fn default() -> Self {
+        Self::new("price:{symbol}", KeyCase::Upper)
+    }
+}
+
+impl RedisKeyFormat {
+    pub fn new(template: &str, case: KeyCase) -> Self {
+        Self {
+            template: template.to_string(),
+            case,
+        }
+    }
+
+    /// Whether the template tells symbols apart; without a placeholder every
+    /// pair would write the same key.
+    pub fn is_per_symbol(&self) -> bool {
+        ["{symbol}", "{base}", "{quote}"]
+            .iter()
+            .any(|placeholder| self.template.contains(placeholder))
+    }
+
+    pub fn price_key(&self, pair: &TradingPair) -> String {
+        let cased = |part: String| match self.case {
+            KeyCase::Upper => part.to_uppercase(),
+            KeyCase::Lower => part.to_lowercase(),
+        };
+        self.template
+            .replace("{symbol}", &cased(pair.to_canonical_symbol()))
+            .replace("{base}", &cased(pair.base.clone()))
+            .replace("{quote}", &cased(pair.quote.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exchange_symbols_concatenate_or_dash_uppercase_codes() {
+        let pair = TradingPair::new("BTC", "USDT");
+        assert_eq!(pair.to_binance_symbol(), "BTCUSDT");
+        assert_eq!(pair.to_bybit_symbol(), "BTCUSDT");
+        assert_eq!(pair.to_coinbase_symbol(), "BTC-USDT");
+        assert_eq!(pair.to_redis_key(), "price:BTC:USDT");
+    }
+
+    #[test]
+    fn spread_pct_is_relative_to_the_mid() {
+        let quote = |bid: i64, ask: i64| PriceUpdate {
+            symbol: "BTCUSDT".to_string(),
+            price: Decimal::new(100, 0),
+            timestamp: SystemTime::now(),
+            source: "binance".to_string(),
+            kind: PriceKind::TopOfBook,
+            bid: Some(Decimal::new(bid, 0)),
+            ask: Some(Decimal::new(ask, 0)),
+            exchange_timestamp: None,
+        };
+        assert_eq!(quote(99, 101).spread_pct(), Some(Decimal::TWO));
+        assert_eq!(quote(100, 100).spread_pct(), Some(Decimal::ZERO));
+        assert!(quote(101, 99).spread_pct().unwrap() < Decimal::ZERO);
+        let mid_only = PriceUpdate {
+            bid: None,
+            ..quote(99, 101)
+        };
+        assert_eq!(mid_only.spread_pct(), None);
+    }
+
+    #[test]
+    fn wire_json_round_trips_with_millisecond_timestamps() {
+        let update = PriceUpdate {
+            symbol: "BTCUSDT".to_string(),
+            price: Decimal::new(1005, 1),
+            timestamp: system_time_from_millis(1_700_000_000_123),
+            source: "binance".to_string(),
+            kind: PriceKind::TopOfBook,
+            bid: Some(Decimal::new(100, 0)),
+            ask: Some(Decimal::new(101, 0)),
+            exchange_timestamp: None,
+        };
+        let wire = update.to_wire_json();
+        assert!(wire.starts_with(r#"{"v":1,"type":"price","data":{"#));
+        assert!(wire.contains(r#""timestamp":1700000000123"#));
+
+        let parsed = PriceUpdate::from_wire_json(&wire).unwrap();
+        assert_eq!(parsed.timestamp, update.timestamp);
+        assert_eq!(parsed.price, update.price);
+        assert_eq!(parsed.bid, update.bid);
+        assert!(PriceUpdate::from_wire_json(&wire.replace(r#""v":1"#, r#""v":2"#)).is_err());
+    }
+
+    #[test]
+    fn htx_symbols_are_lowercase() {
+        assert_eq!(TradingPair::new("BTC", "USDT").to_htx_symbol(), "btcusdt");
+    }
+
+    #[test]
+    fn bitstamp_symbols_are_lowercase() {
+        assert_eq!(
+            TradingPair::new("BTC", "USD").to_bitstamp_symbol(),
+            "btcusd"
+        );
+        assert_eq!(
+            TradingPair::new("eth", "usd").to_bitstamp_symbol(),
+            "ethusd"
+        );
+    }
+
+    #[test]
+    fn redis_key_format_fills_in_the_pair() {
+        let pair = TradingPair::new("BTC", "USDT");
+        assert_eq!(RedisKeyFormat::default().price_key(&pair), "price:BTCUSDT");
+        assert_eq!(
+            RedisKeyFormat::new("price:{base}:{quote}", KeyCase::Upper).price_key(&pair),
+            pair.to_redis_key()
+        );
+        assert_eq!(
+            RedisKeyFormat::new("price:{base}-{quote}", KeyCase::Lower).price_key(&pair),
+            "price:btc-usdt"
+        );
+        assert!(!RedisKeyFormat::new("price", KeyCase::Upper).is_per_symbol());
+    }
+
+    #[test]
+    fn lowercase_input_is_uppercased() {
+        let pair = TradingPair::new("btc", "usdt");
+        assert_eq!(pair.to_binance_symbol(), "BTCUSDT");
+        assert_eq!(pair.to_bybit_symbol(), "BTCUSDT");
+        assert_eq!(pair.to_coinbase_symbol(), "BTC-USDT");
+        assert_eq!(pair.to_redis_key(), "price:BTC:USDT");
+    }
+
+    #[test]
+    fn from_symbol_round_trips_exchange_symbols() {
+        let quotes = ["USD", "USDT", "USDC"];
+        for pair in [
+            TradingPair::new("BTC", "USDT"),
+            TradingPair::new("eth", "usd"),
+            TradingPair::new("SOL", "USDC"),
+        ] {
+            for symbol in [
+                pair.to_binance_symbol(),
+                pair.to_bybit_symbol(),
+                pair.to_coinbase_symbol(),
+            ] {
+                assert_eq!(
+                    TradingPair::from_symbol(&symbol, &quotes),
+                    Some(pair.clone())
+                );
+            }
+        }
+        assert_eq!(TradingPair::from_symbol("USDT", &quotes), None);
+        assert_eq!(TradingPair::from_symbol("BTCEUR", &quotes), None);
+    }
+}
@@ -0,0 +1,310 @@
+#![cfg(feature = "binance")]
+
+use futures_util::{SinkExt, StreamExt};
+use rust_decimal::Decimal;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, watch};
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::Message;
+
+use price_publisher::exchanges::error::ExchangeError;
+use price_publisher::exchanges::remap::{SymbolRemap, SymbolRemaps};
+use price_publisher::exchanges::ws_stream::ConnState;
+use price_publisher::exchanges::{binance::BinanceExchange, Exchange};
+use price_publisher::types::{Exchange as Venue, PriceKind, PriceMethod, PriceUpdate, TradingPair};
+
+// Accepts one websocket client, waits for its subscription and replies with
+// the given messages, then holds the connection open until the client closes
+async fn serve_messages(listener: TcpListener, messages: Vec<String>) {
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+
+    let subscription = ws.next().await.unwrap().unwrap();
+    assert!(subscription.into_text().unwrap().contains("SUBSCRIBE"));
+
+    for message in messages {
+        ws.send(Message::Text(message)).await.unwrap();
+    }
+    while let Some(Ok(message)) = ws.next().await {
+        if message.is_close() {
+            break;
+        }
+    }
+}
+
+// Points `exchange` at a mock server sending `messages` after the
+// subscription ack, and returns the first price update it emits
+async fn first_update(exchange: BinanceExchange, messages: &[&str]) -> PriceUpdate {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let mut replies = vec![r#"{"result":null,"id":1}"#.to_string()];
+    replies.extend(messages.iter().map(|message| message.to_string()));
+    let server = tokio::spawn(serve_messages(listener, replies));
+
+    let exchange = exchange.with_websocket_url(url);
+    let (price_sender, mut price_receiver) = mpsc::channel(10);
+    let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+    let listener =
+        tokio::spawn(async move { exchange.listen(price_sender, shutdown_receiver).await });
+
+    let update = timeout(Duration::from_secs(5), price_receiver.recv())
+        .await
+        .expect("timed out waiting for a price update")
+        .expect("channel closed");
+
+    shutdown_sender.send(true).unwrap();
+    let result = timeout(Duration::from_secs(5), listener)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(result.is_ok());
+    timeout(Duration::from_secs(5), server)
+        .await
+        .unwrap()
+        .unwrap();
+    update
+}
+
+#[tokio::test]
+async fn listen_emits_mid_price_from_book_ticker() {
+    let update = first_update(
+        BinanceExchange::new(vec![TradingPair::new("BTC", "USDT")]),
+        &[
+            // Not a configured pair
+            r#"{"stream":"dogeusdt@bookTicker","data":{"u":1,"s":"DOGEUSDT","b":"0.1","B":"1","a":"0.2","A":"1"}}"#,
+            r#"{"stream":"btcusdt@bookTicker","data":{"u":2,"s":"BTCUSDT","b":"100.00","B":"1","a":"101.00","A":"1"}}"#,
+        ],
+    )
+    .await;
+
+    assert_eq!(update.symbol, "BTCUSDT");
+    assert_eq!(update.price, Decimal::from_str("100.5").unwrap());
+    assert_eq!(update.source, "binance");
+    assert_eq!(update.bid, Some(Decimal::from_str("100.00").unwrap()));
+    assert_eq!(update.ask, Some(Decimal::from_str("101.00").unwrap()));
+    assert_eq!(update.kind, PriceKind::TopOfBook);
+}
+
+#[tokio::test]
+async fn listen_applies_symbol_remaps() {
+    let remaps = SymbolRemaps::new(&[SymbolRemap {
+        exchange: Venue::Binance,
+        native: "1000SHIB".to_string(),
+        canonical: "SHIB".to_string(),
+        price_scale: Decimal::new(1, 3),
+    }]);
+    let update = first_update(
+        BinanceExchange::new(vec![TradingPair::new("SHIB", "USDT")])
+            .with_symbol_remaps(remaps.for_exchange(Venue::Binance)),
+        &[r#"{"stream":"1000shibusdt@bookTicker","data":{"u":1,"s":"1000SHIBUSDT","b":"0.020","B":"1","a":"0.022","A":"1"}}"#],
+    )
+    .await;
+
+    assert_eq!(update.symbol, "SHIBUSDT");
+    assert_eq!(update.price, Decimal::from_str("0.000021").unwrap());
+    assert_eq!(update.bid, Some(Decimal::from_str("0.00002").unwrap()));
+    assert_eq!(update.ask, Some(Decimal::from_str("0.000022").unwrap()));
+}
+
+#[tokio::test]
+async fn listen_weights_mid_by_book_ticker_sizes() {
+    let update = first_update(
+        BinanceExchange::new(vec![TradingPair::new("BTC", "USDT")])
+            .with_price_method(PriceMethod::WeightedMid),
+        &[r#"{"stream":"btcusdt@bookTicker","data":{"u":1,"s":"BTCUSDT","b":"100.00","B":"3","a":"101.00","A":"1"}}"#],
+    )
+    .await;
+
+    // Three times the size on the bid pulls the price towards the ask
+    assert_eq!(update.price, Decimal::from_str("100.75").unwrap());
+    assert_eq!(update.bid, Some(Decimal::from_str("100.00").unwrap()));
+}
+
+#[tokio::test]
+async fn listen_emits_last_trade_prices() {
+    let update = first_update(
+        BinanceExchange::new(vec![TradingPair::new("BTC", "USDT")]).with_trades(),
+        &[
+            r#"{"stream":"btcusdt@trade","data":{"e":"trade","E":1700000000001,"s":"BTCUSDT","t":7,"p":"100.25","q":"0.5","T":1700000000000,"m":true}}"#,
+        ],
+    )
+    .await;
+
+    assert_eq!(update.symbol, "BTCUSDT");
+    assert_eq!(update.price, Decimal::from_str("100.25").unwrap());
+    assert_eq!(update.kind, PriceKind::LastTrade);
+    assert_eq!(update.bid, None);
+}
+
+#[tokio::test]
+async fn is_unhealthy_until_prices_arrive() {
+    // The heartbeat starts fresh, but no tick has produced a price yet
+    let exchange = BinanceExchange::new(vec![TradingPair::new("BTC", "USDT")]);
+    assert!(!exchange.is_healthy().await);
+    assert_eq!(exchange.ticks_last_interval(), 0);
+}
+
+#[tokio::test]
+async fn resubscribe_changes_streams_on_open_connection() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        let subscription = ws.next().await.unwrap().unwrap().into_text().unwrap();
+        assert!(subscription.contains("btcusdt@bookTicker"));
+        ws.send(Message::Text(r#"{"result":null,"id":1}"#.to_string()))
+            .await
+            .unwrap();
+        ws.send(Message::Text(
+            r#"{"stream":"btcusdt@bookTicker","data":{"u":1,"s":"BTCUSDT","b":"100","B":"1","a":"101","A":"1"}}"#.to_string(),
+        ))
+        .await
+        .unwrap();
+
+        let unsubscribe = ws.next().await.unwrap().unwrap().into_text().unwrap();
+        assert!(unsubscribe.contains("UNSUBSCRIBE"));
+        assert!(unsubscribe.contains("btcusdt@bookTicker"));
+        let subscribe = ws.next().await.unwrap().unwrap().into_text().unwrap();
+        assert!(subscribe.contains(r#""SUBSCRIBE""#));
+        assert!(subscribe.contains("ethusdt@bookTicker"));
+        ws.send(Message::Text(
+            r#"{"stream":"ethusdt@bookTicker","data":{"u":2,"s":"ETHUSDT","b":"10","B":"1","a":"11","A":"1"}}"#.to_string(),
+        ))
+        .await
+        .unwrap();
+        while let Some(Ok(message)) = ws.next().await {
+            if message.is_close() {
+                break;
+            }
+        }
+    });
+
+    let exchange =
+        BinanceExchange::new(vec![TradingPair::new("BTC", "USDT")]).with_websocket_url(url);
+    let (price_sender, mut price_receiver) = mpsc::channel(10);
+    let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+    let listening = exchange.clone();
+    let listener =
+        tokio::spawn(async move { listening.listen(price_sender, shutdown_receiver).await });
+
+    let first = timeout(Duration::from_secs(5), price_receiver.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(first.symbol, "BTCUSDT");
+
+    exchange.resubscribe(vec![TradingPair::new("ETH", "USDT")]);
+    let second = timeout(Duration::from_secs(5), price_receiver.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(second.symbol, "ETHUSDT");
+
+    shutdown_sender.send(true).unwrap();
+    let result = timeout(Duration::from_secs(5), listener)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(result.is_ok());
+    timeout(Duration::from_secs(5), server)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn ws_state_tracks_the_connection_lifecycle() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let (subscribed_sender, subscribed) = tokio::sync::oneshot::channel();
+    let (ack_sender, ack) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        ws.next().await.unwrap().unwrap();
+        subscribed_sender.send(()).unwrap();
+        ack.await.unwrap();
+        ws.send(Message::Text(r#"{"result":null,"id":1}"#.to_string()))
+            .await
+            .unwrap();
+        ws.send(Message::Text(
+            r#"{"stream":"btcusdt@bookTicker","data":{"u":1,"s":"BTCUSDT","b":"100","B":"1","a":"101","A":"1"}}"#.to_string(),
+        ))
+        .await
+        .unwrap();
+        while let Some(Ok(message)) = ws.next().await {
+            if message.is_close() {
+                break;
+            }
+        }
+    });
+
+    let exchange =
+        BinanceExchange::new(vec![TradingPair::new("BTC", "USDT")]).with_websocket_url(url);
+    assert_eq!(exchange.ws_state(), ConnState::Closed);
+    let (price_sender, mut price_receiver) = mpsc::channel(10);
+    let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+    let listening = exchange.clone();
+    let listener =
+        tokio::spawn(async move { listening.listen(price_sender, shutdown_receiver).await });
+
+    // Connected and subscribed, but not yet acknowledged
+    timeout(Duration::from_secs(5), subscribed)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(exchange.ws_state(), ConnState::Subscribing);
+
+    ack_sender.send(()).unwrap();
+    timeout(Duration::from_secs(5), price_receiver.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(exchange.ws_state(), ConnState::Live);
+
+    shutdown_sender.send(true).unwrap();
+    timeout(Duration::from_secs(5), listener)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(exchange.ws_state(), ConnState::Closed);
+    timeout(Duration::from_secs(5), server)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn listen_fails_after_consecutive_unparseable_messages() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    // A renamed field, as after an unannounced schema change
+    let mut replies = vec![r#"{"result":null,"id":1}"#.to_string()];
+    replies.extend(std::iter::repeat_n(
+        r#"{"stream":"btcusdt@bookTicker","data":{"u":1,"symbol":"BTCUSDT","b":"100","B":"1","a":"101","A":"1"}}"#.to_string(),
+        100,
+    ));
+    let server = tokio::spawn(serve_messages(listener, replies));
+
+    let exchange =
+        BinanceExchange::new(vec![TradingPair::new("BTC", "USDT")]).with_websocket_url(url);
+    let (price_sender, mut price_receiver) = mpsc::channel(10);
+    let (_shutdown_sender, shutdown_receiver) = watch::channel(false);
+
+    let result = timeout(
+        Duration::from_secs(5),
+        exchange.listen(price_sender, shutdown_receiver),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(result, Err(ExchangeError::Undecodable(100))));
+    assert!(price_receiver.try_recv().is_err());
+    timeout(Duration::from_secs(5), server)
+        .await
+        .unwrap()
+        .unwrap();
+}
@@ -0,0 +1,100 @@
+use price_publisher::config::Config;
+use price_publisher::types::TradingPair;
+
+#[test]
+fn example_config_parses() {
+    let config = Config::load(concat!(env!("CARGO_MANIFEST_DIR"), "/config.example.toml")).unwrap();
+    assert_eq!(config.trading_pairs[0], TradingPair::new("BTC", "USDT"));
+    assert_eq!(config.trading_pairs.len(), 4);
+    assert_eq!(
+        config.coinbase_peg_fallbacks.get("USDCUSDT"),
+        Some(&rust_decimal::Decimal::ONE)
+    );
+}
+
+#[test]
+fn trading_pairs_accept_strings_and_tables() {
+    let config: Config = toml::from_str(
+        r#"
+        trading_pairs = ["btc/usdt", "ETH-USD", { base = "sol", quote = "usdt" }]
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        config.trading_pairs,
+        vec![
+            TradingPair::new("BTC", "USDT"),
+            TradingPair::new("ETH", "USD"),
+            TradingPair::new("SOL", "USDT"),
+        ]
+    );
+}
+
+#[test]
+fn redis_key_template_and_case_shape_price_keys() {
+    let config: Config = toml::from_str(
+        r#"
+        redis_key_template = "price:{base}-{quote}"
+        redis_key_case = "lower"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        config
+            .redis_key_format()
+            .price_key(&TradingPair::new("BTC", "USDT")),
+        "price:btc-usdt"
+    );
+}
+
+#[test]
+fn rejects_a_book_depth_bybit_does_not_publish() {
+    let path = std::env::temp_dir().join(format!("bybit-depth-{}.toml", std::process::id()));
+    std::fs::write(&path, "bybit_book_depth = 10\n").unwrap();
+    let result = Config::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert!(result.is_err());
+
+    std::fs::write(&path, "bybit_book_depth = 50\n").unwrap();
+    let config = Config::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(config.bybit_book_depth, 50);
+}
+
+#[test]
+fn symbol_price_bounds_need_min_below_max() {
+    let path = std::env::temp_dir().join(format!("price-bounds-{}.toml", std::process::id()));
+    std::fs::write(&path, "[symbol_price_bounds]\nbtcusdt = [1000, 1000000]\n").unwrap();
+    let config = Config::load(&path).unwrap();
+    assert_eq!(
+        config.symbol_price_bounds["BTCUSDT"],
+        (
+            rust_decimal::Decimal::new(1000, 0),
+            rust_decimal::Decimal::new(1000000, 0)
+        )
+    );
+
+    std::fs::write(&path, "[symbol_price_bounds]\nBTCUSDT = [100, 10]\n").unwrap();
+    let result = Config::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert!(result.is_err());
+}
+
+#[test]
+fn health_table_overrides_defaults_and_is_validated() {
+    let path = std::env::temp_dir().join(format!("health-{}.toml", std::process::id()));
+    std::fs::write(
+        &path,
+        "[health]\nheartbeat_timeout_secs = 20\nmax_error_count = 3\n",
+    )
+    .unwrap();
+    let config = Config::load(&path).unwrap();
+    assert_eq!(config.health.heartbeat_timeout_secs, 20);
+    assert_eq!(config.health.max_error_count, 3);
+    assert_eq!(config.health.check_interval_secs, 30);
+
+    std::fs::write(&path, "[health]\nreconnect_base_delay_secs = 300\n").unwrap();
+    let result = Config::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert!(result.is_err());
+}
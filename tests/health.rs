@@ -0,0 +1,82 @@
+#![cfg(feature = "binance")]
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use rust_decimal::Decimal;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use price_publisher::clock::{Clock, MockClock};
+use price_publisher::config::Config;
+use price_publisher::publisher::PricePublisher;
+use price_publisher::sink::MemorySink;
+use price_publisher::types::{Exchange, PriceKind, PriceUpdate};
+
+// Keeps every warning logged in this test binary
+struct CaptureLogger {
+    warnings: Mutex<Vec<String>>,
+}
+
+impl Log for CaptureLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Warn
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.warnings
+                .lock()
+                .unwrap()
+                .push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CaptureLogger = CaptureLogger {
+    warnings: Mutex::new(Vec::new()),
+};
+
+#[tokio::test]
+async fn check_health_warns_about_stale_prices() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(LevelFilter::Warn);
+
+    let config = Config {
+        exchanges: vec![Exchange::Binance],
+        ..Config::default()
+    };
+    let clock = MockClock::default();
+    let publisher = PricePublisher::with_sinks_and_clock(
+        &config,
+        vec![Arc::new(MemorySink::new())],
+        Arc::new(clock.clone()),
+    )
+    .await
+    .unwrap();
+
+    publisher
+        .publish_updates(vec![PriceUpdate {
+            symbol: "BTCUSDT".to_string(),
+            price: Decimal::new(100, 0),
+            timestamp: clock.now(),
+            source: "binance".to_string(),
+            kind: PriceKind::TopOfBook,
+            bid: None,
+            ask: None,
+            exchange_timestamp: None,
+        }])
+        .await;
+    // Twice the default 30s staleness window
+    clock.advance(Duration::from_secs(60));
+    publisher.check_health().await;
+
+    let warnings = LOGGER.warnings.lock().unwrap();
+    assert!(
+        warnings
+            .iter()
+            .any(|warning| warning.starts_with("Stale price for BTCUSDT/binance")),
+        "no stale price warning in {:?}",
+        warnings
+    );
+}
@@ -0,0 +1,86 @@
+#![cfg(feature = "htx")]
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures_util::{SinkExt, StreamExt};
+use rust_decimal::Decimal;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, watch};
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::Message;
+
+use price_publisher::exchanges::{htx::HtxExchange, Exchange};
+use price_publisher::types::{PriceKind, TradingPair};
+
+// HTX gzips every frame it sends
+fn gzipped(text: &str) -> Message {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(text.as_bytes()).unwrap();
+    Message::Binary(encoder.finish().unwrap())
+}
+
+#[tokio::test]
+async fn listen_answers_pings_and_emits_bbo_mids() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+
+        let subscription = ws.next().await.unwrap().unwrap().into_text().unwrap();
+        assert!(subscription.contains("market.btcusdt.bbo"));
+        ws.send(gzipped(
+            r#"{"id":"btcusdt","status":"ok","subbed":"market.btcusdt.bbo","ts":1700000000000}"#,
+        ))
+        .await
+        .unwrap();
+
+        // The feed must echo the server's ping before it gets any prices
+        ws.send(gzipped(r#"{"ping":1700000000001}"#)).await.unwrap();
+        let pong = ws.next().await.unwrap().unwrap().into_text().unwrap();
+        assert_eq!(pong, r#"{"pong":1700000000001}"#);
+
+        ws.send(gzipped(
+            r#"{"ch":"market.btcusdt.bbo","ts":1700000000002,"tick":{"symbol":"btcusdt","quoteTime":1700000000002,"bid":100.0,"bidSize":1.5,"ask":101.0,"askSize":2.0,"seqId":1}}"#,
+        ))
+        .await
+        .unwrap();
+        while let Some(Ok(message)) = ws.next().await {
+            if message.is_close() {
+                break;
+            }
+        }
+    });
+
+    let exchange = HtxExchange::new(vec![TradingPair::new("BTC", "USDT")]).with_websocket_url(url);
+    let (price_sender, mut price_receiver) = mpsc::channel(10);
+    let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+    let listener =
+        tokio::spawn(async move { exchange.listen(price_sender, shutdown_receiver).await });
+
+    let update = timeout(Duration::from_secs(5), price_receiver.recv())
+        .await
+        .expect("timed out waiting for a price update")
+        .expect("channel closed");
+    assert_eq!(update.symbol, "BTCUSDT");
+    assert_eq!(update.source, "htx");
+    assert_eq!(update.kind, PriceKind::TopOfBook);
+    assert_eq!(update.price, Decimal::from_str("100.5").unwrap());
+    assert_eq!(update.bid, Some(Decimal::from(100)));
+    assert_eq!(update.ask, Some(Decimal::from(101)));
+    assert!(update.exchange_timestamp.is_some());
+
+    shutdown_sender.send(true).unwrap();
+    let result = timeout(Duration::from_secs(5), listener)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(result.is_ok());
+    timeout(Duration::from_secs(5), server)
+        .await
+        .unwrap()
+        .unwrap();
+}
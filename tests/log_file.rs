@@ -0,0 +1,40 @@
+use std::fs;
+use std::io::Write;
+
+use price_publisher::log_file::RotatingFile;
+
+#[test]
+fn rolls_over_at_the_size_cap_and_keeps_max_files() {
+    let dir = std::env::temp_dir().join(format!("log-file-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("price_publisher.out");
+
+    let mut file = RotatingFile::open(&path, 10, 2).unwrap();
+    for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+        file.write_all(line.as_bytes()).unwrap();
+    }
+    file.flush().unwrap();
+
+    // Each line pushes the previous one past 10 bytes; "first" fell off
+    assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+    assert_eq!(
+        fs::read_to_string(dir.join("price_publisher.out.1")).unwrap(),
+        "third\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("price_publisher.out.2")).unwrap(),
+        "second\n"
+    );
+    assert!(!dir.join("price_publisher.out.3").exists());
+
+    // Reopening counts what's already in the file
+    let mut file = RotatingFile::open(&path, 10, 2).unwrap();
+    file.write_all(b"fifth\n").unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "fifth\n");
+    assert_eq!(
+        fs::read_to_string(dir.join("price_publisher.out.1")).unwrap(),
+        "fourth\n"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,722 @@
+#![cfg(feature = "binance")]
+
+use rust_decimal::Decimal;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use tokio_tungstenite::tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
+
+use price_publisher::clock::{Clock, MockClock};
+use price_publisher::config::Config;
+use price_publisher::exchanges::error::ExchangeError;
+use price_publisher::publisher::{FeedFailure, PricePublisher};
+use price_publisher::sink::MemorySink;
+use price_publisher::types::{Exchange, FundingUpdate, PriceKind, PriceUpdate, TradingPair};
+
+// Binance needs no initialization, so building the publisher stays offline
+async fn publisher_with(config: Config) -> (PricePublisher, Arc<MemorySink>) {
+    let config = Config {
+        exchanges: vec![Exchange::Binance],
+        ..config
+    };
+    let sink = Arc::new(MemorySink::new());
+    let publisher = PricePublisher::with_sink(&config, sink.clone())
+        .await
+        .unwrap();
+    (publisher, sink)
+}
+
+fn update(source: &str, price: i64) -> PriceUpdate {
+    PriceUpdate {
+        symbol: "BTCUSDT".to_string(),
+        price: Decimal::new(price, 0),
+        timestamp: SystemTime::now(),
+        source: source.to_string(),
+        kind: PriceKind::TopOfBook,
+        bid: None,
+        ask: None,
+        exchange_timestamp: None,
+    }
+}
+
+fn published(sink: &MemorySink) -> Vec<(String, Decimal)> {
+    sink.updates()
+        .into_iter()
+        .map(|update| (update.source, update.price))
+        .collect()
+}
+
+#[tokio::test]
+async fn publishes_valid_updates_and_drops_invalid_ones() {
+    let (publisher, sink) = publisher_with(Config::default()).await;
+
+    publisher
+        .publish_updates(vec![update("binance", 100), update("bybit", 0)])
+        .await;
+
+    assert_eq!(
+        published(&sink),
+        vec![("binance".to_string(), Decimal::new(100, 0))]
+    );
+    let snapshot = sink.latest_snapshot("BTCUSDT").unwrap();
+    // The invalid price doesn't count towards the consensus quorum
+    assert_eq!(snapshot.consensus, None);
+    assert!(snapshot.insufficient_sources);
+    assert_eq!(snapshot.ohlc.unwrap().close, Decimal::new(100, 0));
+}
+
+#[tokio::test]
+async fn holds_back_outliers() {
+    let (publisher, sink) = publisher_with(Config::default()).await;
+
+    publisher
+        .publish_updates(vec![
+            update("binance", 100),
+            update("bybit", 101),
+            update("okx", 102),
+        ])
+        .await;
+    publisher.publish_updates(vec![update("kraken", 150)]).await;
+
+    assert!(published(&sink)
+        .iter()
+        .all(|(source, _)| source != "kraken"));
+    // The outlier is still recorded, so it can recover once it converges
+    assert_eq!(
+        publisher.get_price("BTCUSDT", "kraken").await.unwrap().0,
+        Decimal::new(150, 0)
+    );
+}
+
+#[tokio::test]
+async fn skips_repeated_prices() {
+    let (publisher, sink) = publisher_with(Config::default()).await;
+
+    publisher
+        .publish_updates(vec![update("binance", 100)])
+        .await;
+    publisher
+        .publish_updates(vec![update("binance", 100)])
+        .await;
+    publisher
+        .publish_updates(vec![update("binance", 101)])
+        .await;
+
+    assert_eq!(
+        published(&sink),
+        vec![
+            ("binance".to_string(), Decimal::new(100, 0)),
+            ("binance".to_string(), Decimal::new(101, 0)),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn prefers_sources_by_priority() {
+    let (publisher, sink) = publisher_with(Config {
+        source_priority: vec![Exchange::Bybit],
+        ..Config::default()
+    })
+    .await;
+
+    publisher
+        .publish_updates(vec![update("bybit", 100), update("binance", 101)])
+        .await;
+
+    let snapshot = sink.latest_snapshot("BTCUSDT").unwrap();
+    assert_eq!(snapshot.preferred.unwrap().source, "bybit");
+    assert_eq!(snapshot.consensus, Some(Decimal::new(1005, 1)));
+}
+
+#[tokio::test]
+async fn applies_symbol_price_scale_and_decimals() {
+    let (publisher, sink) = publisher_with(Config {
+        symbol_price_scale: [("BTCUSDT".to_string(), Decimal::new(1, 3))].into(),
+        symbol_price_decimals: [("BTCUSDT".to_string(), 4)].into(),
+        ..Config::default()
+    })
+    .await;
+
+    publisher
+        .publish_updates(vec![update("binance", 100)])
+        .await;
+
+    assert_eq!(
+        published(&sink),
+        vec![("binance".to_string(), Decimal::new(1, 1))]
+    );
+    assert_eq!(sink.latest_snapshot("BTCUSDT").unwrap().decimals, Some(4));
+}
+
+#[tokio::test]
+async fn weights_consensus_by_source_trust() {
+    let (publisher, sink) = publisher_with(Config {
+        source_weights: [(Exchange::Binance, 0.75), (Exchange::Bybit, 0.25)].into(),
+        ..Config::default()
+    })
+    .await;
+
+    publisher
+        .publish_updates(vec![
+            update("binance", 100),
+            update("bybit", 104),
+            update("okx", 103),
+        ])
+        .await;
+
+    // OKX has no weight; the other two renormalize to 0.75 and 0.25
+    let snapshot = sink.latest_snapshot("BTCUSDT").unwrap();
+    assert_eq!(snapshot.consensus, Some(Decimal::new(101, 0)));
+}
+
+#[tokio::test]
+async fn keeps_last_trades_apart_from_book_prices() {
+    let (publisher, sink) = publisher_with(Config {
+        min_consensus_sources: 1,
+        ..Config::default()
+    })
+    .await;
+
+    let trade = PriceUpdate {
+        kind: PriceKind::LastTrade,
+        ..update("bybit", 150)
+    };
+    publisher
+        .publish_updates(vec![update("binance", 100), trade])
+        .await;
+
+    let snapshot = sink.latest_snapshot("BTCUSDT").unwrap();
+    assert_eq!(snapshot.last_trade, Some(Decimal::new(150, 0)));
+    // A trade 50% away would otherwise be an outlier and skew the consensus
+    assert_eq!(snapshot.consensus, Some(Decimal::new(100, 0)));
+    assert_eq!(snapshot.preferred.unwrap().source, "binance");
+}
+
+#[tokio::test]
+async fn drops_untracked_symbols() {
+    let (publisher, sink) = publisher_with(Config::default()).await;
+
+    let untracked = PriceUpdate {
+        symbol: "KPEPEUSDT".to_string(),
+        ..update("hyperliquid", 1)
+    };
+    publisher
+        .publish_updates(vec![untracked, update("hyperliquid", 100)])
+        .await;
+
+    assert_eq!(
+        published(&sink),
+        vec![("hyperliquid".to_string(), Decimal::new(100, 0))]
+    );
+    assert!(publisher
+        .get_price("KPEPEUSDT", "hyperliquid")
+        .await
+        .is_none());
+}
+
+#[tokio::test]
+async fn first_price_marks_connected_since() {
+    let (publisher, _sink) = publisher_with(Config::default()).await;
+    assert!(publisher.get_exchange_health().await["binance"]
+        .connected_since
+        .is_none());
+
+    let first = update("binance", 100);
+    let first_timestamp = first.timestamp;
+    publisher
+        .publish_updates(vec![first, update("binance", 101)])
+        .await;
+
+    let health = &publisher.get_exchange_health().await["binance"];
+    assert_eq!(health.connected_since, Some(first_timestamp));
+    assert_eq!(health.reconnect_count, 0);
+}
+
+#[tokio::test]
+async fn prices_from_a_connected_exchange_send_no_health_events() {
+    let (publisher, _sink) = publisher_with(Config::default()).await;
+    let mut events = publisher.health_events();
+
+    publisher
+        .publish_updates(vec![update("binance", 100), update("binance", 101)])
+        .await;
+
+    // Binance starts out connected, so its prices are not a transition
+    assert!(events.try_recv().is_err());
+    assert!(publisher.get_exchange_health().await["binance"].is_connected);
+}
+
+#[tokio::test]
+async fn prices_stamped_ahead_of_our_clock_count_as_fresh() {
+    let (publisher, _sink) = publisher_with(Config::default()).await;
+
+    // The exchange's clock runs a second fast
+    let mut ahead = update("binance", 100);
+    ahead.exchange_timestamp = Some(ahead.timestamp + Duration::from_secs(1));
+    publisher.publish_updates(vec![ahead]).await;
+
+    let (_, timestamp) = publisher.get_price("BTCUSDT", "binance").await.unwrap();
+    assert!(timestamp > SystemTime::now());
+    assert_eq!(publisher.age_of(timestamp), Duration::ZERO);
+    assert!(publisher.is_sending_prices(Duration::from_secs(30)).await);
+}
+
+#[tokio::test]
+async fn evicts_sources_past_max_age() {
+    let (publisher, _sink) = publisher_with(Config {
+        source_max_age_secs: 60,
+        ..Config::default()
+    })
+    .await;
+
+    let dead = PriceUpdate {
+        timestamp: SystemTime::now() - Duration::from_secs(120),
+        ..update("bybit", 100)
+    };
+    publisher
+        .publish_updates(vec![dead, update("binance", 100)])
+        .await;
+
+    assert_eq!(publisher.evict_stale_sources().await, 1);
+    assert!(publisher.get_price("BTCUSDT", "bybit").await.is_none());
+    assert!(publisher.get_price("BTCUSDT", "binance").await.is_some());
+}
+
+#[tokio::test]
+async fn halts_while_sources_disagree() {
+    let (publisher, sink) = publisher_with(Config::default()).await;
+
+    publisher
+        .publish_updates(vec![update("binance", 100), update("bybit", 103)])
+        .await;
+    let snapshot = sink.latest_snapshot("BTCUSDT").unwrap();
+    assert!(snapshot.halted);
+    assert!(publisher.is_halted("BTCUSDT").await);
+
+    publisher.publish_updates(vec![update("bybit", 101)]).await;
+    assert!(!sink.latest_snapshot("BTCUSDT").unwrap().halted);
+    assert!(!publisher.is_halted("BTCUSDT").await);
+}
+
+#[tokio::test]
+async fn halts_when_the_breaking_update_is_an_outlier() {
+    let (publisher, sink) = publisher_with(Config::default()).await;
+
+    publisher
+        .publish_updates(vec![
+            update("binance", 100),
+            update("bybit", 100),
+            update("okx", 101),
+        ])
+        .await;
+    publisher.publish_updates(vec![update("kraken", 110)]).await;
+
+    assert!(published(&sink)
+        .iter()
+        .all(|(source, _)| source != "kraken"));
+    assert!(sink.latest_snapshot("BTCUSDT").unwrap().halted);
+}
+
+#[tokio::test]
+async fn writes_funding_for_tracked_symbols_only() {
+    let (publisher, sink) = publisher_with(Config::default()).await;
+    let funding = |symbol: &str| FundingUpdate {
+        symbol: symbol.to_string(),
+        funding_rate: Decimal::new(125, 7),
+        next_funding_ts: SystemTime::now(),
+        source: "hyperliquid".to_string(),
+    };
+
+    publisher.publish_funding(&funding("BTCUSDT")).await;
+    publisher.publish_funding(&funding("DOGEUSDT")).await;
+
+    let written = sink.funding();
+    assert_eq!(written.len(), 1);
+    assert_eq!(written[0].symbol, "BTCUSDT");
+    assert_eq!(written[0].funding_rate, Decimal::new(125, 7));
+    // Funding never reaches the price path
+    assert!(sink.updates().is_empty());
+}
+
+#[tokio::test]
+async fn broadcasts_published_updates_to_subscribers() {
+    let (publisher, _sink) = publisher_with(Config::default()).await;
+    let mut receiver = publisher.subscribe();
+
+    publisher
+        .publish_updates(vec![update("binance", 100), update("bybit", 0)])
+        .await;
+
+    let received = receiver.try_recv().unwrap();
+    assert_eq!(received.source, "binance");
+    assert_eq!(received.price, Decimal::new(100, 0));
+    // The invalid update was never published, so it isn't broadcast either
+    assert!(receiver.try_recv().is_err());
+}
+
+#[test]
+fn classifies_server_close_codes() {
+    let closed_with = |code: u16| {
+        FeedFailure::classify(&ExchangeError::ServerClose(Some(CloseFrame {
+            code: CloseCode::from(code),
+            reason: "".into(),
+        })))
+    };
+
+    assert_eq!(closed_with(1000), FeedFailure::ClosedNormally);
+    assert_eq!(closed_with(1001), FeedFailure::ClosedNormally);
+    assert_eq!(closed_with(1008), FeedFailure::ClosedByPolicy);
+    assert_eq!(closed_with(4004), FeedFailure::ClosedByPolicy);
+    assert_eq!(closed_with(1011), FeedFailure::Disconnected);
+    assert_eq!(
+        FeedFailure::classify(&ExchangeError::ServerClose(None)),
+        FeedFailure::Disconnected
+    );
+}
+
+#[tokio::test]
+async fn adds_and_removes_pairs_at_runtime() {
+    let (publisher, _sink) = publisher_with(Config {
+        trading_pairs: vec![TradingPair::new("BTC", "USDT")],
+        ..Config::default()
+    })
+    .await;
+
+    assert!(!publisher.is_tracked("SOLUSDT"));
+    assert!(publisher.add_pair(TradingPair::new("SOL", "USDT")));
+    assert!(!publisher.add_pair(TradingPair::new("SOL", "USDT")));
+    assert!(publisher.is_tracked("SOLUSDT"));
+
+    publisher
+        .publish_updates(vec![update("binance", 100)])
+        .await;
+    assert!(publisher.get_price("BTCUSDT", "binance").await.is_some());
+
+    assert!(
+        publisher
+            .remove_pair(&TradingPair::new("BTC", "USDT"))
+            .await
+    );
+    assert!(
+        !publisher
+            .remove_pair(&TradingPair::new("BTC", "USDT"))
+            .await
+    );
+    assert!(!publisher.is_tracked("BTCUSDT"));
+    assert!(publisher.get_price("BTCUSDT", "binance").await.is_none());
+    assert_eq!(
+        publisher.trading_pairs(),
+        vec![TradingPair::new("SOL", "USDT")]
+    );
+}
+
+#[tokio::test]
+async fn fans_batches_out_to_every_sink() {
+    let config = Config {
+        exchanges: vec![Exchange::Binance],
+        ..Config::default()
+    };
+    let (first, second) = (Arc::new(MemorySink::new()), Arc::new(MemorySink::new()));
+    let publisher = PricePublisher::with_sinks(&config, vec![first.clone(), second.clone()])
+        .await
+        .unwrap();
+
+    publisher
+        .publish_updates(vec![update("binance", 100)])
+        .await;
+
+    let expected = vec![("binance".to_string(), Decimal::new(100, 0))];
+    assert_eq!(published(&first), expected);
+    assert_eq!(published(&second), expected);
+    assert!(second.latest_snapshot("BTCUSDT").is_some());
+}
+
+#[tokio::test]
+async fn throttles_sink_writes_per_symbol() {
+    let (publisher, sink) = publisher_with(Config {
+        min_write_interval_ms: 100,
+        min_consensus_sources: 1,
+        ..Config::default()
+    })
+    .await;
+
+    publisher
+        .publish_updates(vec![update("binance", 100)])
+        .await;
+    publisher
+        .publish_updates(vec![update("binance", 101)])
+        .await;
+    publisher
+        .publish_updates(vec![update("binance", 102)])
+        .await;
+
+    // In-memory prices keep up; the sink only saw the first write
+    let (price, _) = publisher.get_price("BTCUSDT", "binance").await.unwrap();
+    assert_eq!(price, Decimal::new(102, 0));
+    assert_eq!(
+        published(&sink),
+        vec![("binance".to_string(), Decimal::new(100, 0))]
+    );
+
+    // Nothing is due before the interval passes
+    publisher.flush_held_writes().await;
+    assert_eq!(published(&sink).len(), 1);
+
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    publisher.flush_held_writes().await;
+    assert_eq!(
+        published(&sink),
+        vec![
+            ("binance".to_string(), Decimal::new(100, 0)),
+            ("binance".to_string(), Decimal::new(102, 0)),
+        ]
+    );
+    assert_eq!(
+        sink.latest_snapshot("BTCUSDT").unwrap().consensus,
+        Some(Decimal::new(102, 0))
+    );
+}
+
+#[tokio::test]
+async fn stamps_updates_with_exchange_time() {
+    let (publisher, sink) = publisher_with(Config::default()).await;
+    let received = SystemTime::now();
+    let exchange_time = received - Duration::from_secs(2);
+
+    publisher
+        .publish_updates(vec![
+            PriceUpdate {
+                exchange_timestamp: Some(exchange_time),
+                timestamp: received,
+                ..update("binance", 100)
+            },
+            PriceUpdate {
+                timestamp: received,
+                ..update("okx", 100)
+            },
+        ])
+        .await;
+
+    let timestamps: Vec<SystemTime> = sink
+        .updates()
+        .into_iter()
+        .map(|update| update.timestamp)
+        .collect();
+    // Beyond max_clock_skew_ms the time is still taken from the exchange
+    assert_eq!(timestamps, vec![exchange_time, received]);
+    let health = publisher.get_exchange_health().await;
+    assert_eq!(
+        health["binance"].last_message_latency,
+        Duration::from_secs(2)
+    );
+}
+
+#[tokio::test]
+async fn is_ready_once_a_connected_feed_has_a_fresh_price() {
+    let (publisher, _sink) = publisher_with(Config::default()).await;
+    assert!(!publisher.is_ready().await);
+
+    // Older than half the default 60s expiry
+    publisher
+        .publish_updates(vec![PriceUpdate {
+            timestamp: SystemTime::now() - Duration::from_secs(45),
+            ..update("binance", 100)
+        }])
+        .await;
+    assert!(!publisher.is_ready().await);
+
+    publisher
+        .publish_updates(vec![update("binance", 101)])
+        .await;
+    assert!(publisher.is_ready().await);
+}
+
+#[tokio::test]
+async fn measures_deviation_from_the_reference_exchange() {
+    let (publisher, sink) = publisher_with(Config {
+        reference_exchange: Some(Exchange::Coinbase),
+        ..Config::default()
+    })
+    .await;
+
+    publisher
+        .publish_updates(vec![
+            update("coinbase", 100),
+            update("binance", 101),
+            update("bybit", 99),
+        ])
+        .await;
+
+    let deviations = publisher.compute_deviations("BTCUSDT").await;
+    assert_eq!(deviations.len(), 2);
+    assert_eq!(deviations["binance"], Decimal::new(100, 0));
+    assert_eq!(deviations["bybit"], Decimal::new(-100, 0));
+    assert_eq!(
+        sink.latest_snapshot("BTCUSDT").unwrap().deviations,
+        deviations
+    );
+
+    let health = publisher.get_exchange_health().await;
+    assert_eq!(
+        health["binance"].reference_deviation_bps["BTCUSDT"],
+        Decimal::new(100, 0)
+    );
+}
+
+#[tokio::test]
+async fn robust_consensus_drops_the_most_deviant_source() {
+    let (publisher, sink) = publisher_with(Config::default()).await;
+
+    publisher
+        .publish_updates(vec![update("binance", 1000), update("bybit", 1001)])
+        .await;
+    // Too few sources to trim; the median of two is their mean
+    assert_eq!(
+        publisher.compute_robust_consensus("BTCUSDT").await,
+        Some(Decimal::new(10005, 1))
+    );
+
+    publisher.publish_updates(vec![update("okx", 1010)]).await;
+    assert_eq!(
+        publisher.compute_consensus("BTCUSDT").await,
+        Some(Decimal::new(1001, 0))
+    );
+    assert_eq!(
+        publisher.compute_robust_consensus("BTCUSDT").await,
+        Some(Decimal::new(10005, 1))
+    );
+    assert_eq!(
+        sink.latest_snapshot("BTCUSDT").unwrap().robust_consensus,
+        Some(Decimal::new(10005, 1))
+    );
+}
+
+#[tokio::test]
+async fn ema_smooths_published_ticks() {
+    let (publisher, sink) = publisher_with(Config {
+        ema_alpha: Some(Decimal::new(5, 1)),
+        ..Config::default()
+    })
+    .await;
+
+    publisher
+        .publish_updates(vec![update("binance", 100)])
+        .await;
+    assert_eq!(
+        sink.latest_snapshot("BTCUSDT").unwrap().ema,
+        Some(Decimal::new(100, 0))
+    );
+
+    publisher
+        .publish_updates(vec![update("binance", 110)])
+        .await;
+    publisher
+        .publish_updates(vec![update("binance", 120)])
+        .await;
+    // 100 -> 105 -> 112.5
+    assert_eq!(
+        sink.latest_snapshot("BTCUSDT").unwrap().ema,
+        Some(Decimal::new(1125, 1))
+    );
+}
+
+#[tokio::test]
+async fn withholds_consensus_without_a_quorum_of_sources() {
+    let (publisher, sink) = publisher_with(Config::default()).await;
+
+    publisher
+        .publish_updates(vec![update("binance", 100)])
+        .await;
+    assert!(!publisher.has_consensus_quorum("BTCUSDT").await);
+    assert_eq!(publisher.get_consensus_price("BTCUSDT").await, None);
+    let snapshot = sink.latest_snapshot("BTCUSDT").unwrap();
+    assert_eq!(snapshot.consensus, None);
+    assert!(snapshot.insufficient_sources);
+
+    publisher.publish_updates(vec![update("bybit", 102)]).await;
+    let snapshot = sink.latest_snapshot("BTCUSDT").unwrap();
+    assert_eq!(snapshot.consensus, Some(Decimal::new(101, 0)));
+    assert!(!snapshot.insufficient_sources);
+}
+
+#[tokio::test]
+async fn drops_crossed_and_wide_books() {
+    let (publisher, sink) = publisher_with(Config::default()).await;
+    let quote = |source: &str, bid: i64, ask: i64| PriceUpdate {
+        bid: Some(Decimal::new(bid, 0)),
+        ask: Some(Decimal::new(ask, 0)),
+        ..update(source, (bid + ask) / 2)
+    };
+
+    publisher
+        .publish_updates(vec![
+            quote("binance", 101, 99),
+            quote("bybit", 100, 100),
+            quote("okx", 90, 110),
+            quote("coinbase", 99, 101),
+        ])
+        .await;
+
+    assert_eq!(
+        published(&sink),
+        vec![("coinbase".to_string(), Decimal::new(100, 0))]
+    );
+}
+
+#[tokio::test]
+async fn drops_prices_outside_symbol_bounds() {
+    let (publisher, sink) = publisher_with(Config {
+        symbol_price_bounds: [(
+            "BTCUSDT".to_string(),
+            (Decimal::new(50, 0), Decimal::new(200, 0)),
+        )]
+        .into(),
+        ..Config::default()
+    })
+    .await;
+
+    publisher
+        .publish_updates(vec![
+            update("binance", 10),
+            update("bybit", 1000),
+            update("okx", 200),
+        ])
+        .await;
+
+    assert_eq!(
+        published(&sink),
+        vec![("okx".to_string(), Decimal::new(200, 0))]
+    );
+}
+
+#[tokio::test]
+async fn consensus_goes_stale_as_the_clock_advances() {
+    let clock = MockClock::default();
+    let config = Config {
+        exchanges: vec![Exchange::Binance],
+        ..Config::default()
+    };
+    let publisher = PricePublisher::with_sinks_and_clock(
+        &config,
+        vec![Arc::new(MemorySink::new())],
+        Arc::new(clock.clone()),
+    )
+    .await
+    .unwrap();
+    let at_clock = |source: &str, price: i64| PriceUpdate {
+        timestamp: clock.now(),
+        ..update(source, price)
+    };
+
+    publisher
+        .publish_updates(vec![at_clock("binance", 100), at_clock("bybit", 102)])
+        .await;
+    assert_eq!(
+        publisher.compute_consensus("BTCUSDT").await,
+        Some(Decimal::new(101, 0))
+    );
+
+    // Past half the default 60s expiry, both sources are stale
+    clock.advance(Duration::from_secs(31));
+    assert_eq!(publisher.compute_consensus("BTCUSDT").await, None);
+}
@@ -0,0 +1,57 @@
+use rust_decimal::Decimal;
+use std::time::{Duration, SystemTime};
+
+use price_publisher::queue::UpdateQueue;
+use price_publisher::types::{PriceKind, PriceUpdate};
+
+fn update(symbol: &str, source: &str, price: i64) -> PriceUpdate {
+    PriceUpdate {
+        symbol: symbol.to_string(),
+        price: Decimal::new(price, 0),
+        timestamp: SystemTime::now(),
+        source: source.to_string(),
+        kind: PriceKind::TopOfBook,
+        bid: None,
+        ask: None,
+        exchange_timestamp: None,
+    }
+}
+
+#[tokio::test]
+async fn full_queue_drops_the_oldest_update_for_the_same_symbol() {
+    let queue = UpdateQueue::new(3);
+    queue.push(update("BTCUSDT", "binance", 100));
+    queue.push(update("ETHUSDT", "binance", 10));
+    queue.push(update("BTCUSDT", "okx", 101));
+    assert_eq!(queue.utilization(), 1.0);
+
+    // Supersedes binance's BTC price, not the oldest update overall
+    queue.push(update("BTCUSDT", "binance", 102));
+    assert_eq!(queue.len(), 3);
+    assert_eq!(queue.len_for_source("binance"), 2);
+
+    let batch = queue.next_batch(Duration::ZERO).await.unwrap();
+    let prices: Vec<_> = batch
+        .iter()
+        .map(|update| (update.symbol.as_str(), update.source.as_str(), update.price))
+        .collect();
+    assert_eq!(
+        prices,
+        vec![
+            ("ETHUSDT", "binance", Decimal::new(10, 0)),
+            ("BTCUSDT", "okx", Decimal::new(101, 0)),
+            ("BTCUSDT", "binance", Decimal::new(102, 0)),
+        ]
+    );
+    assert!(queue.is_empty());
+}
+
+#[tokio::test]
+async fn next_batch_ends_once_closed_and_drained() {
+    let queue = UpdateQueue::new(10);
+    queue.push(update("BTCUSDT", "binance", 100));
+    queue.close();
+
+    assert_eq!(queue.next_batch(Duration::ZERO).await.unwrap().len(), 1);
+    assert!(queue.next_batch(Duration::ZERO).await.is_none());
+}
@@ -0,0 +1,131 @@
+#![cfg(feature = "binance")]
+
+use rust_decimal::Decimal;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use price_publisher::config::Config;
+use price_publisher::exchanges::replay::{FileReplayExchange, ReplayPacing};
+use price_publisher::publisher::PricePublisher;
+use price_publisher::sink::MemorySink;
+use price_publisher::types::{Exchange, PriceKind, PriceUpdate, TradingPair};
+
+fn update(symbol: &str, source: &str, price: i64, timestamp: SystemTime) -> PriceUpdate {
+    PriceUpdate {
+        symbol: symbol.to_string(),
+        price: Decimal::new(price, 0),
+        timestamp,
+        source: source.to_string(),
+        kind: PriceKind::TopOfBook,
+        bid: None,
+        ask: None,
+        exchange_timestamp: None,
+    }
+}
+
+// Writes `updates` as newline-delimited JSON to a file unique to `name`
+fn replay_file(name: &str, updates: &[PriceUpdate]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("replay-{}-{}.jsonl", name, std::process::id()));
+    let lines: Vec<String> = updates
+        .iter()
+        .map(|update| serde_json::to_string(update).unwrap())
+        .collect();
+    std::fs::write(&path, lines.join("\n")).unwrap();
+    path
+}
+
+#[tokio::test]
+async fn replays_recorded_updates_through_the_publisher() {
+    // Recorded an hour ago; replayed as if live
+    let recorded = SystemTime::now() - Duration::from_secs(3600);
+    let path = replay_file(
+        "publisher",
+        &[
+            update("BTCUSDT", "binance", 100, recorded),
+            update("BTCUSDT", "okx", 101, recorded + Duration::from_millis(10)),
+            // Way off the others, dropped as an outlier
+            update(
+                "BTCUSDT",
+                "bybit",
+                150,
+                recorded + Duration::from_millis(20),
+            ),
+            update(
+                "DOGEUSDT",
+                "binance",
+                1,
+                recorded + Duration::from_millis(30),
+            ),
+        ],
+    );
+
+    let config = Config {
+        exchanges: vec![Exchange::Binance],
+        ..Config::default()
+    };
+    let sink = Arc::new(MemorySink::new());
+    let publisher = PricePublisher::with_sink(&config, sink.clone())
+        .await
+        .unwrap();
+    let exchange =
+        FileReplayExchange::new(&path).with_trading_pairs(vec![TradingPair::new("BTC", "USDT")]);
+    publisher.replay(&exchange).await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let published: Vec<(String, Decimal)> = sink
+        .updates()
+        .into_iter()
+        .map(|update| (update.source, update.price))
+        .collect();
+    assert_eq!(
+        published,
+        vec![
+            ("binance".to_string(), Decimal::new(100, 0)),
+            ("okx".to_string(), Decimal::new(101, 0)),
+        ]
+    );
+    // The outlier is still recorded, so the median moves to okx's price
+    assert_eq!(
+        publisher.get_consensus_price("BTCUSDT").await,
+        Some(Decimal::new(101, 0))
+    );
+    assert!(publisher.get_price("DOGEUSDT", "binance").await.is_none());
+}
+
+#[tokio::test]
+async fn real_time_pacing_keeps_recorded_gaps() {
+    let recorded = SystemTime::now() - Duration::from_secs(3600);
+    let path = replay_file(
+        "pacing",
+        &[
+            update("BTCUSDT", "binance", 100, recorded),
+            update(
+                "BTCUSDT",
+                "binance",
+                101,
+                recorded + Duration::from_millis(200),
+            ),
+        ],
+    );
+
+    let config = Config {
+        exchanges: vec![Exchange::Binance],
+        ..Config::default()
+    };
+    let publisher = PricePublisher::with_sink(&config, Arc::new(MemorySink::new()))
+        .await
+        .unwrap();
+    let started = std::time::Instant::now();
+    publisher
+        .replay(&FileReplayExchange::new(&path).with_pacing(ReplayPacing::RealTime))
+        .await
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(started.elapsed() >= Duration::from_millis(200));
+    let (price, timestamp) = publisher.get_price("BTCUSDT", "binance").await.unwrap();
+    assert_eq!(price, Decimal::new(101, 0));
+    // Rebased onto the replay, not an hour old
+    assert!(SystemTime::now().duration_since(timestamp).unwrap() < Duration::from_secs(5));
+}
@@ -0,0 +1,42 @@
+use price_publisher::exchanges::remap::{SymbolRemap, SymbolRemaps};
+use price_publisher::exchanges::subscription::SubscriptionBuilder;
+use price_publisher::types::{Exchange, TradingPair};
+use rust_decimal::Decimal;
+
+#[test]
+fn builds_topics_from_remapped_deduplicated_symbols() {
+    let pairs = vec![
+        TradingPair::new("BTC", "USDT"),
+        TradingPair::new("SHIB", "USDT"),
+        TradingPair::new("BTC", "USDT"),
+    ];
+    let remaps = SymbolRemaps::new(&[SymbolRemap {
+        exchange: Exchange::Bybit,
+        native: "SHIB1000".to_string(),
+        canonical: "SHIB".to_string(),
+        price_scale: Decimal::new(1, 3),
+    }])
+    .for_exchange(Exchange::Bybit);
+
+    let builder = SubscriptionBuilder::new(&pairs, &remaps, |pair| pair.to_bybit_symbol());
+    assert_eq!(builder.symbols(), ["BTCUSDT", "SHIB1000USDT"]);
+    assert_eq!(
+        builder.topics(&["orderbook.1", "publicTrade"], |channel, symbol| {
+            format!("{}.{}", channel, symbol)
+        }),
+        [
+            "orderbook.1.BTCUSDT",
+            "publicTrade.BTCUSDT",
+            "orderbook.1.SHIB1000USDT",
+            "publicTrade.SHIB1000USDT",
+        ]
+    );
+    assert_eq!(
+        builder.batched(|symbols| serde_json::json!({ "op": "subscribe", "args": symbols })),
+        r#"{"args":["BTCUSDT","SHIB1000USDT"],"op":"subscribe"}"#
+    );
+    assert_eq!(
+        builder.per_symbol(|symbol| serde_json::json!({ "sub": symbol })),
+        [r#"{"sub":"BTCUSDT"}"#, r#"{"sub":"SHIB1000USDT"}"#]
+    );
+}
@@ -0,0 +1,88 @@
+use rust_decimal::Decimal;
+use std::fs;
+use std::io::Write;
+use std::time::{Duration, SystemTime};
+
+use price_publisher::sink::{read_wal_tail, PriceBatch, PriceSink, WalSink};
+use price_publisher::types::{PriceKind, PriceUpdate};
+
+fn update(source: &str, price: i64) -> PriceUpdate {
+    PriceUpdate {
+        symbol: "BTCUSDT".to_string(),
+        price: Decimal::new(price, 0),
+        timestamp: SystemTime::now(),
+        source: source.to_string(),
+        kind: PriceKind::TopOfBook,
+        bid: None,
+        ask: None,
+        exchange_timestamp: None,
+    }
+}
+
+#[tokio::test]
+async fn reads_back_appended_updates_skipping_torn_lines() {
+    let path = std::env::temp_dir().join(format!("wal-{}.jsonl", std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    let sink = WalSink::open(&path, Duration::ZERO).unwrap();
+    sink.write_batch(&PriceBatch {
+        updates: vec![update("binance", 100), update("bybit", 101)],
+        symbols: Vec::new(),
+    })
+    .await
+    .unwrap();
+    sink.write(&update("okx", 102)).await.unwrap();
+
+    // A crash mid-write leaves half a line at the end
+    fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(b"{\"symbol\":\"BTC")
+        .unwrap();
+
+    let prices = |updates: Vec<PriceUpdate>| {
+        updates
+            .into_iter()
+            .map(|update| (update.source, update.price))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        prices(read_wal_tail(&path, u64::MAX).unwrap()),
+        vec![
+            ("binance".to_string(), Decimal::new(100, 0)),
+            ("bybit".to_string(), Decimal::new(101, 0)),
+            ("okx".to_string(), Decimal::new(102, 0)),
+        ]
+    );
+
+    // A tail starting mid-line drops that line rather than misparse it
+    let last_line = fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .nth(2)
+        .unwrap()
+        .len() as u64;
+    assert_eq!(
+        prices(read_wal_tail(&path, last_line + 20).unwrap()),
+        vec![("okx".to_string(), Decimal::new(102, 0))]
+    );
+
+    fs::remove_file(&path).unwrap();
+    assert!(read_wal_tail(&path, u64::MAX).unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn syncs_on_demand_with_a_long_fsync_interval() {
+    let path = std::env::temp_dir().join(format!("wal-sync-{}.jsonl", std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    let sink = WalSink::open(&path, Duration::from_secs(3600)).unwrap();
+    sink.write(&update("binance", 100)).await.unwrap();
+    sink.sync().await.unwrap();
+    drop(sink);
+
+    let updates = read_wal_tail(&path, u64::MAX).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(updates.len(), 1);
+}